@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS album_image (
+    id INTEGER PRIMARY KEY,
+    album_id INTEGER NOT NULL,
+    usage TEXT NOT NULL,
+    mime TEXT NOT NULL,
+    hash BLOB NOT NULL,
+    image BLOB NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (album_id) REFERENCES album (id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS album_image_album_id_hash_idx ON album_image (album_id, hash);
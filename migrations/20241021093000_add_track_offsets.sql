@@ -0,0 +1,4 @@
+-- start and end offsets (in milliseconds) of virtual tracks that occupy part of a file, such as
+-- tracks created from a CUE sheet
+ALTER TABLE track ADD start_offset INTEGER;
+ALTER TABLE track ADD end_offset INTEGER;
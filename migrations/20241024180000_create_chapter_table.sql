@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS chapter (
+    id INTEGER PRIMARY KEY,
+    track_id INTEGER NOT NULL,
+    chapter_index INTEGER NOT NULL,
+    title TEXT,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER,
+    FOREIGN KEY (track_id) REFERENCES track (id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS chapter_track_id_chapter_index_idx ON chapter (track_id, chapter_index);
+
+-- audiobooks remember their playback position between sessions
+ALTER TABLE track ADD audiobook BOOLEAN NOT NULL DEFAULT FALSE;
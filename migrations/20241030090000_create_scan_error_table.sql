@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS scan_error (
+    id INTEGER PRIMARY KEY,
+    location TEXT NOT NULL UNIQUE,
+    -- the step that failed (open, start, metadata, duration, close)
+    stage TEXT NOT NULL,
+    message TEXT NOT NULL,
+    first_failed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    failed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
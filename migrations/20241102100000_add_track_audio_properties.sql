@@ -0,0 +1,12 @@
+-- technical properties of the file a track is stored in, read from its headers when it's scanned
+-- codec is the decoder's short name (e.g. flac, mp3, pcm_s16le), container is the file extension
+ALTER TABLE track ADD codec TEXT;
+ALTER TABLE track ADD container TEXT;
+ALTER TABLE track ADD sample_rate INTEGER;
+ALTER TABLE track ADD bit_depth INTEGER;
+ALTER TABLE track ADD channels INTEGER;
+-- average bitrate of the whole file, in bits per second
+ALTER TABLE track ADD bitrate INTEGER;
+-- size of the file in bytes (the uncompressed size for tracks inside of archives)
+ALTER TABLE track ADD file_size INTEGER;
+ALTER TABLE track ADD lossless BOOLEAN;
@@ -0,0 +1,14 @@
+-- tracks in a library folder that isn't currently available, such as an unmounted drive or network
+-- share; they're kept (and shown as unavailable) until the folder comes back
+ALTER TABLE track ADD offline BOOLEAN NOT NULL DEFAULT 0;
+
+-- library folders that are currently unavailable, and when they were first found to be
+CREATE TABLE IF NOT EXISTS offline_root (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    offline_since DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- when a track outside of every library folder was first found to be; unlike the tracks in an
+-- offline folder, these are kept for the grace period one by one
+ALTER TABLE track ADD offline_since DATETIME;
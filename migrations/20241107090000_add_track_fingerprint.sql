@@ -0,0 +1,4 @@
+-- identifies a track by its contents rather than its location, so that moved or renamed files keep
+-- their track ID; this is a hash of the file size, duration and tags rather than of the audio
+ALTER TABLE track ADD fingerprint BLOB;
+CREATE INDEX IF NOT EXISTS track_fingerprint ON track (fingerprint);
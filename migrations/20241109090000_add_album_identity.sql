@@ -0,0 +1,31 @@
+-- albums used to be looked up by title alone, which merged albums with the same title by
+-- different artists; they're now told apart by their album artist, and by their MusicBrainz
+-- release ID or release year when either is known
+ALTER TABLE album ADD mbid TEXT;
+
+-- NULLs are distinct in unique indexes, so missing artists, release IDs and release years are
+-- keyed by placeholders instead. The year is part of the key so that releases of the same album
+-- in different years can be told apart, like they are by get_album_id.sql
+DROP INDEX IF EXISTS album_title_artist_id_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS album_identity_idx
+    ON album (
+        title,
+        coalesce(artist_id, -1),
+        coalesce(mbid, ''),
+        coalesce(substr(release_date, 1, 4), '')
+    );
+
+-- the track table doesn't record artists, so the artist each track of a merged album belongs to
+-- is only known from its tags. Albums that look merged, with tracks in more than one folder or
+-- with repeated track numbers, have their tracks read again during the next scan (see
+-- find_tracks_without_properties.sql), which moves each of them to the right album.
+ALTER TABLE track ADD needs_rescan BOOLEAN NOT NULL DEFAULT 0;
+
+UPDATE track SET needs_rescan = 1
+WHERE album_id IN (
+    SELECT album_id FROM track
+    WHERE album_id IS NOT NULL
+    GROUP BY album_id
+    HAVING count(DISTINCT rtrim(location, replace(replace(location, '/', ''), '\', ''))) > 1
+        OR count(DISTINCT coalesce(disc_number, 1) || '.' || track_number) < count(track_number)
+);
@@ -0,0 +1,17 @@
+-- the state of every file the scanner has seen (including art files and CUE sheets), used to tell
+-- whether a file has changed since it was last scanned; this replaces scan_record.json
+CREATE TABLE IF NOT EXISTS file_state (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    -- 'audio' for files that are read for tracks, 'support' for art files and CUE sheets, which
+    -- only affect the tracks next to them
+    kind TEXT NOT NULL DEFAULT 'audio',
+    -- modification time, in seconds since the Unix epoch
+    modified INTEGER NOT NULL,
+    -- size in bytes, missing for files imported from scan_record.json
+    size INTEGER,
+    -- SHA-256 of the start and end of the file, compared when the modification time changed but
+    -- the size didn't
+    hash BLOB,
+    scanned_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS playlist (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- a playlist may list the same track more than once, so items are identified by their own id and
+-- ordered by position (0-based, without gaps)
+CREATE TABLE IF NOT EXISTS playlist_item (
+    id INTEGER PRIMARY KEY,
+    playlist_id INTEGER NOT NULL,
+    -- the library track, missing for streams and files outside of the library
+    track_id INTEGER,
+    -- where the item was when it was added; the track's current location is preferred when the
+    -- track is still in the library
+    location TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (playlist_id) REFERENCES playlist (id) ON DELETE CASCADE,
+    FOREIGN KEY (track_id) REFERENCES track (id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS playlist_item_playlist_id_position_idx
+    ON playlist_item (playlist_id, position);
+CREATE INDEX IF NOT EXISTS playlist_item_track_id_idx ON playlist_item (track_id);
@@ -0,0 +1,13 @@
+-- playlists defined by rules instead of items; rules is a JSON SmartPlaylistRules, evaluated
+-- against the library whenever the playlist is read
+CREATE TABLE IF NOT EXISTS smart_playlist (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    rules TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- the user's rating of a track, from 1 to 5, or NULL if it hasn't been rated; the scanner never
+-- writes this, so ratings survive rescans
+ALTER TABLE track ADD rating INTEGER;
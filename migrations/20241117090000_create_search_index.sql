@@ -0,0 +1,131 @@
+-- full-text index of the library, used by search
+-- every artist, album and track has one row, identified by rowid = id * 4 + kind, where kind is 1
+-- for artists, 2 for albums and 3 for tracks; this lets the triggers below find rows by rowid
+-- instead of scanning the whole index
+-- albums and tracks also index the names of the things they belong to, so that e.g.
+-- `artist:coltrane` finds Coltrane's albums and tracks as well as Coltrane himself
+CREATE VIRTUAL TABLE IF NOT EXISTS library_search USING fts5 (
+    title,
+    album,
+    artist,
+    genre,
+    label,
+    year,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- rank matches in titles above matches in the names of albums and artists, and those above
+-- matches in genres, labels and years
+INSERT INTO library_search (library_search, rank)
+    VALUES ('rank', 'bm25(10.0, 5.0, 5.0, 2.0, 1.0, 1.0)');
+
+INSERT INTO library_search (rowid, artist)
+    SELECT id * 4 + 1, name FROM artist;
+
+INSERT INTO library_search (rowid, album, artist, label, year)
+    SELECT album.id * 4 + 2, album.title, artist.name, album.label, substr(album.release_date, 1, 4)
+    FROM album
+    LEFT JOIN artist ON artist.id = album.artist_id;
+
+INSERT INTO library_search (rowid, title, album, artist, genre, label, year)
+    SELECT
+        track.id * 4 + 3,
+        track.title,
+        album.title,
+        artist.name,
+        track.genres,
+        album.label,
+        substr(album.release_date, 1, 4)
+    FROM track
+    LEFT JOIN album ON album.id = track.album_id
+    LEFT JOIN artist ON artist.id = album.artist_id;
+
+CREATE TRIGGER IF NOT EXISTS search_insert_artist_trigger AFTER INSERT ON artist
+BEGIN
+    INSERT INTO library_search (rowid, artist) VALUES (NEW.id * 4 + 1, NEW.name);
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_update_artist_trigger AFTER UPDATE OF name ON artist
+BEGIN
+    UPDATE library_search SET artist = NEW.name
+    WHERE rowid = NEW.id * 4 + 1
+        OR rowid IN (SELECT id * 4 + 2 FROM album WHERE artist_id = NEW.id)
+        OR rowid IN (
+            SELECT track.id * 4 + 3 FROM track
+            JOIN album ON album.id = track.album_id
+            WHERE album.artist_id = NEW.id
+        );
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_delete_artist_trigger AFTER DELETE ON artist
+BEGIN
+    DELETE FROM library_search WHERE rowid = OLD.id * 4 + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_insert_album_trigger AFTER INSERT ON album
+BEGIN
+    INSERT INTO library_search (rowid, album, artist, label, year)
+        VALUES (
+            NEW.id * 4 + 2,
+            NEW.title,
+            (SELECT name FROM artist WHERE id = NEW.artist_id),
+            NEW.label,
+            substr(NEW.release_date, 1, 4)
+        );
+END;
+
+-- not fired by art changes, which are by far the most common update
+CREATE TRIGGER IF NOT EXISTS search_update_album_trigger
+    AFTER UPDATE OF title, artist_id, label, release_date ON album
+BEGIN
+    UPDATE library_search SET
+        album = NEW.title,
+        artist = (SELECT name FROM artist WHERE id = NEW.artist_id),
+        label = NEW.label,
+        year = substr(NEW.release_date, 1, 4)
+    WHERE rowid = NEW.id * 4 + 2
+        OR rowid IN (SELECT id * 4 + 3 FROM track WHERE album_id = NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_delete_album_trigger AFTER DELETE ON album
+BEGIN
+    DELETE FROM library_search WHERE rowid = OLD.id * 4 + 2;
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_insert_track_trigger AFTER INSERT ON track
+BEGIN
+    INSERT INTO library_search (rowid, title, album, artist, genre, label, year)
+        SELECT
+            NEW.id * 4 + 3,
+            NEW.title,
+            album.title,
+            artist.name,
+            NEW.genres,
+            album.label,
+            substr(album.release_date, 1, 4)
+        FROM (SELECT NEW.album_id AS id) AS new_album
+        LEFT JOIN album ON album.id = new_album.id
+        LEFT JOIN artist ON artist.id = album.artist_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_update_track_trigger
+    AFTER UPDATE OF title, album_id, genres ON track
+BEGIN
+    UPDATE library_search SET
+        title = NEW.title,
+        album = (SELECT title FROM album WHERE id = NEW.album_id),
+        artist = (
+            SELECT artist.name FROM album
+            JOIN artist ON artist.id = album.artist_id
+            WHERE album.id = NEW.album_id
+        ),
+        genre = NEW.genres,
+        label = (SELECT label FROM album WHERE id = NEW.album_id),
+        year = (SELECT substr(release_date, 1, 4) FROM album WHERE id = NEW.album_id)
+    WHERE rowid = NEW.id * 4 + 3;
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_delete_track_trigger AFTER DELETE ON track
+BEGIN
+    DELETE FROM library_search WHERE rowid = OLD.id * 4 + 3;
+END;
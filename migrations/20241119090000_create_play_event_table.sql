@@ -0,0 +1,21 @@
+-- one row every time a track stops playing, written by the playback interface
+CREATE TABLE IF NOT EXISTS play_event (
+    id INTEGER PRIMARY KEY,
+    -- the library track, missing for streams and files outside of the library; kept as NULL when
+    -- the track is removed so that listening time stays accurate
+    track_id INTEGER,
+    location TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    -- seconds of audio actually played, not counting parts skipped over by seeking
+    listened INTEGER NOT NULL,
+    -- whether the track was ended before half of it (or four minutes) had been played; skipped
+    -- plays don't count towards play counts
+    skipped BOOLEAN NOT NULL,
+    -- what playback was started from (album, playlist or smart_playlist) and its id
+    source TEXT,
+    source_id INTEGER,
+    FOREIGN KEY (track_id) REFERENCES track (id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS play_event_track_id_idx ON play_event (track_id, skipped);
+CREATE INDEX IF NOT EXISTS play_event_started_at_idx ON play_event (started_at);
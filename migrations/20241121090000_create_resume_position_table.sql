@@ -0,0 +1,8 @@
+-- where playback of each audiobook was left off, written by the playback thread
+CREATE TABLE IF NOT EXISTS resume_position (
+    track_id INTEGER PRIMARY KEY,
+    -- seconds from the start of the file
+    position INTEGER NOT NULL,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (track_id) REFERENCES track (id) ON DELETE CASCADE
+);
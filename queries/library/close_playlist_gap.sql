@@ -0,0 +1,2 @@
+UPDATE playlist_item SET position = position - 1
+WHERE playlist_id = $1 AND position > $2;
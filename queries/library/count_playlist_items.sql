@@ -0,0 +1 @@
+SELECT count(*) FROM playlist_item WHERE playlist_id = $1;
@@ -0,0 +1,3 @@
+INSERT INTO play_event (track_id, location, started_at, listened, skipped, source, source_id)
+    VALUES ((SELECT id FROM track WHERE location = $1), $1, $2, $3, $4, $5, $6)
+    RETURNING id;
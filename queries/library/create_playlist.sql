@@ -0,0 +1,3 @@
+INSERT INTO playlist (name)
+    VALUES ($1)
+    RETURNING id;
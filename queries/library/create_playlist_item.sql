@@ -0,0 +1,8 @@
+INSERT INTO playlist_item (playlist_id, track_id, location, position)
+    VALUES (
+        $1,
+        (SELECT id FROM track WHERE location = $2),
+        $2,
+        (SELECT coalesce(max(position) + 1, 0) FROM playlist_item WHERE playlist_id = $1)
+    )
+    RETURNING id;
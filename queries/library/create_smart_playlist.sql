@@ -0,0 +1,3 @@
+INSERT INTO smart_playlist (name, rules)
+    VALUES ($1, $2)
+    RETURNING id;
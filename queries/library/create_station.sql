@@ -0,0 +1,5 @@
+INSERT INTO station (name, url)
+    VALUES ($1, $2)
+    ON CONFLICT (url) DO UPDATE SET
+        name = EXCLUDED.name
+    RETURNING id;
@@ -0,0 +1 @@
+DELETE FROM playlist WHERE id = $1;
@@ -0,0 +1,2 @@
+DELETE FROM playlist_item WHERE id = $1
+    RETURNING playlist_id, position;
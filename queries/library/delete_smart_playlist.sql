@@ -0,0 +1 @@
+DELETE FROM smart_playlist WHERE id = $1;
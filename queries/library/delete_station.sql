@@ -0,0 +1 @@
+DELETE FROM station WHERE id = $1;
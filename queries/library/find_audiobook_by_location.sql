@@ -0,0 +1,3 @@
+SELECT track.id, resume_position.position FROM track
+    LEFT JOIN resume_position ON resume_position.track_id = track.id
+    WHERE track.location = $1 AND track.audiobook;
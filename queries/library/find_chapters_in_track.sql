@@ -0,0 +1 @@
+SELECT * FROM chapter WHERE track_id = $1 ORDER BY chapter_index ASC;
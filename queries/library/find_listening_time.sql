@@ -0,0 +1 @@
+SELECT coalesce(sum(listened), 0) FROM play_event WHERE $1 IS NULL OR started_at >= $1;
@@ -0,0 +1 @@
+SELECT * FROM play_event ORDER BY started_at DESC LIMIT $1;
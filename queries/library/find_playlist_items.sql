@@ -0,0 +1,12 @@
+SELECT
+    playlist_item.id,
+    playlist_item.playlist_id,
+    playlist_item.track_id,
+    coalesce(track.location, playlist_item.location) AS location,
+    playlist_item.position,
+    track.title,
+    track.duration
+FROM playlist_item
+LEFT JOIN track ON track.id = playlist_item.track_id
+WHERE playlist_item.playlist_id = $1
+ORDER BY playlist_item.position ASC;
@@ -0,0 +1,4 @@
+SELECT playlist.*, count(playlist_item.id) AS item_count FROM playlist
+LEFT JOIN playlist_item ON playlist_item.playlist_id = playlist.id
+GROUP BY playlist.id
+ORDER BY playlist.name COLLATE NOCASE ASC;
@@ -0,0 +1 @@
+SELECT * FROM scan_error ORDER BY failed_at DESC;
@@ -0,0 +1 @@
+SELECT * FROM smart_playlist WHERE id = $1;
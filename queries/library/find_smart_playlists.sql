@@ -0,0 +1 @@
+SELECT * FROM smart_playlist ORDER BY name COLLATE NOCASE ASC;
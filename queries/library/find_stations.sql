@@ -0,0 +1 @@
+SELECT * FROM station ORDER BY name COLLATE NOCASE ASC;
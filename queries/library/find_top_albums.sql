@@ -0,0 +1,10 @@
+-- plays of each album since $1 (or ever, if $1 is NULL), most played first
+SELECT track.album_id AS id, count(*) AS play_count, sum(play_event.listened) AS listened
+FROM play_event
+JOIN track ON track.id = play_event.track_id
+WHERE NOT play_event.skipped
+    AND ($1 IS NULL OR play_event.started_at >= $1)
+    AND track.album_id IS NOT NULL
+GROUP BY track.album_id
+ORDER BY play_count DESC, listened DESC
+LIMIT $2;
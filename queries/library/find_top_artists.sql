@@ -0,0 +1,11 @@
+-- plays of each album artist since $1 (or ever, if $1 is NULL), most played first
+SELECT album.artist_id AS id, count(*) AS play_count, sum(play_event.listened) AS listened
+FROM play_event
+JOIN track ON track.id = play_event.track_id
+JOIN album ON album.id = track.album_id
+WHERE NOT play_event.skipped
+    AND ($1 IS NULL OR play_event.started_at >= $1)
+    AND album.artist_id IS NOT NULL
+GROUP BY album.artist_id
+ORDER BY play_count DESC, listened DESC
+LIMIT $2;
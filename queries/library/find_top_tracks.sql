@@ -0,0 +1,7 @@
+-- plays of each track since $1 (or ever, if $1 is NULL), most played first
+SELECT track_id AS id, count(*) AS play_count, sum(listened) AS listened
+FROM play_event
+WHERE NOT skipped AND ($1 IS NULL OR started_at >= $1) AND track_id IS NOT NULL
+GROUP BY track_id
+ORDER BY play_count DESC, listened DESC
+LIMIT $2;
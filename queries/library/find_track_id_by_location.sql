@@ -0,0 +1 @@
+SELECT id FROM track WHERE location = $1;
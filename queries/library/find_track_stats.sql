@@ -0,0 +1,7 @@
+SELECT
+    count(*) FILTER (WHERE NOT skipped) AS play_count,
+    count(*) FILTER (WHERE skipped) AS skip_count,
+    max(started_at) FILTER (WHERE NOT skipped) AS last_played,
+    coalesce(sum(listened), 0) AS listened
+FROM play_event
+WHERE track_id = $1;
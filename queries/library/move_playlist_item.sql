@@ -0,0 +1,8 @@
+-- moves the item at position $2 to position $3, shifting the items in between
+UPDATE playlist_item SET position = CASE
+        WHEN position = $2 THEN $3
+        WHEN $2 < $3 THEN position - 1
+        ELSE position + 1
+    END
+WHERE playlist_id = $1
+    AND position BETWEEN min($2, $3) AND max($2, $3);
@@ -0,0 +1,2 @@
+UPDATE playlist SET name = $2, updated_at = CURRENT_TIMESTAMP
+WHERE id = $1;
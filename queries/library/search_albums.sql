@@ -0,0 +1,4 @@
+SELECT rowid / 4 FROM library_search
+WHERE library_search MATCH $1 AND rowid % 4 = 2
+ORDER BY rank
+LIMIT $2;
@@ -0,0 +1,5 @@
+-- see the search index migration for how rowids map to artists, albums and tracks
+SELECT rowid / 4 FROM library_search
+WHERE library_search MATCH $1 AND rowid % 4 = 1
+ORDER BY rank
+LIMIT $2;
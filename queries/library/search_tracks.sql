@@ -0,0 +1,5 @@
+SELECT track.* FROM library_search
+JOIN track ON track.id = library_search.rowid / 4
+WHERE library_search MATCH $1 AND library_search.rowid % 4 = 3
+ORDER BY library_search.rank
+LIMIT $2;
@@ -0,0 +1 @@
+UPDATE playlist SET updated_at = CURRENT_TIMESTAMP WHERE id = $1;
@@ -0,0 +1,5 @@
+INSERT INTO resume_position (track_id, position)
+    VALUES ($1, $2)
+    ON CONFLICT (track_id) DO UPDATE SET
+        position = excluded.position,
+        updated_at = CURRENT_TIMESTAMP;
@@ -0,0 +1,2 @@
+UPDATE smart_playlist SET name = $2, rules = $3, updated_at = CURRENT_TIMESTAMP
+WHERE id = $1;
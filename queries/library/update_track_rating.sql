@@ -0,0 +1 @@
+UPDATE track SET rating = $2 WHERE id = $1;
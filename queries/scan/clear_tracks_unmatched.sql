@@ -0,0 +1,4 @@
+-- $1 is the folder's path followed by a separator, see set_tracks_offline.sql
+UPDATE track SET offline = 0, offline_since = NULL
+    WHERE offline_since IS NOT NULL AND substr(location, 1, length($1)) = $1
+    RETURNING album_id;
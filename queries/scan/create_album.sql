@@ -1,4 +1,9 @@
-INSERT INTO album (title, title_sortable, artist_id, image, thumb, release_date, label, catalog_number, isrc)
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-    ON CONFLICT (title, artist_id) DO NOTHING -- TODO: ideally we should have some way of updating this
+INSERT INTO album (title, title_sortable, artist_id, image, thumb, release_date, label, catalog_number, isrc, mbid)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+    ON CONFLICT (
+        title,
+        coalesce(artist_id, -1),
+        coalesce(mbid, ''),
+        coalesce(substr(release_date, 1, 4), '')
+    ) DO NOTHING -- TODO: ideally we should have some way of updating this
     RETURNING id;
@@ -0,0 +1,3 @@
+INSERT INTO album_image (album_id, usage, mime, hash, image)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (album_id, hash) DO NOTHING;
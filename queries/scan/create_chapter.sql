@@ -0,0 +1,2 @@
+INSERT INTO chapter (track_id, chapter_index, title, start_offset, end_offset)
+    VALUES ($1, $2, $3, $4, $5);
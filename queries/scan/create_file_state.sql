@@ -0,0 +1,8 @@
+INSERT INTO file_state (path, kind, modified, size, hash)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (path) DO UPDATE SET
+        kind = EXCLUDED.kind,
+        modified = EXCLUDED.modified,
+        size = EXCLUDED.size,
+        hash = EXCLUDED.hash,
+        scanned_at = CURRENT_TIMESTAMP;
@@ -0,0 +1,2 @@
+INSERT INTO offline_root (path) VALUES ($1)
+    ON CONFLICT (path) DO NOTHING;
@@ -0,0 +1,6 @@
+INSERT INTO scan_error (location, stage, message)
+    VALUES ($1, $2, $3)
+    ON CONFLICT (location) DO UPDATE SET
+        stage = excluded.stage,
+        message = excluded.message,
+        failed_at = CURRENT_TIMESTAMP;
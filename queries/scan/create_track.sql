@@ -1,5 +1,5 @@
-INSERT INTO track (title, title_sortable, album_id, track_number, disc_number, duration, location, genres)
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+INSERT INTO track (title, title_sortable, album_id, track_number, disc_number, duration, location, genres, start_offset, end_offset, audiobook, codec, container, sample_rate, bit_depth, channels, bitrate, file_size, lossless, fingerprint)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
     ON CONFLICT (location) DO UPDATE SET
         title = EXCLUDED.title,
         title_sortable = EXCLUDED.title_sortable,
@@ -8,5 +8,18 @@ INSERT INTO track (title, title_sortable, album_id, track_number, disc_number, d
         disc_number = EXCLUDED.disc_number,
         duration = EXCLUDED.duration,
         location = EXCLUDED.location,
-        genres = EXCLUDED.genres
+        genres = EXCLUDED.genres,
+        start_offset = EXCLUDED.start_offset,
+        end_offset = EXCLUDED.end_offset,
+        audiobook = EXCLUDED.audiobook,
+        codec = EXCLUDED.codec,
+        container = EXCLUDED.container,
+        sample_rate = EXCLUDED.sample_rate,
+        bit_depth = EXCLUDED.bit_depth,
+        channels = EXCLUDED.channels,
+        bitrate = EXCLUDED.bitrate,
+        file_size = EXCLUDED.file_size,
+        lossless = EXCLUDED.lossless,
+        fingerprint = EXCLUDED.fingerprint,
+        needs_rescan = 0
     RETURNING id;
@@ -0,0 +1 @@
+DELETE FROM album_image WHERE album_id = $1;
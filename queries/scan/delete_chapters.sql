@@ -0,0 +1 @@
+DELETE FROM chapter WHERE track_id = $1;
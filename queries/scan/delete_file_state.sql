@@ -0,0 +1 @@
+DELETE FROM file_state WHERE path = $1;
@@ -0,0 +1 @@
+DELETE FROM offline_root WHERE path = $1;
@@ -0,0 +1,4 @@
+-- albums are left without tracks when their tracks are retagged or moved to another album, which
+-- the delete triggers don't catch; deleting the albums also deletes artists left without albums
+DELETE FROM album WHERE NOT EXISTS (SELECT 1 FROM track WHERE track.album_id = album.id)
+    RETURNING id, artist_id;
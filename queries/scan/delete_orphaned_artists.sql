@@ -0,0 +1,3 @@
+-- artists are created for tracks without an album too, which never get an album to keep them
+DELETE FROM artist WHERE NOT EXISTS (SELECT 1 FROM album WHERE album.artist_id = artist.id)
+    RETURNING id;
@@ -0,0 +1 @@
+DELETE FROM scan_error WHERE location = $1;
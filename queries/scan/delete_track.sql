@@ -1 +1,3 @@
-DELETE FROM track WHERE location = $1;
+-- virtual tracks are stored as <path>#t=<start>,<end>, and are deleted along with their file
+DELETE FROM track WHERE location = $1 OR substr(location, 1, length($1) + 3) = $1 || '#t='
+    RETURNING album_id;
@@ -0,0 +1 @@
+DELETE FROM track WHERE location = $1;
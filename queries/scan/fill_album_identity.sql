@@ -0,0 +1,7 @@
+-- albums that were created from tracks without a release ID or date take them from the first
+-- track that has them, so that other releases with the same title aren't merged into them. The
+-- album is left alone if another one already has that identity
+UPDATE OR IGNORE album SET
+    mbid = coalesce(mbid, $2),
+    release_date = coalesce(release_date, $3)
+WHERE id = $1;
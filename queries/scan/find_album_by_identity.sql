@@ -0,0 +1,6 @@
+-- matches the key of album_identity_idx exactly, to find the album an insert conflicted with
+SELECT id FROM album
+    WHERE title = $1
+    AND coalesce(artist_id, -1) = coalesce($2, -1)
+    AND coalesce(mbid, '') = coalesce($3, '')
+    AND coalesce(substr(release_date, 1, 4), '') = coalesce($4, '');
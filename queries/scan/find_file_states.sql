@@ -0,0 +1 @@
+SELECT path, kind, modified, size, hash FROM file_state;
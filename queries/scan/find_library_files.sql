@@ -0,0 +1,5 @@
+-- the tracks are included as well, since their files can be missing from file_state (see
+-- find_tracks_without_properties.sql); virtual tracks are mapped to their files by the scanner
+SELECT path FROM file_state WHERE kind = 'audio'
+UNION
+SELECT location FROM track;
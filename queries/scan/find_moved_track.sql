@@ -0,0 +1,4 @@
+-- a track can only be moved to a location that isn't already taken by another track
+SELECT id, location FROM track
+    WHERE fingerprint = $1
+    AND NOT EXISTS (SELECT 1 FROM track WHERE location = $2);
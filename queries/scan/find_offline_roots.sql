@@ -0,0 +1 @@
+SELECT path, offline_since FROM offline_root;
@@ -0,0 +1 @@
+SELECT location FROM track WHERE codec IS NULL OR fingerprint IS NULL OR needs_rescan;
@@ -0,0 +1 @@
+SELECT location FROM track WHERE substr(location, 1, length($1) + 3) = $1 || '#t=';
@@ -1 +1,14 @@
-SELECT id FROM album WHERE title = $1;
+-- $3 is the MusicBrainz release ID and $4 the release year; albums missing either one match
+-- albums that have it, so that partially tagged releases aren't split
+SELECT id FROM album
+    WHERE title = $1
+    AND artist_id IS $2
+    AND (
+        mbid = $3
+        OR (
+            ($3 IS NULL OR mbid IS NULL)
+            AND ($4 IS NULL OR release_date IS NULL OR substr(release_date, 1, 4) = $4)
+        )
+    )
+    ORDER BY mbid IS $3 DESC, substr(release_date, 1, 4) IS $4 DESC
+    LIMIT 1;
@@ -1 +0,0 @@
-SELECT id FROM artist WHERE name = $1;
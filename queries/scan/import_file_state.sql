@@ -0,0 +1,4 @@
+-- states already in the database are newer than the ones being imported
+INSERT INTO file_state (path, modified)
+    VALUES ($1, $2)
+    ON CONFLICT (path) DO NOTHING;
@@ -0,0 +1 @@
+UPDATE track SET location = $2 WHERE id = $1;
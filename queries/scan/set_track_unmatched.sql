@@ -0,0 +1,5 @@
+-- tracks outside of every library folder are kept as offline, since their folder might only be
+-- missing from the settings; virtual tracks are stored as <path>#t=<start>,<end>
+UPDATE track SET offline = 1, offline_since = coalesce(offline_since, CURRENT_TIMESTAMP)
+    WHERE location = $1 OR substr(location, 1, length($1) + 3) = $1 || '#t='
+    RETURNING album_id, offline_since;
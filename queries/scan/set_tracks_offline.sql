@@ -0,0 +1,3 @@
+-- $1 is the folder's path followed by a separator, so that folders sharing a prefix aren't matched
+UPDATE track SET offline = $2 WHERE substr(location, 1, length($1)) = $1
+    RETURNING album_id;
@@ -0,0 +1,9 @@
+-- returns whether the album's art differs from $6, so that the thumbnail is only regenerated when
+-- the art has actually changed
+UPDATE album SET
+    title_sortable = $2,
+    label = $3,
+    catalog_number = $4,
+    isrc = $5
+WHERE id = $1
+    RETURNING image IS NOT $6;
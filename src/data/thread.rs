@@ -1,5 +1,6 @@
 use std::{
     io::Cursor,
+    path::Path,
     sync::{
         mpsc::{Receiver, Sender},
         Arc,
@@ -14,7 +15,15 @@ use smallvec::SmallVec;
 use tracing::{debug, warn};
 
 use crate::{
-    media::{builtin::symphonia::SymphoniaProvider, metadata::Metadata, traits::MediaProvider},
+    media::{
+        builtin::{create_provider, provider_name, symphonia::SymphoniaProvider},
+        cue::{find_cue_sheet, split_location},
+        metadata::Metadata,
+        playlist::is_playlist_location,
+        source::open_location,
+        stream::is_stream_location,
+        traits::{MediaPlugin, MediaProvider},
+    },
     util::rgb_to_bgr,
 };
 
@@ -43,6 +52,7 @@ pub struct DataThread {
     image_cache: AHashMap<u64, Arc<RenderImage>>,
     // TODO: get metadata from other providers as well
     media_provider: Box<dyn MediaProvider>,
+    media_provider_name: &'static str,
     hash_state: RandomState,
 }
 
@@ -60,6 +70,7 @@ impl DataThread {
                     events_tx,
                     image_cache: AHashMap::new(),
                     media_provider: Box::new(SymphoniaProvider::default()),
+                    media_provider_name: SymphoniaProvider::NAME,
                     hash_state: RandomState::new(),
                 };
 
@@ -139,19 +150,30 @@ impl DataThread {
     }
 
     fn read_metadata(&mut self, path: String) -> UIQueueItem {
-        let file = if let Ok(file) = std::fs::File::open(path.clone()) {
-            file
+        // streams aren't opened just to read their metadata, which usually isn't available until
+        // playback starts anyway
+        if is_stream_location(&path) || is_playlist_location(&path) {
+            return create_generic_queue_item(path);
+        }
+
+        let (file_path, range) = split_location(&path);
+
+        let opened = if let Ok(opened) = open_location(file_path) {
+            opened
         } else {
             warn!("Failed to open file {}, queue may be desynced", path);
             warn!("Ensure the file exists before placing it in the queue");
             return create_generic_queue_item(path);
         };
 
-        if self
-            .media_provider
-            .open(file, path.split(".").last().map(|v| v.to_string()))
-            .is_err()
-        {
+        let name = provider_name(opened.ext.as_deref());
+
+        if name != self.media_provider_name {
+            self.media_provider = create_provider(name);
+            self.media_provider_name = name;
+        }
+
+        if self.media_provider.open(opened.source, opened.ext).is_err() {
             warn!("Media provider couldn't open file, creating generic queue item");
             return create_generic_queue_item(path);
         }
@@ -161,13 +183,18 @@ impl DataThread {
             return create_generic_queue_item(path);
         }
 
-        let metadata = if let Ok(metadata) = self.media_provider.read_metadata() {
+        let mut metadata = if let Ok(metadata) = self.media_provider.read_metadata() {
             metadata.clone()
         } else {
             warn!("Media provider couldn't retrieve metadata, creating generic queue item");
             return create_generic_queue_item(path);
         };
 
+        // virtual tracks take their metadata from the CUE sheet instead of the file
+        if let Some(range) = range {
+            self.apply_cue_metadata(Path::new(file_path), range.start, &mut metadata);
+        }
+
         let album_art = self
             .media_provider
             .read_image()
@@ -217,6 +244,18 @@ impl DataThread {
         }
     }
 
+    fn apply_cue_metadata(&self, path: &Path, start: f64, metadata: &mut Metadata) {
+        let Some(sheet) = find_cue_sheet(path, metadata) else {
+            warn!("Couldn't find CUE sheet for virtual track in {:?}", path);
+            return;
+        };
+
+        if let Some(track) = sheet.track_at(path, start) {
+            let count = sheet.tracks_for_file(path).map(|v| v.len());
+            sheet.apply_to_metadata(track, count.unwrap_or_default(), metadata);
+        }
+    }
+
     fn evict_unneeded_data(&mut self) {
         // we have to duplicate this data in order to get around borrowing rules
         let keys: Vec<u64> = self.image_cache.keys().cloned().collect();
@@ -1,4 +1,5 @@
 pub mod builtin;
+pub mod dsd;
 pub mod errors;
 pub mod format;
 pub mod resample;
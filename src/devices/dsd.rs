@@ -0,0 +1,325 @@
+use std::{collections::VecDeque, f64::consts::PI};
+
+use intx::I24;
+
+use crate::media::playback::{PlaybackFrame, Samples};
+
+use super::format::{FormatInfo, SampleFormat};
+
+/// The number of taps in the decimation filter. Each byte of DSD covers 8 of them.
+const TAPS: usize = 128;
+const TABLES: usize = TAPS / 8;
+
+/// The cutoff of the decimation filter, relative to the DSD sample rate (about 90kHz for DSD64).
+/// The filter only has to remove the noise that would alias into the audible range after
+/// decimation; the resampler takes care of everything above the device's Nyquist frequency.
+const CUTOFF: f64 = 0.032;
+
+/// DSD's idle pattern, which decodes to silence.
+const DSD_SILENCE: u8 = 0x69;
+
+/// Returns true if DSD at the specified rate can be sent to a device with the specified format
+/// as DSD over PCM (DoP). DoP needs an integer format with at least 24 bits, running at 1/16th of
+/// the DSD rate.
+pub fn dop_supported(dsd_rate: u32, format: &FormatInfo) -> bool {
+    let format_supported = matches!(
+        format.sample_type,
+        SampleFormat::Signed32 | SampleFormat::Signed24 | SampleFormat::Signed24Packed
+    );
+
+    format_supported && format.sample_rate as u64 * 16 == dsd_rate as u64
+}
+
+/// Packs DSD bits into bytes, earliest bit first. Bits that don't fill a whole byte are kept in
+/// `pending` until the next call.
+fn pack_bytes(pending: &mut Vec<bool>, bits: Vec<bool>) -> Vec<u8> {
+    pending.extend(bits);
+
+    let whole = pending.len() / 8 * 8;
+    let bytes = pending[..whole]
+        .chunks_exact(8)
+        .map(|chunk| chunk.iter().fold(0u8, |byte, bit| (byte << 1) | *bit as u8))
+        .collect();
+
+    pending.drain(..whole);
+
+    bytes
+}
+
+/// Converts DSD to PCM using a low-pass FIR filter, decimating by 8 (DSD64 becomes 352.8kHz
+/// PCM). The filter is evaluated a byte at a time using lookup tables, since every byte of DSD
+/// can only contribute 256 different values to the output.
+///
+/// DSD's 0dB reference is 50% modulation, so the output is 6dB quieter than a PCM master would
+/// be. No gain is applied, since DSD is allowed to exceed the reference.
+pub struct DsdDecimator {
+    tables: Vec<[f32; 256]>,
+    history: Vec<VecDeque<u8>>,
+    pending: Vec<Vec<bool>>,
+}
+
+impl DsdDecimator {
+    pub fn new(channels: usize) -> Self {
+        let center = (TAPS - 1) as f64 / 2.0;
+
+        let coefficients: Vec<f64> = (0..TAPS)
+            .map(|i| {
+                let x = i as f64 - center;
+                let sinc = if x == 0.0 {
+                    2.0 * CUTOFF
+                } else {
+                    (2.0 * PI * CUTOFF * x).sin() / (PI * x)
+                };
+                let phase = 2.0 * PI * i as f64 / (TAPS - 1) as f64;
+                let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+
+                sinc * window
+            })
+            .collect();
+
+        let sum: f64 = coefficients.iter().sum();
+
+        // table k covers the kth most recent byte, whose least significant bit is the most
+        // recent sample
+        let tables = (0..TABLES)
+            .map(|k| {
+                let mut table = [0.0; 256];
+
+                for (byte, value) in table.iter_mut().enumerate() {
+                    *value = (0..8)
+                        .map(|bit| {
+                            let sign = if byte >> bit & 1 == 1 { 1.0 } else { -1.0 };
+                            coefficients[k * 8 + bit] / sum * sign
+                        })
+                        .sum::<f64>() as f32;
+                }
+
+                table
+            })
+            .collect();
+
+        DsdDecimator {
+            tables,
+            history: vec![VecDeque::from(vec![DSD_SILENCE; TABLES]); channels],
+            pending: vec![Vec::new(); channels],
+        }
+    }
+
+    pub fn convert(&mut self, samples: Vec<Vec<bool>>) -> Vec<Vec<f32>> {
+        samples
+            .into_iter()
+            .zip(self.history.iter_mut().zip(self.pending.iter_mut()))
+            .map(|(bits, (history, pending))| {
+                pack_bytes(pending, bits)
+                    .into_iter()
+                    .map(|byte| {
+                        history.pop_back();
+                        history.push_front(byte);
+
+                        history
+                            .iter()
+                            .zip(self.tables.iter())
+                            .map(|(byte, table)| table[*byte as usize])
+                            .sum()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Packs DSD into PCM samples for DACs that unpack it themselves (DSD over PCM). Each sample
+/// carries 16 bits of DSD below a marker byte, which alternates between 0x05 and 0xFA so the DAC
+/// can tell the stream apart from PCM. The samples must reach the DAC unchanged.
+pub struct DopPacker {
+    pending: Vec<Vec<bool>>,
+    frames: u64,
+}
+
+impl DopPacker {
+    pub fn new(channels: usize) -> Self {
+        DopPacker {
+            pending: vec![Vec::new(); channels],
+            frames: 0,
+        }
+    }
+
+    /// Returns the DoP samples as 24-bit values, which are sign-extended so they can be stored
+    /// in any signed integer format.
+    fn pack(&mut self, samples: Vec<Vec<bool>>) -> Vec<Vec<i32>> {
+        let frames = self.frames;
+
+        let packed: Vec<Vec<i32>> = samples
+            .into_iter()
+            .zip(self.pending.iter_mut())
+            .map(|(bits, pending)| {
+                let bytes = pack_bytes(pending, bits);
+
+                // an odd byte has to wait for the rest of its sample
+                if bytes.len() % 2 == 1 {
+                    let last = bytes[bytes.len() - 1];
+                    let carried = (0..8).rev().map(|bit| last >> bit & 1 == 1);
+                    pending.splice(0..0, carried);
+                }
+
+                bytes
+                    .chunks_exact(2)
+                    .enumerate()
+                    .map(|(i, pair)| {
+                        let marker: u32 = match (frames + i as u64) % 2 {
+                            0 => 0x05,
+                            _ => 0xFA,
+                        };
+                        let value = (marker << 16) | ((pair[0] as u32) << 8) | pair[1] as u32;
+
+                        ((value << 8) as i32) >> 8
+                    })
+                    .collect()
+            })
+            .collect();
+
+        self.frames += packed.first().map(|v| v.len()).unwrap_or_default() as u64;
+
+        packed
+    }
+}
+
+/// Turns DSD frames into PCM frames the device can play.
+pub enum DsdConverter {
+    Pcm(DsdDecimator),
+    Dop(DopPacker),
+}
+
+impl DsdConverter {
+    pub fn convert(&mut self, frame: PlaybackFrame, target_depth: SampleFormat) -> PlaybackFrame {
+        let Samples::DSD(samples) = frame.samples else {
+            return frame;
+        };
+
+        match self {
+            DsdConverter::Pcm(decimator) => PlaybackFrame {
+                samples: Samples::Float32(decimator.convert(samples)),
+                rate: frame.rate / 8,
+            },
+            DsdConverter::Dop(packer) => {
+                let packed = packer.pack(samples);
+
+                let samples = match target_depth {
+                    SampleFormat::Signed32 => Samples::Signed32(
+                        packed
+                            .into_iter()
+                            .map(|v| v.into_iter().map(|v| v << 8).collect())
+                            .collect(),
+                    ),
+                    _ => Samples::Signed24(
+                        packed
+                            .into_iter()
+                            .map(|v| {
+                                v.into_iter()
+                                    .map(|v| I24::try_from(v).expect("DoP sample is not 24 bits"))
+                                    .collect()
+                            })
+                            .collect(),
+                    ),
+                };
+
+                PlaybackFrame {
+                    samples,
+                    rate: frame.rate / 16,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unpacks bytes into DSD bits, most significant bit first.
+    fn bits(bytes: &[u8]) -> Vec<bool> {
+        bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+            .collect()
+    }
+
+    fn decimate(byte: u8, len: usize) -> Vec<f32> {
+        let mut decimator = DsdDecimator::new(1);
+        let output = decimator.convert(vec![bits(&vec![byte; len])]);
+
+        // skip the bytes that are still mixed with the initial history
+        output[0][TABLES..].to_vec()
+    }
+
+    #[test]
+    fn dop_markers_alternate() {
+        let mut packer = DopPacker::new(2);
+
+        let packed = packer.pack(vec![
+            bits(&[0xAB, 0xCD, 0x12, 0x34, 0x56]),
+            bits(&[0x01, 0x02, 0x03, 0x04, 0x05]),
+        ]);
+
+        assert_eq!(packed[0], vec![0x05ABCD, 0xFA1234 - 0x1000000]);
+        assert_eq!(packed[1], vec![0x050102, 0xFA0304 - 0x1000000]);
+
+        // the odd byte is carried over, and the markers carry on from the last frame
+        let packed = packer.pack(vec![bits(&[0x78]), bits(&[0x06])]);
+
+        assert_eq!(packed[0], vec![0x055678]);
+        assert_eq!(packed[1], vec![0x050506]);
+
+        let packed = packer.pack(vec![bits(&[0x9A, 0xBC]), bits(&[0x07, 0x08])]);
+
+        assert_eq!(packed[0], vec![0xFA9ABC - 0x1000000]);
+        assert_eq!(packed[1], vec![0xFA0708 - 0x1000000]);
+    }
+
+    #[test]
+    fn dop_fills_the_top_of_32_bit_samples() {
+        let mut converter = DsdConverter::Dop(DopPacker::new(1));
+
+        let frame = converter.convert(
+            PlaybackFrame {
+                samples: Samples::DSD(vec![bits(&[0x12, 0x34, 0x56, 0x78])]),
+                rate: 2822400,
+            },
+            SampleFormat::Signed32,
+        );
+
+        assert_eq!(frame.rate, 176400);
+
+        let Samples::Signed32(samples) = frame.samples else {
+            panic!("DoP should be sent as 32-bit samples");
+        };
+
+        assert_eq!(samples[0], vec![0x05123400, (0xFA567800u32) as i32]);
+    }
+
+    #[test]
+    fn decimates_silence_to_zero() {
+        let output = decimate(DSD_SILENCE, 256);
+
+        assert!(output.iter().all(|v| v.abs() < 0.01), "{:?}", output);
+    }
+
+    #[test]
+    fn decimates_dc() {
+        assert!(decimate(0xFF, 64).iter().all(|v| (v - 1.0).abs() < 1e-4));
+        assert!(decimate(0x00, 64).iter().all(|v| (v + 1.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn decimates_by_8() {
+        let mut decimator = DsdDecimator::new(2);
+
+        // partial bytes wait for the rest of their bits
+        let output = decimator.convert(vec![vec![true; 20], vec![false; 20]]);
+        assert_eq!(output[0].len(), 2);
+
+        let output = decimator.convert(vec![vec![true; 4], vec![false; 4]]);
+        assert_eq!(output[0].len(), 1);
+        assert_eq!(output[1].len(), 1);
+    }
+}
@@ -47,3 +47,11 @@ pub enum StateError {
 pub enum ResetError {
     Unknown,
 }
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ConversionError {
+    /// DSD samples reached a conversion that only handles PCM.
+    UnconvertedDsd,
+    /// The device asked for a format that samples can't be converted to.
+    UnsupportedFormat,
+}
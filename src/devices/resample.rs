@@ -4,7 +4,11 @@ use tracing::info;
 
 use crate::media::playback::{PlaybackFrame, Samples};
 
-use super::format::{FormatInfo, SampleFormat};
+use super::{
+    dsd::{DopPacker, DsdConverter, DsdDecimator},
+    errors::ConversionError,
+    format::{FormatInfo, SampleFormat},
+};
 
 fn scale<T, U>(target: Vec<Vec<T>>) -> Vec<Vec<U>>
 where
@@ -17,11 +21,12 @@ where
         .collect()
 }
 
-pub fn convert_samples<T>(target_frame: Samples) -> Vec<Vec<T>>
+/// Converts PCM samples to another format. DSD has to go through a `DsdConverter` first.
+pub fn convert_samples<T>(target_frame: Samples) -> Result<Vec<Vec<T>>, ConversionError>
 where
     T: Copy + SampleInto<f64> + SampleFrom<f64>,
 {
-    match target_frame {
+    Ok(match target_frame {
         Samples::Float64(v) => scale(v),
         Samples::Float32(v) => scale(v),
         Samples::Signed32(v) => scale(v),
@@ -32,8 +37,8 @@ where
         Samples::Unsigned16(v) => scale(v),
         Samples::Signed8(v) => scale(v),
         Samples::Unsigned8(v) => scale(v),
-        Samples::DSD(_) => unimplemented!(),
-    }
+        Samples::DSD(_) => return Err(ConversionError::UnconvertedDsd),
+    })
 }
 
 pub trait SampleInto<T> {
@@ -48,7 +53,7 @@ impl SampleInto<f64> for U24 {
 
 impl SampleInto<f64> for I24 {
     fn sample_into(self) -> f64 {
-        f64::from(i32::from(self)) / f64::from(i32::from(I24::MAX)) - 1.0
+        f64::from(i32::from(self)) / f64::from(i32::from(I24::MAX))
     }
 }
 
@@ -132,40 +137,46 @@ impl SampleFrom<f64> for f64 {
     }
 }
 
-pub fn match_bit_depth(target_frame: PlaybackFrame, target_depth: SampleFormat) -> PlaybackFrame {
+pub fn match_bit_depth(
+    target_frame: PlaybackFrame,
+    target_depth: SampleFormat,
+) -> Result<PlaybackFrame, ConversionError> {
     let rate = target_frame.rate;
 
     let samples = if !target_frame.samples.is_format(target_depth) {
         match target_depth {
             SampleFormat::Float64 => todo!(),
-            SampleFormat::Float32 => Samples::Float32(convert_samples(target_frame.samples)),
-            SampleFormat::Signed32 => Samples::Signed32(convert_samples(target_frame.samples)),
-            SampleFormat::Unsigned32 => Samples::Unsigned32(convert_samples(target_frame.samples)),
-            SampleFormat::Signed24 => Samples::Signed24(convert_samples(target_frame.samples)),
-            SampleFormat::Unsigned24 => Samples::Unsigned24(convert_samples(target_frame.samples)),
+            SampleFormat::Float32 => Samples::Float32(convert_samples(target_frame.samples)?),
+            SampleFormat::Signed32 => Samples::Signed32(convert_samples(target_frame.samples)?),
+            SampleFormat::Unsigned32 => Samples::Unsigned32(convert_samples(target_frame.samples)?),
+            SampleFormat::Signed24 => Samples::Signed24(convert_samples(target_frame.samples)?),
+            SampleFormat::Unsigned24 => Samples::Unsigned24(convert_samples(target_frame.samples)?),
             SampleFormat::Signed24Packed => {
-                Samples::Signed24(convert_samples(target_frame.samples))
+                Samples::Signed24(convert_samples(target_frame.samples)?)
             }
             SampleFormat::Unsigned24Packed => {
-                Samples::Unsigned24(convert_samples(target_frame.samples))
+                Samples::Unsigned24(convert_samples(target_frame.samples)?)
+            }
+            SampleFormat::Signed16 => Samples::Signed16(convert_samples(target_frame.samples)?),
+            SampleFormat::Unsigned16 => Samples::Unsigned16(convert_samples(target_frame.samples)?),
+            SampleFormat::Signed8 => Samples::Signed8(convert_samples(target_frame.samples)?),
+            SampleFormat::Unsigned8 => Samples::Unsigned8(convert_samples(target_frame.samples)?),
+            // native DSD output isn't supported, DSD only reaches devices through DoP
+            SampleFormat::DSD | SampleFormat::Unsupported => {
+                return Err(ConversionError::UnsupportedFormat)
             }
-            SampleFormat::Signed16 => Samples::Signed16(convert_samples(target_frame.samples)),
-            SampleFormat::Unsigned16 => Samples::Unsigned16(convert_samples(target_frame.samples)),
-            SampleFormat::Signed8 => Samples::Signed8(convert_samples(target_frame.samples)),
-            SampleFormat::Unsigned8 => Samples::Unsigned8(convert_samples(target_frame.samples)),
-            SampleFormat::DSD => unimplemented!(),
-            SampleFormat::Unsupported => panic!("target depth is unsupported"),
         }
     } else {
         target_frame.samples
     };
 
-    PlaybackFrame { samples, rate }
+    Ok(PlaybackFrame { samples, rate })
 }
 
 pub struct Resampler {
     resampler: FftFixedIn<f32>,
     duration: u64,
+    dsd: Option<DsdConverter>,
 }
 
 impl Resampler {
@@ -189,6 +200,30 @@ impl Resampler {
         Resampler {
             resampler,
             duration,
+            dsd: None,
+        }
+    }
+
+    /// Creates a resampler for DSD input. If `dop` is true, the DSD is packed into DoP frames at
+    /// 1/16th of the DSD rate, which must match the target rate. Otherwise, it's decimated to PCM
+    /// at 1/8th of the DSD rate and resampled from there.
+    pub fn new_dsd(
+        dsd_rate: u32,
+        target_rate: u32,
+        duration: u64,
+        channels: u16,
+        dop: bool,
+    ) -> Self {
+        if dop {
+            info!("Sending DSD as DoP at {:?}", target_rate);
+
+            let mut resampler = Resampler::new(target_rate, target_rate, duration / 16, channels);
+            resampler.dsd = Some(DsdConverter::Dop(DopPacker::new(channels as usize)));
+            resampler
+        } else {
+            let mut resampler = Resampler::new(dsd_rate / 8, target_rate, duration / 8, channels);
+            resampler.dsd = Some(DsdConverter::Pcm(DsdDecimator::new(channels as usize)));
+            resampler
         }
     }
 
@@ -196,9 +231,14 @@ impl Resampler {
         &mut self,
         frame: PlaybackFrame,
         target_format: &FormatInfo,
-    ) -> PlaybackFrame {
+    ) -> Result<PlaybackFrame, ConversionError> {
+        let frame = match &mut self.dsd {
+            Some(converter) => converter.convert(frame, target_format.sample_type),
+            None => frame,
+        };
+
         if target_format.sample_rate != frame.rate {
-            let source: Vec<Vec<f32>> = convert_samples(frame.samples);
+            let source: Vec<Vec<f32>> = convert_samples(frame.samples)?;
 
             let resampled = if source[0].len() < self.duration as usize {
                 self.resampler
@@ -222,3 +262,56 @@ impl Resampler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_signed_24_bit_samples() {
+        let samples = vec![vec![I24::MIN, I24::try_from(0).unwrap(), I24::MAX]];
+        let Ok(Samples::Float32(scaled)) = match_bit_depth(
+            PlaybackFrame {
+                samples: Samples::Signed24(samples.clone()),
+                rate: 44100,
+            },
+            SampleFormat::Float32,
+        )
+        .map(|v| v.samples) else {
+            panic!("24-bit samples should convert to floats");
+        };
+
+        assert!((scaled[0][0] + 1.0).abs() < 1e-6);
+        assert_eq!(scaled[0][1], 0.0);
+        assert_eq!(scaled[0][2], 1.0);
+
+        let restored: Vec<Vec<I24>> = convert_samples(Samples::Float32(scaled)).unwrap();
+        assert_eq!(restored[0][1..], samples[0][1..]);
+    }
+
+    #[test]
+    fn rejects_unconverted_dsd() {
+        let frame = PlaybackFrame {
+            samples: Samples::DSD(vec![vec![true; 16]]),
+            rate: 2822400,
+        };
+
+        assert_eq!(
+            match_bit_depth(frame, SampleFormat::Signed16).err(),
+            Some(ConversionError::UnconvertedDsd)
+        );
+    }
+
+    #[test]
+    fn rejects_dsd_output() {
+        let frame = PlaybackFrame {
+            samples: Samples::Signed16(vec![vec![0; 16]]),
+            rate: 44100,
+        };
+
+        assert_eq!(
+            match_bit_depth(frame, SampleFormat::DSD).err(),
+            Some(ConversionError::UnsupportedFormat)
+        );
+    }
+}
@@ -62,6 +62,11 @@ where
     T: SampleInto<f64> + SampleFrom<f64> + Copy,
 {
     fn scale(self, factor: f64) -> Vec<Vec<T>> {
+        // leave the samples untouched at full volume, so DoP and bit-perfect output survive
+        if factor == 1.0 {
+            return self;
+        }
+
         self.iter()
             .map(|v| {
                 v.iter()
@@ -74,6 +79,10 @@ where
 
 impl Scale for Vec<Vec<f64>> {
     fn scale(self, factor: f64) -> Vec<Vec<f64>> {
+        if factor == 1.0 {
+            return self;
+        }
+
         self.iter()
             .map(|v| v.iter().map(|v| v * factor).collect())
             .collect()
@@ -1,3 +1,7 @@
 pub mod db;
+pub mod exclude;
 pub mod scan;
+pub mod search;
+pub mod settings;
+pub mod smart_playlist;
 pub mod types;
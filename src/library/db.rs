@@ -1,14 +1,29 @@
-use std::{path::Path, sync::Arc, time::Duration};
+use std::{fmt, fs, io, path::Path, sync::Arc, time::Duration};
 
 use async_std::task;
+use chrono::{DateTime, Utc};
 use gpui::{AppContext, Global};
 use moka::future::Cache;
 use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
-use tracing::debug;
+use tracing::{debug, warn};
 
-use crate::ui::app::Pool;
+use crate::{
+    media::{
+        playlist::{read_playlist, write_playlist, PlaylistEntry, PlaylistFormat},
+        stream::is_stream_location,
+    },
+    playback::events::PlayRecord,
+    ui::app::Pool,
+};
 
-use super::types::{Album, Artist, Track};
+use super::{
+    search::fts_query,
+    smart_playlist::SmartPlaylistRules,
+    types::{
+        Album, Artist, PlayCount, PlayEvent, Playlist, PlaylistItem, ScanFailure, SearchResults,
+        SmartPlaylist, Station, Track, TrackChapter, TrackStats,
+    },
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AlbumMethod {
@@ -37,6 +52,21 @@ pub struct DbCache {
 
 impl Global for DbCache {}
 
+impl DbCache {
+    /// Removes albums and artists that were changed by the scanner, so that they're read from the
+    /// database the next time they're requested.
+    pub async fn invalidate(&self, albums: &[i64], artists: &[i64]) {
+        for album in albums {
+            self.album_cache.invalidate(album).await;
+        }
+
+        for artist in artists {
+            self.artist_name_cache.invalidate(artist).await;
+            self.artist_cache.invalidate(artist).await;
+        }
+    }
+}
+
 pub fn create_cache() -> DbCache {
     let artist_name_cache = Cache::builder()
         .time_to_live(Duration::from_secs(60 * 5))
@@ -58,6 +88,9 @@ pub fn create_cache() -> DbCache {
     }
 }
 
+/// The maximum number of artists, albums and tracks returned by a search.
+const SEARCH_LIMIT: i64 = 100;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AlbumSortMethod {
     TitleAsc,
@@ -100,6 +133,665 @@ pub async fn list_tracks_in_album(
     Ok(albums)
 }
 
+pub async fn list_chapters_in_track(
+    pool: &SqlitePool,
+    track_id: i64,
+) -> Result<Arc<Vec<TrackChapter>>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_chapters_in_track.sql");
+
+    let chapters = Arc::new(
+        sqlx::query_as::<_, TrackChapter>(query)
+            .bind(track_id)
+            .fetch_all(pool)
+            .await?,
+    );
+
+    Ok(chapters)
+}
+
+pub async fn list_stations(pool: &SqlitePool) -> Result<Arc<Vec<Station>>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_stations.sql");
+
+    let stations = Arc::new(sqlx::query_as::<_, Station>(query).fetch_all(pool).await?);
+
+    Ok(stations)
+}
+
+/// Lists the files that failed to scan, most recent failure first.
+pub async fn list_scan_failures(pool: &SqlitePool) -> Result<Vec<ScanFailure>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_scan_errors.sql");
+
+    sqlx::query_as::<_, ScanFailure>(query)
+        .fetch_all(pool)
+        .await
+}
+
+/// Saves a station, returning its ID. If a station with the same URL already exists, it is
+/// renamed instead.
+pub async fn add_station(pool: &SqlitePool, name: &str, url: &str) -> Result<i64, sqlx::Error> {
+    let query = include_str!("../../queries/library/create_station.sql");
+
+    let (id,): (i64,) = sqlx::query_as(query)
+        .bind(name)
+        .bind(url)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(id)
+}
+
+pub async fn remove_station(pool: &SqlitePool, station_id: i64) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/delete_station.sql");
+
+    sqlx::query(query).bind(station_id).execute(pool).await?;
+
+    Ok(())
+}
+
+/// Saves every station listed in a station file (PLS or M3U), returning the IDs of the saved
+/// stations. Stations without a title are named after their URL.
+pub async fn import_stations(pool: &SqlitePool, location: &str) -> Result<Vec<i64>, sqlx::Error> {
+    let Some(entries) = read_playlist(location) else {
+        warn!("Could not read station file {}", location);
+        return Ok(Vec::new());
+    };
+
+    let mut ids = Vec::new();
+
+    for entry in entries {
+        let name = entry.title.as_deref().unwrap_or(&entry.location);
+        ids.push(add_station(pool, name, &entry.location).await?);
+    }
+
+    Ok(ids)
+}
+
+pub async fn list_playlists(pool: &SqlitePool) -> Result<Arc<Vec<Playlist>>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_playlists.sql");
+
+    let playlists = Arc::new(sqlx::query_as::<_, Playlist>(query).fetch_all(pool).await?);
+
+    Ok(playlists)
+}
+
+/// Lists the items in a playlist, in order. Items that are in the library are listed at the
+/// track's current location, so that playlists follow tracks that have been moved.
+pub async fn list_playlist_items(
+    pool: &SqlitePool,
+    playlist_id: i64,
+) -> Result<Arc<Vec<PlaylistItem>>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_playlist_items.sql");
+
+    let items = Arc::new(
+        sqlx::query_as::<_, PlaylistItem>(query)
+            .bind(playlist_id)
+            .fetch_all(pool)
+            .await?,
+    );
+
+    Ok(items)
+}
+
+pub async fn create_playlist(pool: &SqlitePool, name: &str) -> Result<i64, sqlx::Error> {
+    let query = include_str!("../../queries/library/create_playlist.sql");
+
+    let (id,): (i64,) = sqlx::query_as(query).bind(name).fetch_one(pool).await?;
+
+    Ok(id)
+}
+
+pub async fn rename_playlist(
+    pool: &SqlitePool,
+    playlist_id: i64,
+    name: &str,
+) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/rename_playlist.sql");
+
+    sqlx::query(query)
+        .bind(playlist_id)
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes a playlist and all of its items.
+pub async fn delete_playlist(pool: &SqlitePool, playlist_id: i64) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/delete_playlist.sql");
+
+    sqlx::query(query).bind(playlist_id).execute(pool).await?;
+
+    Ok(())
+}
+
+/// Adds the locations to the end of a playlist, returning the IDs of the new items. Locations
+/// that belong to library tracks are linked to them; other locations (streams and files outside
+/// of the library) are saved as-is.
+pub async fn append_to_playlist(
+    pool: &SqlitePool,
+    playlist_id: i64,
+    locations: &[String],
+) -> Result<Vec<i64>, sqlx::Error> {
+    let query = include_str!("../../queries/library/create_playlist_item.sql");
+    let touch = include_str!("../../queries/library/touch_playlist.sql");
+
+    let mut tx = pool.begin().await?;
+    let mut ids = Vec::with_capacity(locations.len());
+
+    for location in locations {
+        let (id,): (i64,) = sqlx::query_as(query)
+            .bind(playlist_id)
+            .bind(location)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        ids.push(id);
+    }
+
+    sqlx::query(touch)
+        .bind(playlist_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(ids)
+}
+
+/// Moves the item at position `from` to position `to`, shifting the items in between. Positions
+/// past the end of the playlist move the item to the end.
+pub async fn move_playlist_item(
+    pool: &SqlitePool,
+    playlist_id: i64,
+    from: i64,
+    to: i64,
+) -> Result<(), sqlx::Error> {
+    let count = include_str!("../../queries/library/count_playlist_items.sql");
+    let query = include_str!("../../queries/library/move_playlist_item.sql");
+    let touch = include_str!("../../queries/library/touch_playlist.sql");
+
+    let mut tx = pool.begin().await?;
+
+    let (len,): (i64,) = sqlx::query_as(count)
+        .bind(playlist_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let to = to.clamp(0, (len - 1).max(0));
+
+    if from == to || !(0..len).contains(&from) {
+        return Ok(());
+    }
+
+    sqlx::query(query)
+        .bind(playlist_id)
+        .bind(from)
+        .bind(to)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(touch)
+        .bind(playlist_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Removes a single item from its playlist. Other items with the same track are kept.
+pub async fn remove_playlist_item(pool: &SqlitePool, item_id: i64) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/delete_playlist_item.sql");
+    let close_gap = include_str!("../../queries/library/close_playlist_gap.sql");
+    let touch = include_str!("../../queries/library/touch_playlist.sql");
+
+    let mut tx = pool.begin().await?;
+
+    let removed: Option<(i64, i64)> = sqlx::query_as(query)
+        .bind(item_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let Some((playlist_id, position)) = removed else {
+        return Ok(());
+    };
+
+    sqlx::query(close_gap)
+        .bind(playlist_id)
+        .bind(position)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(touch)
+        .bind(playlist_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum PlaylistFileError {
+    Database(sqlx::Error),
+    Io(io::Error),
+    /// The file isn't a playlist, or (when exporting) its extension isn't M3U, M3U8, PLS or XSPF.
+    UnsupportedFormat,
+}
+
+impl From<sqlx::Error> for PlaylistFileError {
+    fn from(value: sqlx::Error) -> Self {
+        PlaylistFileError::Database(value)
+    }
+}
+
+impl From<io::Error> for PlaylistFileError {
+    fn from(value: io::Error) -> Self {
+        PlaylistFileError::Io(value)
+    }
+}
+
+impl fmt::Display for PlaylistFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaylistFileError::Database(e) => write!(f, "database error: {}", e),
+            PlaylistFileError::Io(e) => write!(f, "could not access file: {}", e),
+            PlaylistFileError::UnsupportedFormat => write!(f, "unsupported playlist format"),
+        }
+    }
+}
+
+/// The outcome of importing a playlist file.
+#[derive(Debug, Clone)]
+pub struct PlaylistImport {
+    pub playlist_id: i64,
+    /// Entries that aren't in the library and couldn't be found on disk. These are left out of
+    /// the playlist.
+    pub unresolved: Vec<String>,
+}
+
+/// Resolves an entry from a playlist file to a location that can be played, or None if the entry
+/// can't be found. Existing files are canonicalized so that they match the locations of library
+/// tracks; missing files are still accepted if the library knows about them (e.g. because they
+/// are in a library folder that is currently offline).
+async fn resolve_playlist_entry(
+    pool: &SqlitePool,
+    entry: &PlaylistEntry,
+) -> Result<Option<String>, sqlx::Error> {
+    if is_stream_location(&entry.location) {
+        return Ok(Some(entry.location.clone()));
+    }
+
+    if let Ok(path) = fs::canonicalize(&entry.location) {
+        return Ok(Some(path.to_string_lossy().to_string()));
+    }
+
+    let query = include_str!("../../queries/library/find_track_id_by_location.sql");
+
+    let track: Option<(i64,)> = sqlx::query_as(query)
+        .bind(&entry.location)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(track.map(|_| entry.location.clone()))
+}
+
+/// Creates a playlist from a playlist file (M3U, M3U8, PLS or XSPF). Relative paths are resolved
+/// against the folder the file is in. If no name is given, the playlist is named after the file.
+pub async fn import_playlist(
+    pool: &SqlitePool,
+    location: &str,
+    name: Option<&str>,
+) -> Result<PlaylistImport, PlaylistFileError> {
+    let Some(entries) = read_playlist(location) else {
+        warn!("Could not read playlist file {}", location);
+        return Err(PlaylistFileError::UnsupportedFormat);
+    };
+
+    let mut locations = Vec::with_capacity(entries.len());
+    let mut unresolved = Vec::new();
+
+    for entry in entries {
+        match resolve_playlist_entry(pool, &entry).await? {
+            Some(location) => locations.push(location),
+            None => unresolved.push(entry.location),
+        }
+    }
+
+    if !unresolved.is_empty() {
+        warn!(
+            "{} entries in playlist file {} could not be found",
+            unresolved.len(),
+            location
+        );
+    }
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => Path::new(location)
+            .file_stem()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_else(|| location.to_string()),
+    };
+
+    let playlist_id = create_playlist(pool, &name).await?;
+    append_to_playlist(pool, playlist_id, &locations).await?;
+
+    Ok(PlaylistImport {
+        playlist_id,
+        unresolved,
+    })
+}
+
+/// Writes a playlist to a file. The format is picked from the file's extension, and paths are
+/// written relative to the file's folder where possible.
+pub async fn export_playlist(
+    pool: &SqlitePool,
+    playlist_id: i64,
+    path: &Path,
+) -> Result<(), PlaylistFileError> {
+    let format = PlaylistFormat::from_path(path).ok_or(PlaylistFileError::UnsupportedFormat)?;
+
+    let items = list_playlist_items(pool, playlist_id).await?;
+    let entries: Vec<PlaylistEntry> = items
+        .iter()
+        .map(|item| PlaylistEntry {
+            location: item.location.clone(),
+            title: item.title.as_ref().map(|v| v.0.to_string()),
+            duration: item.duration.map(|v| v.max(0) as u64),
+        })
+        .collect();
+
+    // relative paths only make sense against the real location of the folder
+    let base = path
+        .parent()
+        .filter(|v| !v.as_os_str().is_empty())
+        .map(|v| fs::canonicalize(v).unwrap_or_else(|_| v.to_path_buf()))
+        .or_else(|| std::env::current_dir().ok());
+
+    fs::write(path, write_playlist(&entries, format, base.as_deref()))?;
+
+    Ok(())
+}
+
+pub async fn list_smart_playlists(
+    pool: &SqlitePool,
+) -> Result<Arc<Vec<SmartPlaylist>>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_smart_playlists.sql");
+
+    let playlists = Arc::new(
+        sqlx::query_as::<_, SmartPlaylist>(query)
+            .fetch_all(pool)
+            .await?,
+    );
+
+    Ok(playlists)
+}
+
+pub async fn create_smart_playlist(
+    pool: &SqlitePool,
+    name: &str,
+    rules: &SmartPlaylistRules,
+) -> Result<i64, sqlx::Error> {
+    let query = include_str!("../../queries/library/create_smart_playlist.sql");
+    let rules = serde_json::to_string(rules).expect("could not serialize rules");
+
+    let (id,): (i64,) = sqlx::query_as(query)
+        .bind(name)
+        .bind(rules)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(id)
+}
+
+/// Renames a smart playlist and replaces its rules.
+pub async fn update_smart_playlist(
+    pool: &SqlitePool,
+    playlist_id: i64,
+    name: &str,
+    rules: &SmartPlaylistRules,
+) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/update_smart_playlist.sql");
+    let rules = serde_json::to_string(rules).expect("could not serialize rules");
+
+    sqlx::query(query)
+        .bind(playlist_id)
+        .bind(name)
+        .bind(rules)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_smart_playlist(pool: &SqlitePool, playlist_id: i64) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/delete_smart_playlist.sql");
+
+    sqlx::query(query).bind(playlist_id).execute(pool).await?;
+
+    Ok(())
+}
+
+/// Lists the tracks matching a set of rules, e.g. to preview a smart playlist before saving it.
+pub async fn evaluate_rules(
+    pool: &SqlitePool,
+    rules: &SmartPlaylistRules,
+) -> Result<Vec<Track>, sqlx::Error> {
+    rules
+        .query()
+        .build_query_as::<Track>()
+        .fetch_all(pool)
+        .await
+}
+
+/// Lists the tracks currently in a smart playlist. The rules are evaluated on every call, so views
+/// should call this again when the library changes (see `ScanEvent::LibraryChanged`).
+pub async fn list_tracks_in_smart_playlist(
+    pool: &SqlitePool,
+    playlist_id: i64,
+) -> Result<Arc<Vec<Track>>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_smart_playlist_by_id.sql");
+
+    let playlist = sqlx::query_as::<_, SmartPlaylist>(query)
+        .bind(playlist_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(Arc::new(evaluate_rules(pool, &playlist.rules).await?))
+}
+
+/// Rates a track from 1 to 5. A rating of None (or 0) clears the rating.
+pub async fn set_track_rating(
+    pool: &SqlitePool,
+    track_id: i64,
+    rating: Option<u8>,
+) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/update_track_rating.sql");
+    let rating = rating.filter(|v| *v > 0).map(|v| v.min(5) as i64);
+
+    sqlx::query(query)
+        .bind(track_id)
+        .bind(rating)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Searches the titles, artists, genres, labels and years of the library. Terms can be limited to
+/// one field (`artist:coltrane`, `year:1959`, `genre:jazz`), and quoted to match whole phrases;
+/// other terms match any word they're the start of. Accents and case are ignored.
+pub async fn search(pool: &SqlitePool, query: &str) -> Result<SearchResults, sqlx::Error> {
+    let Some(query) = fts_query(query) else {
+        return Ok(SearchResults::default());
+    };
+
+    let artists = include_str!("../../queries/library/search_artists.sql");
+    let albums = include_str!("../../queries/library/search_albums.sql");
+    let tracks = include_str!("../../queries/library/search_tracks.sql");
+
+    let artists: Vec<(i64,)> = sqlx::query_as(artists)
+        .bind(&query)
+        .bind(SEARCH_LIMIT)
+        .fetch_all(pool)
+        .await?;
+
+    let albums: Vec<(i64,)> = sqlx::query_as(albums)
+        .bind(&query)
+        .bind(SEARCH_LIMIT)
+        .fetch_all(pool)
+        .await?;
+
+    let tracks = sqlx::query_as::<_, Track>(tracks)
+        .bind(&query)
+        .bind(SEARCH_LIMIT)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(SearchResults {
+        artists: artists.into_iter().map(|(id,)| id).collect(),
+        albums: albums.into_iter().map(|(id,)| id).collect(),
+        tracks,
+    })
+}
+
+/// Adds a play to the play history, returning its ID. Plays of library tracks are linked to the
+/// track.
+pub async fn record_play(pool: &SqlitePool, record: &PlayRecord) -> Result<i64, sqlx::Error> {
+    let query = include_str!("../../queries/library/create_play_event.sql");
+    let (source, source_id) = record.source.map(|v| v.to_db()).unzip();
+
+    let (id,): (i64,) = sqlx::query_as(query)
+        .bind(&record.location)
+        .bind(record.started_at)
+        .bind(record.listened as i64)
+        .bind(record.skipped)
+        .bind(source)
+        .bind(source_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(id)
+}
+
+/// Lists the most recent plays, newest first.
+pub async fn list_play_history(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<PlayEvent>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_play_events.sql");
+
+    sqlx::query_as::<_, PlayEvent>(query)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_track_stats(pool: &SqlitePool, track_id: i64) -> Result<TrackStats, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_track_stats.sql");
+
+    sqlx::query_as::<_, TrackStats>(query)
+        .bind(track_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Lists the most played album artists since the given time (or of all time), most played first.
+pub async fn list_top_artists(
+    pool: &SqlitePool,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<PlayCount>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_top_artists.sql");
+
+    sqlx::query_as::<_, PlayCount>(query)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Lists the most played albums since the given time (or of all time), most played first.
+pub async fn list_top_albums(
+    pool: &SqlitePool,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<PlayCount>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_top_albums.sql");
+
+    sqlx::query_as::<_, PlayCount>(query)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Lists the most played tracks since the given time (or of all time), most played first.
+pub async fn list_top_tracks(
+    pool: &SqlitePool,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<PlayCount>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_top_tracks.sql");
+
+    sqlx::query_as::<_, PlayCount>(query)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns how long has been spent listening since the given time (or of all time), in seconds.
+/// Unlike play counts, this includes skipped plays and streams.
+pub async fn get_listening_time(
+    pool: &SqlitePool,
+    since: Option<DateTime<Utc>>,
+) -> Result<i64, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_listening_time.sql");
+
+    let (listened,): (i64,) = sqlx::query_as(query).bind(since).fetch_one(pool).await?;
+
+    Ok(listened)
+}
+
+/// Looks up a library track by its location, returning its ID and where playback was left off if
+/// it's an audiobook. Returns None for tracks that aren't audiobooks.
+pub async fn find_audiobook(
+    pool: &SqlitePool,
+    location: &str,
+) -> Result<Option<(i64, Option<u64>)>, sqlx::Error> {
+    let query = include_str!("../../queries/library/find_audiobook_by_location.sql");
+
+    let row: Option<(i64, Option<i64>)> = sqlx::query_as(query)
+        .bind(location)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|(id, position)| (id, position.map(|v| v as u64))))
+}
+
+pub async fn update_resume_position(
+    pool: &SqlitePool,
+    track_id: i64,
+    position: u64,
+) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/update_resume_position.sql");
+
+    sqlx::query(query)
+        .bind(track_id)
+        .bind(position as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_resume_position(pool: &SqlitePool, track_id: i64) -> Result<(), sqlx::Error> {
+    let query = include_str!("../../queries/library/delete_resume_position.sql");
+
+    sqlx::query(query).bind(track_id).execute(pool).await?;
+
+    Ok(())
+}
+
 pub async fn get_album_by_id(
     pool: &SqlitePool,
     db_cache: &DbCache,
@@ -190,6 +882,7 @@ pub async fn get_artist_by_id(
 pub trait LibraryAccess {
     fn list_albums(&self, sort_method: AlbumSortMethod) -> Result<Vec<(u32, String)>, sqlx::Error>;
     fn list_tracks_in_album(&self, album_id: i64) -> Result<Arc<Vec<Track>>, sqlx::Error>;
+    fn list_chapters_in_track(&self, track_id: i64) -> Result<Arc<Vec<TrackChapter>>, sqlx::Error>;
     fn get_album_by_id(
         &self,
         album_id: i64,
@@ -197,6 +890,66 @@ pub trait LibraryAccess {
     ) -> Result<Arc<Album>, sqlx::Error>;
     fn get_artist_name_by_id(&self, artist_id: i64) -> Result<Arc<String>, sqlx::Error>;
     fn get_artist_by_id(&self, artist_id: i64) -> Result<Arc<Artist>, sqlx::Error>;
+    fn list_stations(&self) -> Result<Arc<Vec<Station>>, sqlx::Error>;
+    fn add_station(&self, name: &str, url: &str) -> Result<i64, sqlx::Error>;
+    fn remove_station(&self, station_id: i64) -> Result<(), sqlx::Error>;
+    fn import_stations(&self, location: &str) -> Result<Vec<i64>, sqlx::Error>;
+    fn list_playlists(&self) -> Result<Arc<Vec<Playlist>>, sqlx::Error>;
+    fn list_playlist_items(&self, playlist_id: i64) -> Result<Arc<Vec<PlaylistItem>>, sqlx::Error>;
+    fn create_playlist(&self, name: &str) -> Result<i64, sqlx::Error>;
+    fn rename_playlist(&self, playlist_id: i64, name: &str) -> Result<(), sqlx::Error>;
+    fn delete_playlist(&self, playlist_id: i64) -> Result<(), sqlx::Error>;
+    fn append_to_playlist(
+        &self,
+        playlist_id: i64,
+        locations: &[String],
+    ) -> Result<Vec<i64>, sqlx::Error>;
+    fn move_playlist_item(&self, playlist_id: i64, from: i64, to: i64) -> Result<(), sqlx::Error>;
+    fn remove_playlist_item(&self, item_id: i64) -> Result<(), sqlx::Error>;
+    fn import_playlist(
+        &self,
+        location: &str,
+        name: Option<&str>,
+    ) -> Result<PlaylistImport, PlaylistFileError>;
+    fn export_playlist(&self, playlist_id: i64, path: &Path) -> Result<(), PlaylistFileError>;
+    fn list_smart_playlists(&self) -> Result<Arc<Vec<SmartPlaylist>>, sqlx::Error>;
+    fn create_smart_playlist(
+        &self,
+        name: &str,
+        rules: &SmartPlaylistRules,
+    ) -> Result<i64, sqlx::Error>;
+    fn update_smart_playlist(
+        &self,
+        playlist_id: i64,
+        name: &str,
+        rules: &SmartPlaylistRules,
+    ) -> Result<(), sqlx::Error>;
+    fn delete_smart_playlist(&self, playlist_id: i64) -> Result<(), sqlx::Error>;
+    fn evaluate_rules(&self, rules: &SmartPlaylistRules) -> Result<Vec<Track>, sqlx::Error>;
+    fn list_tracks_in_smart_playlist(
+        &self,
+        playlist_id: i64,
+    ) -> Result<Arc<Vec<Track>>, sqlx::Error>;
+    fn set_track_rating(&self, track_id: i64, rating: Option<u8>) -> Result<(), sqlx::Error>;
+    fn search(&self, query: &str) -> Result<SearchResults, sqlx::Error>;
+    fn list_play_history(&self, limit: i64) -> Result<Vec<PlayEvent>, sqlx::Error>;
+    fn get_track_stats(&self, track_id: i64) -> Result<TrackStats, sqlx::Error>;
+    fn list_top_artists(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<PlayCount>, sqlx::Error>;
+    fn list_top_albums(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<PlayCount>, sqlx::Error>;
+    fn list_top_tracks(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<PlayCount>, sqlx::Error>;
+    fn get_listening_time(&self, since: Option<DateTime<Utc>>) -> Result<i64, sqlx::Error>;
 }
 
 // TODO: profile this with a large library
@@ -211,6 +964,11 @@ impl LibraryAccess for AppContext {
         task::block_on(list_tracks_in_album(&pool.0, album_id))
     }
 
+    fn list_chapters_in_track(&self, track_id: i64) -> Result<Arc<Vec<TrackChapter>>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_chapters_in_track(&pool.0, track_id))
+    }
+
     fn get_album_by_id(
         &self,
         album_id: i64,
@@ -232,4 +990,176 @@ impl LibraryAccess for AppContext {
         let db_cache: &DbCache = self.global();
         task::block_on(get_artist_by_id(&pool.0, db_cache, artist_id))
     }
+
+    fn list_stations(&self) -> Result<Arc<Vec<Station>>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_stations(&pool.0))
+    }
+
+    fn add_station(&self, name: &str, url: &str) -> Result<i64, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(add_station(&pool.0, name, url))
+    }
+
+    fn remove_station(&self, station_id: i64) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(remove_station(&pool.0, station_id))
+    }
+
+    fn import_stations(&self, location: &str) -> Result<Vec<i64>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(import_stations(&pool.0, location))
+    }
+
+    fn list_playlists(&self) -> Result<Arc<Vec<Playlist>>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_playlists(&pool.0))
+    }
+
+    fn list_playlist_items(&self, playlist_id: i64) -> Result<Arc<Vec<PlaylistItem>>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_playlist_items(&pool.0, playlist_id))
+    }
+
+    fn create_playlist(&self, name: &str) -> Result<i64, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(create_playlist(&pool.0, name))
+    }
+
+    fn rename_playlist(&self, playlist_id: i64, name: &str) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(rename_playlist(&pool.0, playlist_id, name))
+    }
+
+    fn delete_playlist(&self, playlist_id: i64) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(delete_playlist(&pool.0, playlist_id))
+    }
+
+    fn append_to_playlist(
+        &self,
+        playlist_id: i64,
+        locations: &[String],
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(append_to_playlist(&pool.0, playlist_id, locations))
+    }
+
+    fn move_playlist_item(&self, playlist_id: i64, from: i64, to: i64) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(move_playlist_item(&pool.0, playlist_id, from, to))
+    }
+
+    fn remove_playlist_item(&self, item_id: i64) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(remove_playlist_item(&pool.0, item_id))
+    }
+
+    fn import_playlist(
+        &self,
+        location: &str,
+        name: Option<&str>,
+    ) -> Result<PlaylistImport, PlaylistFileError> {
+        let pool: &Pool = self.global();
+        task::block_on(import_playlist(&pool.0, location, name))
+    }
+
+    fn export_playlist(&self, playlist_id: i64, path: &Path) -> Result<(), PlaylistFileError> {
+        let pool: &Pool = self.global();
+        task::block_on(export_playlist(&pool.0, playlist_id, path))
+    }
+
+    fn list_smart_playlists(&self) -> Result<Arc<Vec<SmartPlaylist>>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_smart_playlists(&pool.0))
+    }
+
+    fn create_smart_playlist(
+        &self,
+        name: &str,
+        rules: &SmartPlaylistRules,
+    ) -> Result<i64, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(create_smart_playlist(&pool.0, name, rules))
+    }
+
+    fn update_smart_playlist(
+        &self,
+        playlist_id: i64,
+        name: &str,
+        rules: &SmartPlaylistRules,
+    ) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(update_smart_playlist(&pool.0, playlist_id, name, rules))
+    }
+
+    fn delete_smart_playlist(&self, playlist_id: i64) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(delete_smart_playlist(&pool.0, playlist_id))
+    }
+
+    fn evaluate_rules(&self, rules: &SmartPlaylistRules) -> Result<Vec<Track>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(evaluate_rules(&pool.0, rules))
+    }
+
+    fn list_tracks_in_smart_playlist(
+        &self,
+        playlist_id: i64,
+    ) -> Result<Arc<Vec<Track>>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_tracks_in_smart_playlist(&pool.0, playlist_id))
+    }
+
+    fn set_track_rating(&self, track_id: i64, rating: Option<u8>) -> Result<(), sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(set_track_rating(&pool.0, track_id, rating))
+    }
+
+    fn search(&self, query: &str) -> Result<SearchResults, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(search(&pool.0, query))
+    }
+
+    fn list_play_history(&self, limit: i64) -> Result<Vec<PlayEvent>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_play_history(&pool.0, limit))
+    }
+
+    fn get_track_stats(&self, track_id: i64) -> Result<TrackStats, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(get_track_stats(&pool.0, track_id))
+    }
+
+    fn list_top_artists(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<PlayCount>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_top_artists(&pool.0, since, limit))
+    }
+
+    fn list_top_albums(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<PlayCount>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_top_albums(&pool.0, since, limit))
+    }
+
+    fn list_top_tracks(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<PlayCount>, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(list_top_tracks(&pool.0, since, limit))
+    }
+
+    fn get_listening_time(&self, since: Option<DateTime<Utc>>) -> Result<i64, sqlx::Error> {
+        let pool: &Pool = self.global();
+        task::block_on(get_listening_time(&pool.0, since))
+    }
 }
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::{Regex, RegexSet};
+use tracing::warn;
+
+/// Files that exclude the directory containing them, and everything below it, from the library.
+const MARKER_FILES: &[&str] = &[".nomedia", ".ignore"];
+
+/// Returns true if the directory contains one of the marker files.
+pub fn has_marker_file(directory: &Path) -> bool {
+    MARKER_FILES
+        .iter()
+        .any(|marker| directory.join(marker).exists())
+}
+
+/// The exclude patterns from the scan settings. Patterns are matched against the whole path, and
+/// `*` in a glob also matches `/`, so `*.m4b` excludes every M4B file. Patterns that can't be
+/// compiled are skipped with a warning rather than failing the scan.
+pub struct ExcludeRules {
+    globs: GlobSet,
+    regexes: RegexSet,
+}
+
+impl ExcludeRules {
+    pub fn new(globs: &[String], regexes: &[String]) -> Self {
+        let mut builder = GlobSetBuilder::new();
+
+        for pattern in globs {
+            match Glob::new(pattern) {
+                Ok(glob) => {
+                    builder.add(glob);
+                }
+                Err(e) => warn!("Ignoring invalid exclude glob {:?}: {}", pattern, e),
+            }
+        }
+
+        let valid = regexes.iter().filter(|pattern| match Regex::new(pattern) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Ignoring invalid exclude regex {:?}: {}", pattern, e);
+                false
+            }
+        });
+
+        ExcludeRules {
+            globs: builder.build().unwrap_or_else(|e| {
+                warn!("Could not build exclude globs: {}", e);
+                GlobSet::empty()
+            }),
+            regexes: RegexSet::new(valid).unwrap_or_else(|e| {
+                warn!("Could not build exclude regexes: {}", e);
+                RegexSet::empty()
+            }),
+        }
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.globs.is_match(path) || path.to_str().is_some_and(|v| self.regexes.is_match(v))
+    }
+}
+
+impl Default for ExcludeRules {
+    fn default() -> Self {
+        ExcludeRules {
+            globs: GlobSet::empty(),
+            regexes: RegexSet::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn rules(globs: &[&str], regexes: &[&str]) -> ExcludeRules {
+        let globs: Vec<String> = globs.iter().map(|v| v.to_string()).collect();
+        let regexes: Vec<String> = regexes.iter().map(|v| v.to_string()).collect();
+
+        ExcludeRules::new(&globs, &regexes)
+    }
+
+    #[test]
+    fn globs_match_the_whole_path() {
+        let rules = rules(&["*.m4b", "**/Podcasts/**"], &[]);
+
+        assert!(rules.is_excluded(Path::new("/music/Books/book.m4b")));
+        assert!(rules.is_excluded(Path::new("/music/Podcasts/show/episode.mp3")));
+        assert!(!rules.is_excluded(Path::new("/music/Album/track.flac")));
+        assert!(!rules.is_excluded(Path::new("/music/Podcasts")));
+    }
+
+    #[test]
+    fn regexes_match_anywhere_in_the_path() {
+        let rules = rules(&[], &[r"(?i)/demos?/", r"\.tmp$"]);
+
+        assert!(rules.is_excluded(Path::new("/music/Artist/Demos/track.flac")));
+        assert!(rules.is_excluded(Path::new("/music/Album/track.flac.tmp")));
+        assert!(!rules.is_excluded(Path::new("/music/Demolition/track.flac")));
+    }
+
+    #[test]
+    fn skips_invalid_patterns() {
+        let rules = rules(&["[", "*.m4b"], &["(", r"\.tmp$"]);
+
+        assert!(rules.is_excluded(Path::new("/music/book.m4b")));
+        assert!(rules.is_excluded(Path::new("/music/track.tmp")));
+        assert!(!rules.is_excluded(Path::new("/music/track.flac")));
+    }
+
+    #[test]
+    fn nothing_is_excluded_by_default() {
+        assert!(!ExcludeRules::default().is_excluded(Path::new("/music/track.flac")));
+    }
+
+    #[test]
+    fn finds_marker_files() {
+        let dir = std::env::temp_dir().join(format!("muzak-exclude-test-{}", std::process::id()));
+
+        for marker in MARKER_FILES {
+            let excluded = dir.join(marker.trim_start_matches('.'));
+            fs::create_dir_all(&excluded).unwrap();
+            fs::write(excluded.join(marker), b"").unwrap();
+
+            assert!(has_marker_file(&excluded));
+        }
+
+        let included = dir.join("included");
+        fs::create_dir_all(&included).unwrap();
+        fs::write(included.join("nomedia"), b"").unwrap();
+
+        assert!(!has_marker_file(&included));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
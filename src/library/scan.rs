@@ -1,40 +1,138 @@
 use std::{
-    fs::{self, File},
-    io::{BufReader, Cursor, Write},
+    fmt, fs,
     path::{Path, PathBuf},
-    sync::mpsc,
-    time::{Duration, SystemTime},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::available_parallelism,
+    time::{Duration, Instant},
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use async_std::task;
-use gpui::{AppContext, Global};
-use image::imageops::thumbnail;
+use chrono::{DateTime, Utc};
+use discovery::file_is_scannable_with_provider;
+use file_state::{content_hash, FileKind, FileState};
+use gpui::{AppContext, EventEmitter, Global};
+use roots::{location_exists, resolve_root, retrieve_base_paths, LibraryWatcher};
 use sqlx::SqlitePool;
 use tracing::{debug, error, info, warn};
 
 use crate::{
     media::{
-        builtin::symphonia::SymphoniaProvider,
-        metadata::Metadata,
+        archive::{split_archive_location, ArchiveCache},
+        builtin::{dsd::DsdProvider, symphonia::SymphoniaProvider},
+        errors::{CloseError, MetadataError, OpenError, PlaybackStartError, TrackDurationError},
+        metadata::{Chapter, EmbeddedImage, Metadata, StreamInfo},
+        source::OpenedSource,
         traits::{MediaPlugin, MediaProvider},
     },
     ui::models::Models,
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+use super::{
+    db::DbCache,
+    exclude::ExcludeRules,
+    settings::{load_scan_settings, ScanSettings},
+};
+
+mod database;
+mod discovery;
+mod file_state;
+mod roots;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum ScanEvent {
     Cleaning,
     DiscoverProgress(u64),
-    ScanProgress { current: u64, total: u64 },
+    ScanProgress {
+        current: u64,
+        total: u64,
+    },
     ScanCompleteWatching,
     ScanCompleteIdle,
+    /// A file couldn't be added to the library. This doesn't change the state of the scan, so it's
+    /// emitted from the scan state model rather than stored in it.
+    FileFailed {
+        path: PathBuf,
+        error: ScanError,
+    },
+    /// Albums or artists were added, changed or removed. Like `FileFailed`, this is emitted rather
+    /// than stored.
+    LibraryChanged {
+        albums: Vec<i64>,
+        artists: Vec<i64>,
+    },
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// Why a file couldn't be added to the library. Each variant corresponds to the step of reading
+/// the file that failed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ScanError {
+    /// None of the providers support the file's extension.
+    NoProvider,
+    Open(OpenError),
+    Probe(PlaybackStartError),
+    Metadata(MetadataError),
+    Duration(TrackDurationError),
+    Close(CloseError),
+    /// The file was read, but couldn't be written to the library.
+    Database(String),
+}
+
+impl ScanError {
+    /// Returns the name of the step that failed, as stored in the `scan_error` table.
+    pub fn stage(&self) -> &'static str {
+        match self {
+            ScanError::NoProvider => "provider",
+            ScanError::Open(_) => "open",
+            ScanError::Probe(_) => "probe",
+            ScanError::Metadata(_) => "metadata",
+            ScanError::Duration(_) => "duration",
+            ScanError::Close(_) => "close",
+            ScanError::Database(_) => "database",
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::NoProvider => write!(f, "no provider supports this file"),
+            ScanError::Open(e) => e.fmt(f),
+            ScanError::Probe(e) => e.fmt(f),
+            ScanError::Metadata(e) => e.fmt(f),
+            ScanError::Duration(e) => e.fmt(f),
+            ScanError::Close(e) => e.fmt(f),
+            ScanError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 enum ScanCommand {
     Scan,
     Stop,
+    AddRoot(PathBuf),
+    RemoveRoot(PathBuf),
+}
+
+/// Why a directory couldn't be added to the library.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RootError {
+    NotFound,
+    NotADirectory,
+}
+
+impl fmt::Display for RootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RootError::NotFound => write!(f, "directory not found"),
+            RootError::NotADirectory => write!(f, "not a directory"),
+        }
+    }
 }
 
 pub struct ScanInterface {
@@ -65,6 +163,31 @@ impl ScanInterface {
             .expect("could not send tx");
     }
 
+    /// Adds a directory to the library and scans it. The list of directories is saved to the scan
+    /// settings.
+    pub fn add_root(&self, path: &Path) -> Result<(), RootError> {
+        let path = path.canonicalize().map_err(|_| RootError::NotFound)?;
+
+        if !path.is_dir() {
+            return Err(RootError::NotADirectory);
+        }
+
+        self.command_tx
+            .send(ScanCommand::AddRoot(path))
+            .expect("could not send tx");
+
+        Ok(())
+    }
+
+    /// Removes a directory, and every track inside of it, from the library.
+    pub fn remove_root(&self, path: &Path) {
+        let path = resolve_root(path.to_path_buf());
+
+        self.command_tx
+            .send(ScanCommand::RemoveRoot(path))
+            .expect("could not send tx");
+    }
+
     pub fn start_broadcast(&mut self, cx: &mut AppContext) {
         let mut events_rx = None;
         std::mem::swap(&mut self.events_rx, &mut events_rx);
@@ -76,9 +199,21 @@ impl ScanInterface {
                 loop {
                     while let Ok(event) = events_rx.try_recv() {
                         state_model
-                            .update(&mut cx, |m, cx| {
-                                *m = event;
-                                cx.notify()
+                            .update(&mut cx, |m, cx| match event {
+                                ScanEvent::FileFailed { .. } => cx.emit(event),
+                                ScanEvent::LibraryChanged {
+                                    ref albums,
+                                    ref artists,
+                                } => {
+                                    // cached entries have to be gone before anything reloads
+                                    let db_cache = cx.global::<DbCache>();
+                                    task::block_on(db_cache.invalidate(albums, artists));
+                                    cx.emit(event);
+                                }
+                                _ => {
+                                    *m = event;
+                                    cx.notify()
+                                }
                             })
                             .expect("failed to update scan state model");
                     }
@@ -95,6 +230,8 @@ impl ScanInterface {
 
 impl Global for ScanInterface {}
 
+impl EventEmitter<ScanEvent> for ScanEvent {}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ScanState {
     Idle,
@@ -112,59 +249,186 @@ pub struct ScanThread {
     discovered: Vec<PathBuf>,
     to_process: Vec<PathBuf>,
     scan_state: ScanState,
-    provider_table: Vec<(&'static [&'static str], Box<dyn MediaProvider>)>,
-    scan_record: AHashMap<PathBuf, u64>,
-    scan_record_path: Option<PathBuf>,
+    provider_table: ProviderTable,
+    workers: Option<ScanWorkers>,
+    /// A copy of the file states stored in the database, so that discovery doesn't have to
+    /// query the database for every file.
+    scan_record: AHashMap<PathBuf, FileState>,
+    /// The states of the art files and CUE sheets, which are kept apart from the files that are
+    /// read for tracks.
+    support_record: AHashMap<PathBuf, FileState>,
     scanned: u64,
     discovered_total: u64,
+    scan_started: Option<Instant>,
+    settings: ScanSettings,
+    settings_path: Option<PathBuf>,
+    exclude: ExcludeRules,
+    directory_art: AHashMap<PathBuf, PathBuf>,
+    /// The CUE sheets found in each directory during discovery.
+    directory_cue_sheets: AHashMap<PathBuf, Vec<PathBuf>>,
+    /// Directories whose files have to be scanned again even if they haven't changed, because a
+    /// CUE sheet in them was deleted.
+    rescan_directories: AHashSet<PathBuf>,
+    refreshed_albums: AHashSet<i64>,
+    art_cache: Option<(PathBuf, Box<[u8]>)>,
+    watcher: Option<LibraryWatcher>,
+    pending_changes: AHashSet<PathBuf>,
+    last_change: Option<Instant>,
+    changed_albums: AHashSet<i64>,
+    changed_artists: AHashSet<i64>,
+    offline_roots: AHashMap<PathBuf, DateTime<Utc>>,
+    last_root_check: Option<Instant>,
+    missing: AHashSet<PathBuf>,
 }
 
-fn build_provider_table() -> Vec<(&'static [&'static str], Box<dyn MediaProvider>)> {
+/// The worker threads reading files for the current scan.
+struct ScanWorkers {
+    queue: Arc<Mutex<Vec<PathBuf>>>,
+    cancelled: Arc<AtomicBool>,
+    results_rx: mpsc::Receiver<ScanResult>,
+    /// The archives being scanned, shared by the workers and kept until the scan is finished.
+    archives: Arc<ArchiveCache>,
+}
+
+impl ScanWorkers {
+    /// Stops the workers once they've finished the files they're currently reading. Returns the
+    /// files that were queued or read but not yet written to the database.
+    fn cancel(self) -> Vec<PathBuf> {
+        self.cancelled.store(true, Ordering::Relaxed);
+
+        let mut remaining = std::mem::take(&mut *self.queue.lock().unwrap());
+        remaining.extend(self.results_rx.try_iter().map(|result| result.path));
+
+        remaining
+    }
+}
+
+type ProviderTable = Vec<(&'static [&'static str], Box<dyn MediaProvider>)>;
+
+/// A file read by a worker, waiting to be written to the database.
+struct ScanResult {
+    path: PathBuf,
+    /// The file's contents hash, see `content_hash`.
+    hash: Option<[u8; 32]>,
+    file: Result<ScannedFile, ScanError>,
+}
+
+/// The largest number of files written to the database in a single transaction.
+const BATCH_SIZE: usize = 64;
+
+fn build_provider_table() -> ProviderTable {
     // TODO: dynamic plugin loading
-    vec![(
-        SymphoniaProvider::SUPPORTED_EXTENSIONS,
-        Box::new(SymphoniaProvider::default()),
-    )]
+    vec![
+        (
+            SymphoniaProvider::SUPPORTED_EXTENSIONS,
+            Box::new(SymphoniaProvider::default()),
+        ),
+        (
+            DsdProvider::SUPPORTED_EXTENSIONS,
+            Box::new(DsdProvider::default()),
+        ),
+    ]
 }
 
-fn retrieve_base_paths() -> Vec<PathBuf> {
-    // TODO: user-defined base paths
-    // TODO: we should also probably check if these directories exist
-    let system_music = directories::UserDirs::new()
-        .unwrap()
-        .audio_dir()
-        .unwrap()
-        .to_path_buf();
+/// Everything the scanner reads from a file before it's inserted into the library.
+struct ScannedFile {
+    metadata: Metadata,
+    duration: u64,
+    info: StreamInfo,
+    /// The file's extension, which identifies its container format.
+    container: Option<String>,
+    file_size: Option<u64>,
+    image: Option<Box<[u8]>>,
+    images: Vec<EmbeddedImage>,
+    chapters: Vec<Chapter>,
+}
 
-    vec![system_music]
+fn scan_file_with_provider(
+    path: &Path,
+    provider: &mut Box<dyn MediaProvider>,
+    archives: &ArchiveCache,
+) -> Result<ScannedFile, ScanError> {
+    let src = match path.to_str().and_then(split_archive_location) {
+        Some((archive, entry)) => archives.open_entry(archive, entry),
+        None => OpenedSource::file(path),
+    }
+    .map_err(ScanError::Open)?;
+    let container = src.ext.as_deref().map(|v| v.to_ascii_lowercase());
+    let file_size = src.source.byte_len();
+    provider
+        .open(src.source, src.ext)
+        .map_err(ScanError::Open)?;
+    let metadata = provider
+        .read_metadata()
+        .cloned()
+        .map_err(ScanError::Metadata)?;
+    let image = provider.read_image().map_err(ScanError::Metadata)?;
+    let images = provider.read_images().map_err(ScanError::Metadata)?;
+    let chapters = provider.chapters().map_err(ScanError::Metadata)?;
+    // everything is read from the container's headers, so no decoder is created
+    let info = provider.probe_info().map_err(ScanError::Probe)?;
+    let duration = info
+        .duration
+        .ok_or(ScanError::Duration(TrackDurationError::Unknown))?;
+    provider.close().map_err(ScanError::Close)?;
+    Ok(ScannedFile {
+        metadata,
+        duration,
+        info,
+        container,
+        file_size,
+        image,
+        images,
+        chapters,
+    })
 }
 
-fn file_is_scannable_with_provider(path: &PathBuf, exts: &&[&str]) -> bool {
-    for extension in exts.iter() {
-        if let Some(ext) = path.extension() {
-            if ext == *extension {
-                return true;
+/// Reads the file with every provider that supports it, until one succeeds. If none of them do,
+/// the error from the last provider is returned.
+fn read_metadata_for_path(
+    providers: &mut ProviderTable,
+    path: &PathBuf,
+    archives: &ArchiveCache,
+) -> Result<ScannedFile, ScanError> {
+    let mut error = ScanError::NoProvider;
+
+    for (exts, provider) in providers {
+        if file_is_scannable_with_provider(path, exts) {
+            match scan_file_with_provider(path, provider, archives) {
+                Ok(metadata) => return Ok(metadata),
+                Err(e) => error = e,
             }
         }
     }
 
-    false
+    Err(error)
 }
 
-// We don't care about the error message. If the file can't be scanned, we just ignore it.
-// TODO: it might be worth logging why the file couldn't be scanned (for plugin development)
-fn scan_file_with_provider(
-    path: &PathBuf,
-    provider: &mut Box<dyn MediaProvider>,
-) -> Result<(Metadata, u64, Option<Box<[u8]>>), ()> {
-    let src = std::fs::File::open(path).map_err(|_| ())?;
-    provider.open(src, None).map_err(|_| ())?;
-    provider.start_playback().map_err(|_| ())?;
-    let metadata = provider.read_metadata().cloned().map_err(|_| ())?;
-    let image = provider.read_image().map_err(|_| ())?;
-    let len = provider.duration_secs().map_err(|_| ())?;
-    provider.close().map_err(|_| ())?;
-    Ok((metadata, len, image))
+/// Reads files from the queue until it's empty or the scan is cancelled. Every worker has its own
+/// providers so that files can be read in parallel, but only the scan thread writes to the
+/// database.
+fn run_worker(
+    queue: Arc<Mutex<Vec<PathBuf>>>,
+    cancelled: Arc<AtomicBool>,
+    archives: Arc<ArchiveCache>,
+    results_tx: mpsc::SyncSender<ScanResult>,
+) {
+    let mut providers = build_provider_table();
+
+    while !cancelled.load(Ordering::Relaxed) {
+        let Some(path) = queue.lock().unwrap().pop() else {
+            break;
+        };
+
+        // hashed before reading, so that changes made in between are noticed by the next scan
+        let hash = content_hash(&path);
+        let file = read_metadata_for_path(&mut providers, &path, &archives);
+
+        // the scan thread drops the receiver when the scan is stopped
+        if results_tx.send(ScanResult { path, hash, file }).is_err() {
+            break;
+        }
+    }
 }
 
 impl ScanThread {
@@ -175,21 +439,7 @@ impl ScanThread {
         std::thread::Builder::new()
             .name("scanner".to_string())
             .spawn(move || {
-                let mut thread = ScanThread {
-                    event_tx: events_tx,
-                    command_rx: commands_rx,
-                    pool,
-                    visited: Vec::new(),
-                    discovered: Vec::new(),
-                    to_process: Vec::new(),
-                    scan_state: ScanState::Idle,
-                    provider_table: build_provider_table(),
-                    base_paths: retrieve_base_paths(),
-                    scan_record: AHashMap::new(),
-                    scan_record_path: None,
-                    scanned: 0,
-                    discovered_total: 0,
-                };
+                let mut thread = ScanThread::new(pool, events_tx, commands_rx);
 
                 thread.run();
             })
@@ -198,6 +448,46 @@ impl ScanThread {
         ScanInterface::new(Some(events_rx), commands_tx)
     }
 
+    fn new(
+        pool: SqlitePool,
+        event_tx: mpsc::Sender<ScanEvent>,
+        command_rx: mpsc::Receiver<ScanCommand>,
+    ) -> ScanThread {
+        ScanThread {
+            event_tx,
+            command_rx,
+            pool,
+            visited: Vec::new(),
+            discovered: Vec::new(),
+            to_process: Vec::new(),
+            scan_state: ScanState::Idle,
+            provider_table: build_provider_table(),
+            workers: None,
+            base_paths: Vec::new(),
+            scan_record: AHashMap::new(),
+            support_record: AHashMap::new(),
+            scanned: 0,
+            discovered_total: 0,
+            scan_started: None,
+            settings: ScanSettings::default(),
+            settings_path: None,
+            exclude: ExcludeRules::default(),
+            directory_art: AHashMap::new(),
+            directory_cue_sheets: AHashMap::new(),
+            rescan_directories: AHashSet::new(),
+            refreshed_albums: AHashSet::new(),
+            art_cache: None,
+            watcher: None,
+            pending_changes: AHashSet::new(),
+            last_change: None,
+            changed_albums: AHashSet::new(),
+            changed_artists: AHashSet::new(),
+            offline_roots: AHashMap::new(),
+            last_root_check: None,
+            missing: AHashSet::new(),
+        }
+    }
+
     fn run(&mut self) {
         let dirs = directories::ProjectDirs::from("me", "william341", "muzak")
             .expect("couldn't find project dirs");
@@ -205,37 +495,27 @@ impl ScanThread {
         if !directory.exists() {
             fs::create_dir(directory).expect("couldn't create data directory");
         }
-        let file_path = directory.join("scan_record.json");
+        let record_path = directory.join("scan_record.json");
 
-        if file_path.exists() {
-            let file = File::open(&file_path);
-
-            if let Ok(file) = file {
-                let reader = BufReader::new(file);
-
-                match serde_json::from_reader(reader) {
-                    Ok(scan_record) => {
-                        self.scan_record = scan_record;
-                    }
-                    Err(e) => {
-                        error!("could not read scan record: {:?}", e);
-                        error!("scanning will be slow until the scan record is rebuilt");
-                    }
-                }
-            }
+        if record_path.exists() {
+            task::block_on(self.import_scan_record(&record_path));
         }
 
-        self.scan_record_path = Some(file_path);
+        task::block_on(self.load_file_states());
+        task::block_on(self.forget_tracks_without_properties());
+        task::block_on(self.load_offline_roots());
+        let settings_path = directory.join("scan_settings.json");
+        self.settings = load_scan_settings(&settings_path);
+        self.settings_path = Some(settings_path);
+        self.base_paths = retrieve_base_paths(&self.settings);
+        self.exclude = ExcludeRules::new(&self.settings.exclude, &self.settings.exclude_regex);
 
         loop {
             self.read_commands();
 
-            // TODO: clear out old files if they've been deleted or moved
-            // TODO: connect to user interface to display progress
-            // TODO: start file watcher to update db automatically when files are added or removed
             match self.scan_state {
                 ScanState::Idle => {
-                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    self.watch();
                 }
                 ScanState::Cleanup => {
                     self.cleanup();
@@ -255,7 +535,9 @@ impl ScanThread {
             match command {
                 ScanCommand::Scan => {
                     if self.scan_state == ScanState::Idle {
-                        self.discovered = self.base_paths.clone();
+                        self.visited.clear();
+                        self.check_roots();
+                        self.discovered = self.available_roots();
                         self.scan_state = ScanState::Cleanup;
                         self.scanned = 0;
                         self.discovered_total = 0;
@@ -265,11 +547,21 @@ impl ScanThread {
                     }
                 }
                 ScanCommand::Stop => {
+                    // files that weren't written have to be read again next time
+                    if let Some(workers) = self.workers.take() {
+                        let remaining = workers.cancel();
+                        self.forget_paths(&remaining);
+                    }
+
                     self.scan_state = ScanState::Idle;
                     self.visited.clear();
                     self.discovered.clear();
                     self.to_process.clear();
+                    // these are found again by the next scan
+                    self.missing.clear();
                 }
+                ScanCommand::AddRoot(path) => self.add_root(path),
+                ScanCommand::RemoveRoot(path) => self.remove_root(&path),
             }
         }
 
@@ -282,324 +574,365 @@ impl ScanThread {
         }
     }
 
-    fn file_is_scannable(&mut self, path: &PathBuf) -> bool {
-        let timestamp = match fs::metadata(path) {
-            Ok(metadata) => metadata
-                .modified()
-                .unwrap()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            Err(_) => return false,
+    fn start_workers(&mut self) {
+        let count = match self.settings.scan_threads {
+            0 => available_parallelism().map(|v| v.get()).unwrap_or(1),
+            count => count,
         };
 
-        for (exts, _) in self.provider_table.iter() {
-            let x = file_is_scannable_with_provider(path, exts);
+        debug!("Starting {} scan workers", count);
 
-            if x {
-                if let Some(last_scan) = self.scan_record.get(path) {
-                    if *last_scan == timestamp {
-                        return false;
-                    }
-                }
+        let queue = Arc::new(Mutex::new(std::mem::take(&mut self.to_process)));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let archives = Arc::new(ArchiveCache::default());
+        // bounded, so that workers can't get too far ahead of the database
+        let (results_tx, results_rx) = mpsc::sync_channel(BATCH_SIZE * 2);
 
-                self.scan_record.insert(path.clone(), timestamp);
-                return true;
-            }
+        for i in 0..count {
+            let queue = queue.clone();
+            let cancelled = cancelled.clone();
+            let archives = archives.clone();
+            let results_tx = results_tx.clone();
+
+            std::thread::Builder::new()
+                .name(format!("scan-worker-{}", i))
+                .spawn(move || run_worker(queue, cancelled, archives, results_tx))
+                .expect("could not start scan worker");
         }
 
-        false
+        self.workers = Some(ScanWorkers {
+            queue,
+            cancelled,
+            results_rx,
+            archives,
+        });
     }
 
-    fn discover(&mut self) {
-        if self.discovered.is_empty() {
-            self.scan_state = ScanState::Scanning;
-            return;
+    fn finish_scan(&mut self) {
+        info!("Scan complete, writing scan record and stopping");
+        if let Some(started) = self.scan_started.take() {
+            let elapsed = started.elapsed().as_secs_f64();
+            info!(
+                "Scanned {} files in {:.2}s ({:.1} files/s)",
+                self.scanned,
+                elapsed,
+                self.scanned as f64 / elapsed.max(f64::EPSILON)
+            );
+        }
+        if let Some(workers) = self.workers.take() {
+            workers.archives.clear();
         }
 
-        let path = self.discovered.pop().unwrap();
-
-        if self.visited.contains(&path) {
+        // folders added or brought back online while the files were being scanned haven't been
+        // discovered yet, so the scan carries on with them instead of finishing
+        if !self.discovered.is_empty() {
+            info!("Discovering folders added during the scan");
+            self.scan_state = ScanState::Discovering;
             return;
         }
 
-        let paths = fs::read_dir(&path).unwrap();
+        self.delete_missing();
+        task::block_on(self.delete_orphans());
+        self.refreshed_albums.clear();
+        self.art_cache = None;
+        self.scan_state = ScanState::Idle;
+        self.send_library_changes();
 
-        for paths in paths {
-            // TODO: handle errors
-            // this might be slower than just reading the path directly but this prevents loops
-            let path = paths.unwrap().path().canonicalize().unwrap();
-            if path.is_dir() {
-                self.discovered.push(path);
-            } else if self.file_is_scannable(&path) {
-                self.to_process.push(path);
+        let event = if self.start_watching() {
+            ScanEvent::ScanCompleteWatching
+        } else {
+            ScanEvent::ScanCompleteIdle
+        };
 
-                self.discovered_total += 1;
+        self.send_event(event);
+    }
 
-                if self.discovered_total % 20 == 0 {
-                    self.event_tx
-                        .send(ScanEvent::DiscoverProgress(self.discovered_total))
-                        .expect("could not send discovered event");
-                }
-            }
+    fn send_library_changes(&mut self) {
+        if self.changed_albums.is_empty() && self.changed_artists.is_empty() {
+            return;
         }
 
-        self.visited.push(path.clone());
+        let event = ScanEvent::LibraryChanged {
+            albums: self.changed_albums.drain().collect(),
+            artists: self.changed_artists.drain().collect(),
+        };
+
+        self.send_event(event);
     }
 
-    async fn insert_artist(&self, metadata: &Metadata) -> Option<i64> {
-        let artist = metadata.album_artist.clone().or(metadata.artist.clone());
-
-        if let Some(artist) = artist {
-            let result: Result<(i64,), sqlx::Error> =
-                sqlx::query_as(include_str!("../../queries/scan/create_artist.sql"))
-                    .bind(&artist)
-                    .bind(&metadata.artist_sort.as_ref().unwrap_or(&artist))
-                    .fetch_one(&self.pool)
-                    .await;
-
-            match result {
-                Ok(v) => Some(v.0),
-                Err(sqlx::Error::RowNotFound) => {
-                    let result: Result<(i64,), sqlx::Error> =
-                        sqlx::query_as(include_str!("../../queries/scan/get_artist_id.sql"))
-                            .bind(&artist)
-                            .fetch_one(&self.pool)
-                            .await;
-
-                    match result {
-                        Ok(v) => Some(v.0),
-                        Err(e) => {
-                            error!("Database error while retriving artist: {:?}", e);
-                            None
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Database error while creating artist: {:?}", e);
-                    None
-                }
+    fn scan(&mut self) {
+        let Some(workers) = self.workers.as_ref() else {
+            self.start_workers();
+            return;
+        };
+
+        // wait briefly for the workers, so that stop commands are still handled promptly
+        let mut batch = match workers.results_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(result) => vec![result],
+            Err(RecvTimeoutError::Timeout) => return,
+            // every worker has exited, so there's nothing left to read
+            Err(RecvTimeoutError::Disconnected) => {
+                self.finish_scan();
+                return;
             }
-        } else {
-            None
-        }
-    }
+        };
 
-    async fn insert_album(
-        &self,
-        metadata: &Metadata,
-        artist_id: Option<i64>,
-        image: &Option<Box<[u8]>>,
-    ) -> Option<i64> {
-        if let Some(album) = &metadata.album {
-            let result: Result<(i64,), sqlx::Error> =
-                sqlx::query_as(include_str!("../../queries/scan/get_album_id.sql"))
-                    .bind(album)
-                    .fetch_one(&self.pool)
-                    .await;
-
-            match result {
-                Ok(v) => Some(v.0),
-                Err(sqlx::Error::RowNotFound) => {
-                    let thumb = match image {
-                        Some(image) => {
-                            let decoded = image::ImageReader::new(Cursor::new(&image))
-                                .with_guessed_format()
-                                .ok()?
-                                .decode()
-                                .ok()?
-                                .into_rgba8();
-
-                            let thumb = thumbnail(&decoded, 70, 70);
-
-                            let mut buf: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-
-                            thumb
-                                .write_to(&mut buf, image::ImageFormat::Bmp)
-                                .expect("i don't know how Cursor could fail");
-                            buf.flush().expect("could not flush buffer");
-
-                            Some(buf.get_mut().clone())
-                        }
-                        None => None,
-                    };
-
-                    let result: Result<(i64,), sqlx::Error> =
-                        sqlx::query_as(include_str!("../../queries/scan/create_album.sql"))
-                            .bind(album)
-                            .bind(&metadata.sort_album.as_ref().unwrap_or(&album))
-                            .bind(artist_id)
-                            .bind(image)
-                            .bind(thumb)
-                            .bind(metadata.date)
-                            .bind(&metadata.label)
-                            .bind(&metadata.catalog)
-                            .bind(&metadata.isrc)
-                            .fetch_one(&self.pool)
-                            .await;
-
-                    match result {
-                        Ok(v) => Some(v.0),
-                        Err(e) => {
-                            error!("Database error while creating album: {:?}", e);
-                            None
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Database error while retriving album: {:?}", e);
-                    None
-                }
+        while batch.len() < BATCH_SIZE {
+            match workers.results_rx.try_recv() {
+                Ok(result) => batch.push(result),
+                Err(_) => break,
             }
-        } else {
-            None
         }
+
+        self.write_batch(batch);
     }
 
-    async fn insert_track(
-        &self,
-        metadata: &Metadata,
-        album_id: Option<i64>,
-        path: &Path,
-        length: u64,
-    ) {
-        // literally i do not know how this could possibly fail
-        let name = metadata
-            .name
-            .clone()
-            .or_else(|| {
-                path.file_name()
-                    .and_then(|x| x.to_str())
-                    .map(|x| x.to_string())
-            })
-            .expect("weird file recieved in update metadata");
-
-        let result: Result<(i64,), sqlx::Error> =
-            sqlx::query_as(include_str!("../../queries/scan/create_track.sql"))
-                .bind(&name)
-                .bind(&name)
-                .bind(album_id)
-                .bind(metadata.track_current.map(|x| x as i32))
-                .bind(metadata.disc_current.map(|x| x as i32))
-                .bind(length as i32)
-                .bind(path.to_str())
-                .bind(&metadata.genre)
-                .fetch_one(&self.pool)
-                .await;
+    /// Writes the files read by the workers to the database in a single transaction.
+    fn write_batch(&mut self, batch: Vec<ScanResult>) {
+        let paths: Vec<PathBuf> = batch.iter().map(|result| result.path.clone()).collect();
+        let mut failed = Vec::new();
 
-        match result {
-            Ok(_) => (),
-            Err(sqlx::Error::RowNotFound) => (),
+        let mut tx = match task::block_on(self.pool.begin()) {
+            Ok(tx) => tx,
             Err(e) => {
-                error!("Database error while creating track: {:?}", e);
+                error!("Database error while starting scan transaction: {:?}", e);
+                self.forget_paths(&paths);
+                return;
             }
-        }
-    }
+        };
 
-    async fn update_metadata(
-        &mut self,
-        metadata: (Metadata, u64, Option<Box<[u8]>>),
-        path: &Path,
-    ) -> anyhow::Result<()> {
-        debug!(
-            "Adding/updating record for {:?} - {:?}",
-            metadata.0.artist, metadata.0.name
-        );
-
-        let artist_id = self.insert_artist(&metadata.0).await;
-        let album_id = self.insert_album(&metadata.0, artist_id, &metadata.2).await;
-        self.insert_track(&metadata.0, album_id, path, metadata.1)
-            .await;
+        for ScanResult { path, hash, file } in batch {
+            if let Some(state) = self.scan_record.get_mut(&path) {
+                state.hash = hash.or(state.hash);
+            }
 
-        Ok(())
-    }
+            // failed files are recorded too, so they're only retried once they change
+            if let Some(state) = self.scan_record.get(&path).copied() {
+                task::block_on(self.write_file_state(&mut tx, &path, FileKind::Audio, state));
+            }
 
-    fn read_metadata_for_path(
-        &mut self,
-        path: &PathBuf,
-    ) -> Option<(Metadata, u64, Option<Box<[u8]>>)> {
-        for (exts, provider) in &mut self.provider_table {
-            if file_is_scannable_with_provider(path, exts) {
-                if let Ok(metadata) = scan_file_with_provider(path, provider) {
-                    return Some(metadata);
+            let file = match file {
+                Ok(mut file) => {
+                    self.apply_directory_art(&path, &mut file);
+                    task::block_on(self.write_scanned_file(&mut tx, file, &path))
+                }
+                Err(error) => Err(error),
+            };
+
+            match file {
+                Ok(()) => task::block_on(self.clear_scan_error(&mut tx, &path)),
+                Err(error) => {
+                    warn!("Could not add file {:?} to the library: {}", path, error);
+                    task::block_on(self.record_scan_error(&mut tx, &path, &error));
+                    failed.push((path, error));
                 }
             }
         }
 
-        None
+        if let Err(e) = task::block_on(tx.commit()) {
+            error!("Database error while committing scanned files: {:?}", e);
+            self.forget_paths(&paths);
+            return;
+        }
+
+        self.scanned += paths.len() as u64;
+
+        for (path, error) in failed {
+            self.send_event(ScanEvent::FileFailed { path, error });
+        }
+
+        self.send_event(ScanEvent::ScanProgress {
+            current: self.scanned,
+            total: self.discovered_total,
+        });
     }
 
-    fn write_scan_record(&self) {
-        if let Some(path) = self.scan_record_path.as_ref() {
-            let mut file = File::create(path).unwrap();
-            let data = serde_json::to_string(&self.scan_record).unwrap();
-            if let Err(err) = file.write_all(data.as_bytes()) {
-                error!("Could not write scan record: {:?}", err);
-                error!("Scan record will not be saved, this may cause rescans on restart");
-            } else {
-                info!("Scan record written to {:?}", path);
-            }
-        } else {
-            error!("No scan record path set, scan record will not be saved");
+    /// Sends an event to the UI. The UI going away isn't a reason to stop the scan thread, so
+    /// it's only logged.
+    fn send_event(&self, event: ScanEvent) {
+        if self.event_tx.send(event).is_err() {
+            warn!("Could not send scan event, the receiver is gone");
         }
     }
 
-    fn scan(&mut self) {
-        if self.to_process.is_empty() {
-            info!("Scan complete, writing scan record and stopping");
-            self.write_scan_record();
-            self.scan_state = ScanState::Idle;
-            self.event_tx.send(ScanEvent::ScanCompleteIdle).unwrap();
+    // This is done in one shot because it's required for data integrity
+    // Cleanup cannot be cancelled
+    fn cleanup(&mut self) {
+        self.scan_state = ScanState::Discovering;
+
+        // every track would be outside of the library, which is much more likely to mean that the
+        // music directory couldn't be found than that the library is meant to be empty
+        if self.base_paths.is_empty() {
+            warn!("No library folders, skipping cleanup");
             return;
         }
 
-        let path = self.to_process.pop().unwrap();
-        let metadata = self.read_metadata_for_path(&path);
+        // the database is the source of truth, so the copy in memory is refreshed from it first
+        task::block_on(self.load_file_states());
 
-        if let Some(metadata) = metadata {
-            task::block_on(self.update_metadata(metadata, &path)).unwrap();
+        for root in self.available_roots() {
+            task::block_on(self.clear_tracks_unmatched(&root));
+        }
+
+        let mut archives = AHashMap::new();
+        let mut removed = Vec::new();
+        let mut missing = Vec::new();
+
+        for location in task::block_on(self.find_library_files()) {
+            let Some(root) = self
+                .base_paths
+                .iter()
+                .find(|root| location.starts_with(root))
+            else {
+                // only removing a folder through the library deletes its tracks straight away;
+                // otherwise the folder might only be missing from the settings, or have moved,
+                // so its tracks are kept as offline for the grace period
+                if task::block_on(self.set_track_unmatched(&location)) {
+                    removed.push(location);
+                }
 
-            self.scanned += 1;
+                continue;
+            };
+
+            // tracks on drives that aren't mounted are kept until the drive comes back, or
+            // until they've been offline for longer than the grace period
+            if let Some(since) = self.offline_roots.get(root) {
+                if self.offline_expired(since) {
+                    removed.push(location);
+                }
 
-            if self.scanned % 5 == 0 {
-                self.event_tx
-                    .send(ScanEvent::ScanProgress {
-                        current: self.scanned,
-                        total: self.discovered_total,
-                    })
-                    .unwrap();
+                continue;
+            }
+
+            if self.exclude.is_excluded(&location) {
+                removed.push(location);
+                continue;
+            }
+
+            // files that are gone might have been moved, which is only known once their new
+            // locations have been scanned
+            if !location_exists(&location, &mut archives) {
+                missing.push(location);
             }
-        } else {
-            warn!("Could not read metadata for file: {:?}", path);
+        }
+
+        for location in removed {
+            task::block_on(self.delete_track(&location));
+        }
+
+        self.missing.extend(missing);
+
+        // art files and CUE sheets don't have tracks of their own, so only their states are
+        // forgotten
+        let forgotten: Vec<PathBuf> = self
+            .support_record
+            .keys()
+            .filter(
+                |v| match self.base_paths.iter().find(|root| v.starts_with(root)) {
+                    Some(root) if self.offline_roots.contains_key(root) => false,
+                    Some(_) => !v.exists() || self.exclude.is_excluded(v),
+                    None => true,
+                },
+            )
+            .cloned()
+            .collect();
+
+        for file in forgotten {
+            self.forget_support_files(&file);
         }
     }
+}
 
-    async fn delete_track(&mut self, path: &PathBuf) {
-        debug!("track deleted or moved: {:?}", path);
-        let result = sqlx::query(include_str!("../../queries/scan/delete_track.sql"))
-            .bind(path.to_str())
-            .execute(&self.pool)
-            .await;
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
 
-        if let Err(e) = result {
-            error!("Database error while deleting track: {:?}", e);
-        } else {
-            self.scan_record.remove(path);
+    use crate::library::db::create_pool;
+
+    use super::*;
+
+    /// Creates a scan thread with an empty library in a temporary directory, without starting it.
+    fn test_thread(name: &str) -> (ScanThread, mpsc::Receiver<ScanEvent>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("muzak-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let pool = task::block_on(create_pool(dir.join("library.db"))).unwrap();
+        let (events_tx, events_rx) = mpsc::channel();
+        let (_, commands_rx) = mpsc::channel();
+
+        (
+            ScanThread::new(pool, events_tx, commands_rx),
+            events_rx,
+            dir,
+        )
+    }
+
+    #[test]
+    fn discovers_roots_added_during_a_scan() {
+        let (mut thread, events_rx, dir) = test_thread("scan-roots-test");
+        let root = dir.join("music");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("track.flac"), b"").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        thread.scan_state = ScanState::Scanning;
+        thread.add_root(root.clone());
+        assert_eq!(thread.scan_state, ScanState::Scanning);
+
+        // the scan goes back to discovery instead of finishing without the new folder
+        thread.finish_scan();
+        assert_eq!(thread.scan_state, ScanState::Discovering);
+        assert!(events_rx.try_recv().is_err());
+
+        while !thread.discovered.is_empty() {
+            thread.discover();
         }
+
+        assert!(thread.visited.contains(&root));
+        assert_eq!(thread.to_process, vec![root.join("track.flac")]);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 
-    // This is done in one shot because it's required for data integrity
-    // Cleanup cannot be cancelled
-    fn cleanup(&mut self) {
-        self.scan_record
-            .clone()
-            .iter()
-            .filter(|v| !v.0.exists())
-            .map(|v| v.0)
-            .for_each(|v| {
-                task::block_on(self.delete_track(v));
-            });
+    #[test]
+    fn keeps_releases_from_different_years_apart() {
+        let (thread, _, dir) = test_thread("scan-albums-test");
 
-        self.scan_state = ScanState::Discovering;
+        let release = |year| Metadata {
+            album: Some("Greatest Hits".to_string()),
+            date: Some(Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap()),
+            ..Default::default()
+        };
+
+        task::block_on(async {
+            let mut conn = thread.pool.acquire().await.unwrap();
+
+            let first = thread
+                .insert_album(&mut conn, &release(1999), None, &None)
+                .await;
+            let second = thread
+                .insert_album(&mut conn, &release(2005), None, &None)
+                .await;
+
+            assert!(first.is_some());
+            assert!(second.is_some());
+            assert_ne!(first, second);
+
+            // later tracks of either release find the album made for it
+            assert_eq!(
+                thread
+                    .insert_album(&mut conn, &release(1999), None, &None)
+                    .await,
+                first
+            );
+            assert_eq!(
+                thread
+                    .insert_album(&mut conn, &release(2005), None, &None)
+                    .await,
+                second
+            );
+        });
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
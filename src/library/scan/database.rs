@@ -0,0 +1,708 @@
+use std::{
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use ahash::AHashSet;
+use image::imageops::thumbnail;
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, SqliteConnection};
+use tracing::{debug, error};
+
+use crate::media::{
+    chapters::is_audiobook,
+    cue::{find_cue_sheet_in, split_location, virtual_track_location, TrackRange},
+    metadata::{Chapter, EmbeddedImage, Metadata},
+};
+
+use super::{file_state::track_fingerprint, ScanError, ScanThread, ScannedFile};
+
+fn create_thumbnail(image: &[u8]) -> Option<Vec<u8>> {
+    let decoded = image::ImageReader::new(Cursor::new(image))
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?
+        .into_rgba8();
+
+    let thumb = thumbnail(&decoded, 70, 70);
+
+    let mut buf: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+
+    thumb
+        .write_to(&mut buf, image::ImageFormat::Bmp)
+        .expect("i don't know how Cursor could fail");
+    buf.flush().expect("could not flush buffer");
+
+    Some(buf.get_mut().clone())
+}
+
+/// Returns the name of the artist an album is filed under. Compilations without an album artist
+/// are filed under "Various Artists" rather than the artist of whichever track is scanned first.
+fn album_artist(metadata: &Metadata) -> Option<String> {
+    if metadata.album_artist.is_some() {
+        metadata.album_artist.clone()
+    } else if metadata.compilation {
+        Some("Various Artists".to_string())
+    } else {
+        metadata.artist.clone()
+    }
+}
+
+impl ScanThread {
+    /// Updates an album from the first of its tracks to be scanned, so that retagged files and
+    /// replaced art show up in the library. Each album is only updated once per scan.
+    async fn refresh_album(
+        &mut self,
+        conn: &mut SqliteConnection,
+        album_id: i64,
+        metadata: &Metadata,
+        image: &Option<Box<[u8]>>,
+    ) {
+        if !self.refreshed_albums.insert(album_id) {
+            return;
+        }
+
+        let result: Result<(bool,), sqlx::Error> =
+            sqlx::query_as(include_str!("../../../queries/scan/update_album.sql"))
+                .bind(album_id)
+                .bind(metadata.sort_album.as_ref().or(metadata.album.as_ref()))
+                .bind(&metadata.label)
+                .bind(&metadata.catalog)
+                .bind(&metadata.isrc)
+                .bind(image)
+                .fetch_one(&mut *conn)
+                .await;
+
+        let art_changed = match result {
+            Ok((changed,)) => changed,
+            Err(e) => {
+                error!("Database error while updating album: {:?}", e);
+                return;
+            }
+        };
+
+        // files without art keep the album's current art, since its other tracks might have it
+        let Some(image) = image.as_ref().filter(|_| art_changed) else {
+            return;
+        };
+
+        // the images stored with the old art are replaced by the ones in the scanned files
+        let result = sqlx::query(include_str!(
+            "../../../queries/scan/delete_album_images.sql"
+        ))
+        .bind(album_id)
+        .execute(&mut *conn)
+        .await;
+
+        if let Err(e) = result {
+            error!("Database error while deleting album images: {:?}", e);
+        }
+
+        self.update_album_art(&mut *conn, album_id, image).await;
+    }
+
+    async fn update_album_art(&self, conn: &mut SqliteConnection, album_id: i64, image: &[u8]) {
+        debug!("refreshing album art for album {}", album_id);
+
+        let result = sqlx::query(include_str!("../../../queries/scan/update_album_art.sql"))
+            .bind(album_id)
+            .bind(image)
+            .bind(create_thumbnail(image))
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while updating album art: {:?}", e);
+        }
+    }
+
+    async fn insert_artist(&self, conn: &mut SqliteConnection, metadata: &Metadata) -> Option<i64> {
+        let artist = album_artist(metadata);
+
+        if let Some(artist) = artist {
+            let result: Result<(i64,), sqlx::Error> =
+                sqlx::query_as(include_str!("../../../queries/scan/create_artist.sql"))
+                    .bind(&artist)
+                    .bind(&metadata.artist_sort.as_ref().unwrap_or(&artist))
+                    .fetch_one(&mut *conn)
+                    .await;
+
+            match result {
+                Ok(v) => Some(v.0),
+                Err(e) => {
+                    error!("Database error while creating artist: {:?}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    }
+
+    pub(super) async fn insert_album(
+        &self,
+        conn: &mut SqliteConnection,
+        metadata: &Metadata,
+        artist_id: Option<i64>,
+        image: &Option<Box<[u8]>>,
+    ) -> Option<i64> {
+        if let Some(album) = &metadata.album {
+            let year = metadata.date.map(|v| v.format("%Y").to_string());
+            let result: Result<(i64,), sqlx::Error> =
+                sqlx::query_as(include_str!("../../../queries/scan/get_album_id.sql"))
+                    .bind(album)
+                    .bind(artist_id)
+                    .bind(&metadata.release_mbid)
+                    .bind(&year)
+                    .fetch_one(&mut *conn)
+                    .await;
+
+            match result {
+                Ok(v) => {
+                    if metadata.release_mbid.is_some() || metadata.date.is_some() {
+                        let result = sqlx::query(include_str!(
+                            "../../../queries/scan/fill_album_identity.sql"
+                        ))
+                        .bind(v.0)
+                        .bind(&metadata.release_mbid)
+                        .bind(metadata.date)
+                        .execute(&mut *conn)
+                        .await;
+
+                        if let Err(e) = result {
+                            error!("Database error while updating album: {:?}", e);
+                        }
+                    }
+
+                    Some(v.0)
+                }
+                Err(sqlx::Error::RowNotFound) => {
+                    let thumb = match image {
+                        Some(image) => Some(create_thumbnail(image)?),
+                        None => None,
+                    };
+
+                    let result: Result<(i64,), sqlx::Error> =
+                        sqlx::query_as(include_str!("../../../queries/scan/create_album.sql"))
+                            .bind(album)
+                            .bind(&metadata.sort_album.as_ref().unwrap_or(&album))
+                            .bind(artist_id)
+                            .bind(image)
+                            .bind(thumb)
+                            .bind(metadata.date)
+                            .bind(&metadata.label)
+                            .bind(&metadata.catalog)
+                            .bind(&metadata.isrc)
+                            .bind(&metadata.release_mbid)
+                            .fetch_one(&mut *conn)
+                            .await;
+
+                    let result = match result {
+                        // the insert returns nothing if it conflicted with an existing album, in
+                        // which case the track belongs to that album
+                        Err(sqlx::Error::RowNotFound) => {
+                            sqlx::query_as(include_str!(
+                                "../../../queries/scan/find_album_by_identity.sql"
+                            ))
+                            .bind(album)
+                            .bind(artist_id)
+                            .bind(&metadata.release_mbid)
+                            .bind(&year)
+                            .fetch_one(&mut *conn)
+                            .await
+                        }
+                        result => result,
+                    };
+
+                    match result {
+                        Ok(v) => Some(v.0),
+                        Err(e) => {
+                            error!("Database error while creating album: {:?}", e);
+                            None
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Database error while retriving album: {:?}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    }
+
+    async fn insert_album_images(
+        &self,
+        conn: &mut SqliteConnection,
+        album_id: Option<i64>,
+        images: &[EmbeddedImage],
+    ) {
+        let Some(album_id) = album_id else {
+            return;
+        };
+
+        // every track on an album usually carries the same images, so they're deduplicated by
+        // their contents
+        for image in images {
+            let hash = Sha256::digest(&image.data).to_vec();
+
+            let result = sqlx::query(include_str!("../../../queries/scan/create_album_image.sql"))
+                .bind(album_id)
+                .bind(image.usage.as_str())
+                .bind(&image.mime)
+                .bind(hash)
+                .bind(&image.data)
+                .execute(&mut *conn)
+                .await;
+
+            if let Err(e) = result {
+                error!("Database error while creating album image: {:?}", e);
+            }
+        }
+    }
+
+    async fn insert_track(
+        &self,
+        conn: &mut SqliteConnection,
+        metadata: &Metadata,
+        album_id: Option<i64>,
+        path: &Path,
+        range: Option<TrackRange>,
+        file: &ScannedFile,
+    ) -> Option<i64> {
+        // literally i do not know how this could possibly fail
+        let name = metadata
+            .name
+            .clone()
+            .or_else(|| {
+                path.file_name()
+                    .and_then(|x| x.to_str())
+                    .map(|x| x.to_string())
+            })
+            .expect("weird file recieved in update metadata");
+
+        let location = match range {
+            Some(range) => path.to_str().map(|v| virtual_track_location(v, range)),
+            None => path.to_str().map(|v| v.to_string()),
+        };
+
+        // virtual tracks only last as long as their portion of the file
+        let duration = match range {
+            Some(range) => range.end.unwrap_or(file.duration as f64).max(range.start) - range.start,
+            None => file.duration as f64,
+        };
+
+        let fingerprint = track_fingerprint(file, metadata, range);
+
+        if let (false, Some(location)) = (self.missing.is_empty(), &location) {
+            self.move_track(&mut *conn, &fingerprint, location).await;
+        }
+
+        let result: Result<(i64,), sqlx::Error> =
+            sqlx::query_as(include_str!("../../../queries/scan/create_track.sql"))
+                .bind(&name)
+                .bind(&name)
+                .bind(album_id)
+                .bind(metadata.track_current.map(|x| x as i32))
+                .bind(metadata.disc_current.map(|x| x as i32))
+                .bind(duration.round() as i32)
+                .bind(location)
+                .bind(&metadata.genre)
+                .bind(range.map(|v| (v.start * 1000.0).round() as i64))
+                .bind(
+                    range
+                        .and_then(|v| v.end)
+                        .map(|v| (v * 1000.0).round() as i64),
+                )
+                .bind(is_audiobook(path, metadata))
+                .bind(&file.info.codec)
+                .bind(&file.container)
+                .bind(file.info.sample_rate.map(|v| v as i64))
+                .bind(file.info.bit_depth.map(|v| v as i64))
+                .bind(file.info.channels.map(|v| v as i64))
+                .bind(file.info.bitrate.map(|v| v as i64))
+                .bind(file.file_size.map(|v| v as i64))
+                .bind(file.info.lossless)
+                .bind(&fingerprint)
+                .fetch_one(&mut *conn)
+                .await;
+
+        match result {
+            Ok(v) => Some(v.0),
+            Err(sqlx::Error::RowNotFound) => None,
+            Err(e) => {
+                error!("Database error while creating track: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Moves a track whose file disappeared during this scan to a new location with the same
+    /// fingerprint, so that it keeps its ID. The track is then updated in place by `insert_track`.
+    async fn move_track(&self, conn: &mut SqliteConnection, fingerprint: &[u8], location: &str) {
+        let result: Result<Vec<(i64, String)>, sqlx::Error> =
+            sqlx::query_as(include_str!("../../../queries/scan/find_moved_track.sql"))
+                .bind(fingerprint)
+                .bind(location)
+                .fetch_all(&mut *conn)
+                .await;
+
+        let candidates = match result {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                error!("Database error while finding moved track: {:?}", e);
+                return;
+            }
+        };
+
+        let file_name = |location: &str| {
+            let (path, _) = split_location(location);
+            Path::new(path).file_name().map(|v| v.to_os_string())
+        };
+
+        // copies of a file that's still in place aren't moves, and untagged files can share a
+        // fingerprint, so a track with the same file name (a moved folder) is preferred
+        let Some((id, old_location)) = candidates
+            .into_iter()
+            .filter(|(_, old_location)| {
+                let (path, _) = split_location(old_location);
+                self.missing.contains(Path::new(path))
+            })
+            .max_by_key(|(_, old_location)| file_name(old_location) == file_name(location))
+        else {
+            return;
+        };
+
+        debug!("track moved: {:?} -> {:?}", old_location, location);
+
+        let result = sqlx::query(include_str!("../../../queries/scan/move_track.sql"))
+            .bind(id)
+            .bind(location)
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while moving track: {:?}", e);
+        }
+    }
+
+    async fn insert_chapters(
+        &self,
+        conn: &mut SqliteConnection,
+        track_id: i64,
+        chapters: &[Chapter],
+    ) {
+        // chapters are replaced wholesale, since there's nothing to identify them by
+        let result = sqlx::query(include_str!("../../../queries/scan/delete_chapters.sql"))
+            .bind(track_id)
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while deleting chapters: {:?}", e);
+            return;
+        }
+
+        for (i, chapter) in chapters.iter().enumerate() {
+            let result = sqlx::query(include_str!("../../../queries/scan/create_chapter.sql"))
+                .bind(track_id)
+                .bind(i as i64)
+                .bind(&chapter.title)
+                .bind((chapter.start * 1000.0).round() as i64)
+                .bind(chapter.end.map(|v| (v * 1000.0).round() as i64))
+                .execute(&mut *conn)
+                .await;
+
+            if let Err(e) = result {
+                error!("Database error while creating chapter: {:?}", e);
+            }
+        }
+    }
+
+    async fn delete_tracks_with_query(
+        &self,
+        conn: &mut SqliteConnection,
+        query: &str,
+        path: &Path,
+    ) {
+        let result = sqlx::query(query)
+            .bind(path.to_str())
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while deleting replaced tracks: {:?}", e);
+        }
+    }
+
+    /// Deletes the virtual tracks of a file that aren't in `locations`, which are left behind when
+    /// the file's CUE sheet is edited (e.g. when its offsets change or tracks are merged).
+    async fn delete_stale_virtual_tracks(
+        &self,
+        conn: &mut SqliteConnection,
+        path: &Path,
+        locations: &AHashSet<String>,
+    ) {
+        let result: Result<Vec<(String,)>, sqlx::Error> = sqlx::query_as(include_str!(
+            "../../../queries/scan/find_virtual_tracks.sql"
+        ))
+        .bind(path.to_str())
+        .fetch_all(&mut *conn)
+        .await;
+
+        let existing = match result {
+            Ok(existing) => existing,
+            Err(e) => {
+                error!("Database error while finding virtual tracks: {:?}", e);
+                return;
+            }
+        };
+
+        for (location,) in existing {
+            if locations.contains(&location) {
+                continue;
+            }
+
+            debug!("virtual track no longer in CUE sheet: {:?}", location);
+
+            self.delete_tracks_with_query(
+                &mut *conn,
+                include_str!("../../../queries/scan/delete_whole_file_track.sql"),
+                Path::new(&location),
+            )
+            .await;
+        }
+    }
+
+    async fn update_metadata(
+        &mut self,
+        conn: &mut SqliteConnection,
+        file: ScannedFile,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        // single-file rips are split into one virtual track per CUE sheet track
+        let sheets = path
+            .parent()
+            .and_then(|dir| self.directory_cue_sheets.get(dir))
+            .map(|v| v.as_slice())
+            .unwrap_or_default();
+
+        let cue_tracks = find_cue_sheet_in(path, &file.metadata, sheets).and_then(|sheet| {
+            let tracks = sheet.tracks_for_file(path)?.clone();
+            (tracks.len() > 1).then_some((sheet, tracks))
+        });
+
+        if let Some((sheet, tracks)) = cue_tracks {
+            debug!("Splitting {:?} into {} tracks", path, tracks.len());
+
+            let mut locations = AHashSet::new();
+
+            for track in &tracks {
+                let mut metadata = file.metadata.clone();
+                sheet.apply_to_metadata(track, tracks.len(), &mut metadata);
+
+                let range = TrackRange {
+                    start: track.start,
+                    end: track.end,
+                };
+
+                locations.extend(path.to_str().map(|v| virtual_track_location(v, range)));
+
+                self.insert_file_track(&mut *conn, &file, &metadata, path, Some(range))
+                    .await;
+            }
+
+            self.delete_tracks_with_query(
+                &mut *conn,
+                include_str!("../../../queries/scan/delete_whole_file_track.sql"),
+                path,
+            )
+            .await;
+
+            self.delete_stale_virtual_tracks(&mut *conn, path, &locations)
+                .await;
+        } else {
+            self.insert_file_track(&mut *conn, &file, &file.metadata, path, None)
+                .await;
+
+            self.delete_tracks_with_query(
+                &mut *conn,
+                include_str!("../../../queries/scan/delete_virtual_tracks.sql"),
+                path,
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+
+    async fn insert_file_track(
+        &mut self,
+        conn: &mut SqliteConnection,
+        file: &ScannedFile,
+        metadata: &Metadata,
+        path: &Path,
+        range: Option<TrackRange>,
+    ) {
+        debug!(
+            "Adding/updating record for {:?} - {:?}",
+            metadata.artist, metadata.name
+        );
+
+        let artist_id = self.insert_artist(&mut *conn, metadata).await;
+        let album_id = self
+            .insert_album(&mut *conn, metadata, artist_id, &file.image)
+            .await;
+
+        self.changed_artists.extend(artist_id);
+        self.changed_albums.extend(album_id);
+
+        if let Some(album_id) = album_id {
+            self.refresh_album(&mut *conn, album_id, metadata, &file.image)
+                .await;
+        }
+
+        self.insert_album_images(&mut *conn, album_id, &file.images)
+            .await;
+
+        let track_id = self
+            .insert_track(&mut *conn, metadata, album_id, path, range, file)
+            .await;
+
+        // chapter times are relative to the whole file, so they're only kept for whole-file tracks
+        if let (Some(track_id), None) = (track_id, range) {
+            self.insert_chapters(&mut *conn, track_id, &file.chapters)
+                .await;
+        }
+    }
+
+    pub(super) async fn record_scan_error(
+        &self,
+        conn: &mut SqliteConnection,
+        path: &Path,
+        error: &ScanError,
+    ) {
+        let result = sqlx::query(include_str!("../../../queries/scan/create_scan_error.sql"))
+            .bind(path.to_str())
+            .bind(error.stage())
+            .bind(error.to_string())
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while recording scan error: {:?}", e);
+        }
+    }
+
+    pub(super) async fn clear_scan_error(&self, conn: &mut SqliteConnection, path: &Path) {
+        let result = sqlx::query(include_str!("../../../queries/scan/delete_scan_error.sql"))
+            .bind(path.to_str())
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while clearing scan error: {:?}", e);
+        }
+    }
+
+    /// Deletes the albums and artists that no longer have any tracks.
+    pub(super) async fn delete_orphans(&mut self) {
+        let result: Result<Vec<(i64, Option<i64>)>, sqlx::Error> = sqlx::query_as(include_str!(
+            "../../../queries/scan/delete_orphaned_albums.sql"
+        ))
+        .fetch_all(&self.pool)
+        .await;
+
+        match result {
+            Ok(albums) => {
+                debug!("Deleted {} albums without tracks", albums.len());
+
+                for (album, artist) in albums {
+                    self.changed_albums.insert(album);
+                    self.changed_artists.extend(artist);
+                }
+            }
+            Err(e) => error!("Database error while deleting orphaned albums: {:?}", e),
+        }
+
+        let result: Result<Vec<(i64,)>, sqlx::Error> = sqlx::query_as(include_str!(
+            "../../../queries/scan/delete_orphaned_artists.sql"
+        ))
+        .fetch_all(&self.pool)
+        .await;
+
+        match result {
+            Ok(artists) => {
+                self.changed_artists
+                    .extend(artists.into_iter().map(|(artist,)| artist));
+            }
+            Err(e) => error!("Database error while deleting orphaned artists: {:?}", e),
+        }
+    }
+
+    /// Writes a file's tracks to the database. Everything written for the file is rolled back if
+    /// any of it fails, so that the rest of the batch can still be committed.
+    pub(super) async fn write_scanned_file(
+        &mut self,
+        conn: &mut SqliteConnection,
+        file: ScannedFile,
+        path: &Path,
+    ) -> Result<(), ScanError> {
+        let result = async {
+            let mut savepoint = conn.begin().await?;
+            self.update_metadata(&mut savepoint, file, path).await?;
+            savepoint.commit().await?;
+
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        result.map_err(|e| {
+            error!(
+                "Database error while writing {:?} to the library: {:?}",
+                path, e
+            );
+            ScanError::Database(e.to_string())
+        })
+    }
+
+    pub(super) async fn delete_track(&mut self, path: &PathBuf) {
+        debug!("track deleted or moved: {:?}", path);
+
+        let result = async {
+            let mut tx = self.pool.begin().await?;
+
+            let albums: Vec<(Option<i64>,)> =
+                sqlx::query_as(include_str!("../../../queries/scan/delete_track.sql"))
+                    .bind(path.to_str())
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+            for query in [
+                include_str!("../../../queries/scan/delete_scan_error.sql"),
+                include_str!("../../../queries/scan/delete_file_state.sql"),
+            ] {
+                sqlx::query(query)
+                    .bind(path.to_str())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok::<_, sqlx::Error>(albums)
+        }
+        .await;
+
+        match result {
+            Ok(albums) => {
+                self.changed_albums
+                    .extend(albums.into_iter().filter_map(|(album,)| album));
+                self.scan_record.remove(path);
+            }
+            Err(e) => error!("Database error while deleting track: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use tracing::{debug, warn};
+
+use crate::{
+    library::exclude::has_marker_file,
+    media::{
+        archive::{archive_location, is_archive, list_entries, read_entry, split_archive_location},
+        cue::is_cue_sheet,
+        metadata::{EmbeddedImage, ImageUsage},
+    },
+};
+
+use super::{
+    file_state::{content_hash, FileKind},
+    ScanEvent, ScanState, ScanThread, ScannedFile,
+};
+
+const ART_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "bmp"];
+
+/// Finds the album art file in a directory listing, if there is one. Names that come first in the
+/// user's settings take priority.
+fn find_art_file(names: &[String], entries: &[PathBuf]) -> Option<PathBuf> {
+    names.iter().find_map(|name| {
+        entries
+            .iter()
+            .find(|entry| {
+                let stem_matches = entry
+                    .file_stem()
+                    .is_some_and(|stem| stem.eq_ignore_ascii_case(name));
+                let ext_matches = entry
+                    .extension()
+                    .is_some_and(|ext| ART_EXTENSIONS.iter().any(|v| ext.eq_ignore_ascii_case(v)));
+
+                stem_matches && ext_matches
+            })
+            .cloned()
+    })
+}
+
+pub(super) fn file_is_scannable_with_provider(path: &PathBuf, exts: &&[&str]) -> bool {
+    for extension in exts.iter() {
+        if let Some(ext) = path.extension() {
+            if ext.eq_ignore_ascii_case(extension) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+fn art_mime(path: &Path) -> String {
+    let ext = path
+        .extension()
+        .and_then(|v| v.to_str())
+        .map(|v| v.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        _ => "application/octet-stream",
+    }
+    .to_string()
+}
+
+fn image_pixels(image: &[u8]) -> Option<u64> {
+    let (width, height) = image::ImageReader::new(Cursor::new(image))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+
+    Some(width as u64 * height as u64)
+}
+
+impl ScanThread {
+    /// Returns whether or not the file should be scanned. If `force` is true, the file is scanned
+    /// even if it hasn't changed since the last scan.
+    fn file_is_scannable(&mut self, path: &PathBuf, force: bool) -> bool {
+        let supported = self
+            .provider_table
+            .iter()
+            .any(|(exts, _)| file_is_scannable_with_provider(path, exts));
+
+        if !supported {
+            return false;
+        }
+
+        let Some((state, changed)) = self.check_file_state(path, FileKind::Audio) else {
+            return false;
+        };
+
+        if !changed && !force {
+            return false;
+        }
+
+        // this is written to the database along with the file's metadata and hash
+        self.scan_record.insert(path.clone(), state);
+        true
+    }
+
+    pub(super) fn discover(&mut self) {
+        if self.discovered.is_empty() {
+            self.scan_state = ScanState::Scanning;
+            self.scan_started = Some(Instant::now());
+            self.start_workers();
+            return;
+        }
+
+        let path = self.discovered.pop().unwrap();
+
+        if self.visited.contains(&path) {
+            return;
+        }
+
+        self.discover_directory(&path, true);
+        self.visited.push(path);
+    }
+
+    /// Queues the files in a directory that need to be scanned. Subdirectories are queued for
+    /// discovery if `recursive` is set.
+    pub(super) fn discover_directory(&mut self, path: &Path, recursive: bool) {
+        if self.exclude.is_excluded(path) || has_marker_file(path) {
+            debug!("Skipping excluded directory {:?}", path);
+            return;
+        }
+
+        // the directory can disappear between being found and being read, especially while
+        // watching for changes
+        let Ok(read_dir) = fs::read_dir(path) else {
+            warn!("Could not read directory {:?}", path);
+            return;
+        };
+
+        // this might be slower than just reading the path directly but this prevents loops
+        let entries: Vec<PathBuf> = read_dir
+            .filter_map(|entry| entry.ok()?.path().canonicalize().ok())
+            .filter(|path| !self.exclude.is_excluded(path))
+            .collect();
+
+        // if the art or a CUE sheet changed, every file in the directory has to be rescanned to
+        // update the album and its tracks
+        let force = self.rescan_directories.remove(path)
+            | self.discover_art(path, &entries)
+            | self.discover_cue_sheets(path, &entries);
+
+        for path in entries {
+            if path.is_dir() {
+                if recursive {
+                    self.discovered.push(path);
+                }
+            } else if is_archive(&path) {
+                self.discover_archive(&path);
+            } else if self.file_is_scannable(&path, force) {
+                self.queue_file(path);
+            }
+        }
+    }
+
+    fn queue_file(&mut self, path: PathBuf) {
+        self.to_process.push(path);
+
+        self.discovered_total += 1;
+
+        if self.discovered_total % 20 == 0 {
+            self.event_tx
+                .send(ScanEvent::DiscoverProgress(self.discovered_total))
+                .expect("could not send discovered event");
+        }
+    }
+
+    /// Discovers the files inside of an archive. Each directory inside of the archive is treated
+    /// like a directory on disk, so art files next to the tracks are picked up as usual.
+    fn discover_archive(&mut self, archive: &Path) {
+        let Some(names) = list_entries(archive) else {
+            return;
+        };
+
+        let entries: Vec<PathBuf> = names
+            .iter()
+            .map(|name| PathBuf::from(archive_location(archive, name)))
+            .collect();
+
+        let mut directories: Vec<PathBuf> = entries
+            .iter()
+            .filter_map(|entry| entry.parent().map(|v| v.to_path_buf()))
+            .collect();
+        directories.sort();
+        directories.dedup();
+
+        for directory in directories {
+            let children: Vec<PathBuf> = entries
+                .iter()
+                .filter(|entry| entry.parent() == Some(directory.as_path()))
+                .cloned()
+                .collect();
+
+            let force = self.discover_art(&directory, &children);
+
+            for path in children {
+                if self.file_is_scannable(&path, force) {
+                    self.queue_file(path);
+                }
+            }
+        }
+    }
+
+    /// Looks for an album art file in the directory, and records it for use during scanning.
+    /// Returns true if the art file is new or has been modified since the last scan.
+    fn discover_art(&mut self, directory: &Path, entries: &[PathBuf]) -> bool {
+        let Some(art) = find_art_file(&self.settings.art_file_names, entries) else {
+            self.directory_art.remove(directory);
+            return false;
+        };
+
+        let Some((mut state, changed)) = self.check_file_state(&art, FileKind::Support) else {
+            return false;
+        };
+
+        if changed {
+            debug!("album art changed: {:?}", art);
+            state.hash = state.hash.or_else(|| content_hash(&art));
+            self.record_file_state(&art, FileKind::Support, state);
+        }
+
+        self.directory_art.insert(directory.to_path_buf(), art);
+
+        changed
+    }
+
+    /// Records the CUE sheets in a directory and their modification times. Returns true if any of
+    /// them are new or have been modified since the last scan.
+    fn discover_cue_sheets(&mut self, directory: &Path, entries: &[PathBuf]) -> bool {
+        let sheets: Vec<PathBuf> = entries
+            .iter()
+            .filter(|entry| is_cue_sheet(entry))
+            .cloned()
+            .collect();
+
+        let mut changed = false;
+
+        for sheet in &sheets {
+            let Some((mut state, sheet_changed)) = self.check_file_state(sheet, FileKind::Support)
+            else {
+                continue;
+            };
+
+            if sheet_changed {
+                debug!("CUE sheet changed: {:?}", sheet);
+                state.hash = state.hash.or_else(|| content_hash(sheet));
+                self.record_file_state(sheet, FileKind::Support, state);
+                changed = true;
+            }
+        }
+
+        if sheets.is_empty() {
+            self.directory_cue_sheets.remove(directory);
+        } else {
+            self.directory_cue_sheets
+                .insert(directory.to_path_buf(), sheets);
+        }
+
+        changed
+    }
+
+    fn read_directory_art(&mut self, path: &Path) -> Option<Box<[u8]>> {
+        if let Some((cached_path, data)) = &self.art_cache {
+            if cached_path == path {
+                return Some(data.clone());
+            }
+        }
+
+        let data = match path.to_str().and_then(split_archive_location) {
+            Some((archive, entry)) => {
+                let data = match &self.workers {
+                    Some(workers) => workers.archives.read_entry(archive, entry),
+                    None => read_entry(archive, entry),
+                };
+
+                data.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+            }
+            None => fs::read(path),
+        };
+
+        match data {
+            Ok(data) => {
+                let data = data.into_boxed_slice();
+                self.art_cache = Some((path.to_path_buf(), data.clone()));
+                Some(data)
+            }
+            Err(e) => {
+                warn!("Could not read album art file {:?}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Replaces the file's image with the art file in its directory, if there is one and the file
+    /// doesn't have embedded art (or the art file is larger, if the user prefers that).
+    pub(super) fn apply_directory_art(&mut self, path: &Path, file: &mut ScannedFile) {
+        let Some(art_path) = path
+            .parent()
+            .and_then(|dir| self.directory_art.get(dir))
+            .cloned()
+        else {
+            return;
+        };
+
+        if file.image.is_some() && !self.settings.prefer_larger_external_art {
+            return;
+        }
+
+        let Some(data) = self.read_directory_art(&art_path) else {
+            return;
+        };
+
+        if let Some(embedded) = &file.image {
+            if image_pixels(embedded) >= image_pixels(&data) {
+                return;
+            }
+        }
+
+        file.images.push(EmbeddedImage {
+            usage: ImageUsage::FrontCover,
+            mime: art_mime(&art_path),
+            data: data.clone(),
+        });
+        file.image = Some(data);
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use ahash::{AHashMap, AHashSet};
+use async_std::task;
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+use tracing::{error, info, warn};
+
+use crate::media::{
+    archive::split_archive_location,
+    cue::{split_location, TrackRange},
+    metadata::Metadata,
+};
+
+use super::{ScanThread, ScannedFile};
+
+/// What a file in the scan record is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FileKind {
+    /// A file that's read for tracks.
+    Audio,
+    /// An art file or CUE sheet, which only affects the tracks next to it.
+    Support,
+}
+
+impl FileKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FileKind::Audio => "audio",
+            FileKind::Support => "support",
+        }
+    }
+}
+
+/// How much of the start and the end of a file is hashed. Tags are almost always in one of
+/// these, and hashing whole files would mean reading the entire library on every scan.
+const HASHED_BYTES: u64 = 64 * 1024;
+
+/// Entries inside of an archive change whenever the archive does, so the archive's state is used
+/// for them.
+fn state_path(path: &Path) -> &Path {
+    match path.to_str().and_then(split_archive_location) {
+        Some((archive, _)) => archive,
+        None => path,
+    }
+}
+
+/// Hashes the size of a file along with its first and last `HASHED_BYTES`.
+pub(super) fn content_hash(path: &Path) -> Option<[u8; 32]> {
+    let mut file = File::open(state_path(path)).ok()?;
+    let len = file.metadata().ok()?.len();
+    let mut hasher = Sha256::new();
+    let mut buf = Vec::new();
+
+    hasher.update(len.to_le_bytes());
+
+    (&mut file).take(HASHED_BYTES).read_to_end(&mut buf).ok()?;
+
+    if len > HASHED_BYTES {
+        // the rest of files shorter than twice the hashed length is read instead
+        let end = len.saturating_sub(HASHED_BYTES).max(HASHED_BYTES);
+
+        file.seek(SeekFrom::Start(end)).ok()?;
+        file.read_to_end(&mut buf).ok()?;
+    }
+
+    hasher.update(&buf);
+
+    Some(hasher.finalize().into())
+}
+
+/// A row of `file_state`: path, kind, modification time, size and hash.
+type FileStateRow = (String, String, i64, Option<i64>, Option<Vec<u8>>);
+
+/// The state of a file when it was last scanned, used to tell whether it has changed since.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct FileState {
+    pub(super) modified: u64,
+    /// Missing for files imported from the old scan record, which only stored timestamps.
+    pub(super) size: Option<u64>,
+    /// Missing until the file has been hashed, see `content_hash`.
+    pub(super) hash: Option<[u8; 32]>,
+}
+
+impl FileState {
+    fn read(path: &Path) -> Option<FileState> {
+        let metadata = fs::metadata(state_path(path)).ok()?;
+
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+
+        Some(FileState {
+            modified,
+            size: Some(metadata.len()),
+            hash: None,
+        })
+    }
+
+    /// Returns true if the file at `path`, whose state is now `current`, hasn't changed since this
+    /// state was recorded. A different size always means the file changed, and the same
+    /// modification time means it didn't. Otherwise (e.g. when a file was copied back over
+    /// itself, or its size isn't known), the contents are hashed and compared, and the hash is
+    /// kept in `current`.
+    fn unchanged(&self, current: &mut FileState, path: &Path) -> bool {
+        if self.size.is_some() && current.size.is_some() && self.size != current.size {
+            return false;
+        }
+
+        if self.modified == current.modified {
+            return true;
+        }
+
+        let Some(hash) = self.hash else {
+            return false;
+        };
+
+        current.hash = current.hash.or_else(|| content_hash(path));
+        current.hash == Some(hash)
+    }
+}
+
+/// Identifies a track by its contents rather than its location, so that it can be found again
+/// after being moved or renamed. The file size and tags are used rather than a hash of the audio,
+/// which would mean reading every file in full.
+pub(super) fn track_fingerprint(
+    file: &ScannedFile,
+    metadata: &Metadata,
+    range: Option<TrackRange>,
+) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+
+    hasher.update(file.file_size.unwrap_or_default().to_le_bytes());
+    hasher.update(file.duration.to_le_bytes());
+
+    if let Some(range) = range {
+        hasher.update(range.start.to_le_bytes());
+        hasher.update(range.end.unwrap_or(-1.0).to_le_bytes());
+    }
+
+    for field in [
+        &metadata.name,
+        &metadata.artist,
+        &metadata.album_artist,
+        &metadata.album,
+    ] {
+        hasher.update(field.as_deref().unwrap_or_default().as_bytes());
+        // keeps ("ab", "c") and ("a", "bc") apart
+        hasher.update([0]);
+    }
+
+    hasher.update(metadata.track_current.unwrap_or_default().to_le_bytes());
+    hasher.update(metadata.disc_current.unwrap_or_default().to_le_bytes());
+
+    hasher.finalize().to_vec()
+}
+
+impl ScanThread {
+    fn record(&self, kind: FileKind) -> &AHashMap<PathBuf, FileState> {
+        match kind {
+            FileKind::Audio => &self.scan_record,
+            FileKind::Support => &self.support_record,
+        }
+    }
+
+    /// Reads the state of a file and compares it with the recorded one. Returns `None` if the
+    /// file can't be read, and its state along with whether it changed otherwise. Files that
+    /// were only touched have their new state recorded, so that they aren't hashed again.
+    pub(super) fn check_file_state(
+        &mut self,
+        path: &Path,
+        kind: FileKind,
+    ) -> Option<(FileState, bool)> {
+        let mut state = FileState::read(path)?;
+
+        let Some(last_scan) = self.record(kind).get(path).copied() else {
+            return Some((state, true));
+        };
+
+        if !last_scan.unchanged(&mut state, path) {
+            return Some((state, true));
+        }
+
+        if state.modified != last_scan.modified {
+            self.record_file_state(path, kind, state);
+        }
+
+        Some((state, false))
+    }
+
+    /// Removes the files of tracks that were added before the scanner stored technical properties
+    /// or fingerprints, or that a migration flagged for rescanning, from the scan record, so
+    /// they're read again during the next scan.
+    pub(super) async fn forget_tracks_without_properties(&mut self) {
+        let result: Result<Vec<(String,)>, sqlx::Error> = sqlx::query_as(include_str!(
+            "../../../queries/scan/find_tracks_without_properties.sql"
+        ))
+        .fetch_all(&self.pool)
+        .await;
+
+        let locations = match result {
+            Ok(locations) => locations,
+            Err(e) => {
+                error!("Database error while finding outdated tracks: {:?}", e);
+                return;
+            }
+        };
+
+        let result = async {
+            let mut tx = self.pool.begin().await?;
+
+            for (location,) in &locations {
+                let (path, _) = split_location(location);
+
+                sqlx::query(include_str!("../../../queries/scan/delete_file_state.sql"))
+                    .bind(path)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                for (location,) in &locations {
+                    let (path, _) = split_location(location);
+                    self.scan_record.remove(Path::new(path));
+                }
+            }
+            Err(e) => error!("Database error while forgetting outdated tracks: {:?}", e),
+        }
+    }
+
+    pub(super) async fn load_file_states(&mut self) {
+        let result: Result<Vec<FileStateRow>, sqlx::Error> =
+            sqlx::query_as(include_str!("../../../queries/scan/find_file_states.sql"))
+                .fetch_all(&self.pool)
+                .await;
+
+        match result {
+            Ok(states) => {
+                self.scan_record.clear();
+                self.support_record.clear();
+
+                for (path, kind, modified, size, hash) in states {
+                    let state = FileState {
+                        modified: modified as u64,
+                        size: size.map(|v| v as u64),
+                        hash: hash.and_then(|v| v.try_into().ok()),
+                    };
+
+                    let record = match kind.as_str() {
+                        "support" => &mut self.support_record,
+                        _ => &mut self.scan_record,
+                    };
+
+                    record.insert(PathBuf::from(path), state);
+                }
+            }
+            Err(e) => {
+                error!("Database error while loading scan record: {:?}", e);
+                error!("Scanning will be slow until the scan record is rebuilt");
+            }
+        }
+    }
+
+    /// Imports the scan record from the JSON file used by older versions, and deletes the file
+    /// once it's in the database.
+    pub(super) async fn import_scan_record(&self, path: &Path) {
+        let record: AHashMap<PathBuf, u64> = match File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(serde_json::from_reader(BufReader::new(file))?))
+        {
+            Ok(record) => record,
+            Err(e) => {
+                error!("Could not read old scan record, not importing it: {:?}", e);
+                return;
+            }
+        };
+
+        let result = async {
+            let mut tx = self.pool.begin().await?;
+
+            for (path, modified) in &record {
+                sqlx::query(include_str!("../../../queries/scan/import_file_state.sql"))
+                    .bind(path.to_str())
+                    .bind(*modified as i64)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                info!("Imported {} files from old scan record", record.len());
+
+                if let Err(e) = fs::remove_file(path) {
+                    warn!("Could not delete old scan record: {:?}", e);
+                }
+            }
+            Err(e) => error!("Database error while importing scan record: {:?}", e),
+        }
+    }
+
+    pub(super) async fn write_file_state(
+        &self,
+        conn: &mut SqliteConnection,
+        path: &Path,
+        kind: FileKind,
+        state: FileState,
+    ) {
+        let result = sqlx::query(include_str!("../../../queries/scan/create_file_state.sql"))
+            .bind(path.to_str())
+            .bind(kind.as_str())
+            .bind(state.modified as i64)
+            .bind(state.size.map(|v| v as i64))
+            .bind(state.hash.map(|v| v.to_vec()))
+            .execute(&mut *conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Database error while recording file state: {:?}", e);
+        }
+    }
+
+    /// Records the state of a file outside of a scan batch, such as an art file or CUE sheet, or
+    /// a file that was touched without changing.
+    pub(super) fn record_file_state(&mut self, path: &Path, kind: FileKind, state: FileState) {
+        let record = match kind {
+            FileKind::Audio => &mut self.scan_record,
+            FileKind::Support => &mut self.support_record,
+        };
+
+        record.insert(path.to_path_buf(), state);
+
+        match task::block_on(self.pool.acquire()) {
+            Ok(mut conn) => task::block_on(self.write_file_state(&mut conn, path, kind, state)),
+            Err(e) => error!("Database error while recording file state: {:?}", e),
+        }
+    }
+
+    /// Forgets the art files and CUE sheets at or below a path. The files in the directories of
+    /// forgotten CUE sheets are scanned again, so that the tracks they were split into are
+    /// replaced.
+    pub(super) fn forget_support_files(&mut self, path: &Path) {
+        let forgotten: Vec<PathBuf> = self
+            .support_record
+            .keys()
+            .filter(|v| v.starts_with(path))
+            .cloned()
+            .collect();
+
+        for file in forgotten {
+            let result = task::block_on(
+                sqlx::query(include_str!("../../../queries/scan/delete_file_state.sql"))
+                    .bind(file.to_str())
+                    .execute(&self.pool),
+            );
+
+            if let Err(e) = result {
+                error!("Database error while forgetting file state: {:?}", e);
+                continue;
+            }
+
+            self.support_record.remove(&file);
+            self.rescan_for_deleted_sheet(&file);
+        }
+    }
+
+    /// Returns the files that the library has tracks from, or had tracks from when they were
+    /// last scanned.
+    pub(super) async fn find_library_files(&self) -> Vec<PathBuf> {
+        let result: Result<Vec<(String,)>, sqlx::Error> =
+            sqlx::query_as(include_str!("../../../queries/scan/find_library_files.sql"))
+                .fetch_all(&self.pool)
+                .await;
+
+        match result {
+            Ok(locations) => {
+                let files: AHashSet<PathBuf> = locations
+                    .iter()
+                    .map(|(location,)| PathBuf::from(split_location(location).0))
+                    .collect();
+
+                files.into_iter().collect()
+            }
+            Err(e) => {
+                error!("Database error while finding library files: {:?}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Removes files that couldn't be written to the database from the scan record, so that
+    /// they're read again during the next scan.
+    pub(super) fn forget_paths(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            self.scan_record.remove(path);
+        }
+    }
+}
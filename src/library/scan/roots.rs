@@ -0,0 +1,609 @@
+use std::{
+    fs,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use ahash::{AHashMap, AHashSet};
+use async_std::task;
+use chrono::{DateTime, Utc};
+use notify::{
+    event::{AccessKind, AccessMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    library::settings::{save_scan_settings, ScanSettings},
+    media::{
+        archive::{list_entries, split_archive_location},
+        cue::is_cue_sheet,
+    },
+};
+
+use super::{ScanState, ScanThread};
+
+/// Watches the base paths for changes once a scan has finished.
+pub(super) struct LibraryWatcher {
+    // events stop when the watcher is dropped
+    watcher: RecommendedWatcher,
+    events_rx: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+/// How long the library has to go without changes before they're applied, so that copying in a
+/// whole album results in one update rather than one per file.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// How often the library folders are checked while watching, to notice drives being mounted or
+/// unmounted.
+const ROOT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The most symlinks followed when resolving a root that's missing.
+const MAX_ROOT_LINKS: usize = 32;
+
+/// Canonicalizes a root so that it can be compared with discovered paths. Roots that are missing,
+/// like a link to a drive that isn't mounted, are resolved as far as they can be, so that the
+/// tracks found in them before still match.
+pub(super) fn resolve_root(mut path: PathBuf) -> PathBuf {
+    for _ in 0..MAX_ROOT_LINKS {
+        if let Ok(resolved) = path.canonicalize() {
+            return resolved;
+        }
+
+        // a dangling link is followed to where its target would be
+        let Ok(target) = fs::read_link(&path) else {
+            break;
+        };
+
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => resolve_root(parent.to_path_buf()).join(name),
+        _ => path,
+    }
+}
+
+/// Returns the directories the library is built from, see `resolve_root`.
+pub(super) fn retrieve_base_paths(settings: &ScanSettings) -> Vec<PathBuf> {
+    let roots = match &settings.roots {
+        Some(roots) => roots.clone(),
+        None => {
+            let system_music = directories::UserDirs::new()
+                .and_then(|dirs| dirs.audio_dir().map(|v| v.to_path_buf()));
+
+            if system_music.is_none() {
+                warn!("No music directory found, add a library folder to scan");
+            }
+
+            system_music.into_iter().collect()
+        }
+    };
+
+    roots.into_iter().map(resolve_root).collect()
+}
+
+/// A track kept after leaving every library folder: its album and when it became unmatched.
+type UnmatchedTrackRow = (Option<i64>, DateTime<Utc>);
+
+/// Returns true if the file still exists. Archives are only listed once, since a single archive may
+/// contain an entire album.
+pub(super) fn location_exists(
+    path: &Path,
+    archives: &mut AHashMap<PathBuf, Option<AHashSet<String>>>,
+) -> bool {
+    let Some((archive, entry)) = path.to_str().and_then(split_archive_location) else {
+        return path.exists();
+    };
+
+    archives
+        .entry(archive.to_path_buf())
+        .or_insert_with(|| list_entries(archive).map(|v| v.into_iter().collect()))
+        .as_ref()
+        .is_some_and(|entries| entries.contains(entry))
+}
+
+/// Returns a root's path followed by a separator, which keeps roots that share a prefix (like
+/// /music and /music2) apart when matching track locations.
+fn root_prefix(root: &Path) -> String {
+    let mut prefix = root.to_string_lossy().to_string();
+
+    if !prefix.ends_with(MAIN_SEPARATOR) {
+        prefix.push(MAIN_SEPARATOR);
+    }
+
+    prefix
+}
+
+/// Canonicalizes a path reported by the watcher, so that it matches the paths found during
+/// discovery. Paths that no longer exist are resolved through their parent directory.
+fn canonical_event_path(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .ok()
+        .or_else(|| {
+            let parent = path.parent()?.canonicalize().ok()?;
+            Some(parent.join(path.file_name()?))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+impl ScanThread {
+    /// Returns the roots that were available the last time they were checked. Missing roots are
+    /// skipped rather than treated as empty, since they're usually drives or network shares that
+    /// aren't mounted.
+    pub(super) fn available_roots(&self) -> Vec<PathBuf> {
+        self.base_paths
+            .iter()
+            .filter(|root| !self.offline_roots.contains_key(*root))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns whether a root can currently be read. An empty root that the library has tracks
+    /// in is treated as unavailable, since that's usually the mount point of a drive that isn't
+    /// mounted.
+    fn root_available(&self, root: &Path) -> bool {
+        let Ok(mut entries) = fs::read_dir(root) else {
+            return false;
+        };
+
+        entries.next().is_some() || !self.scan_record.keys().any(|v| v.starts_with(root))
+    }
+
+    fn is_offline(&self, path: &Path) -> bool {
+        self.offline_roots.keys().any(|root| path.starts_with(root))
+    }
+
+    /// Checks whether each root is available, marking the tracks in roots that have gone offline
+    /// as unavailable and the tracks in roots that have come back as available again. Returns the
+    /// roots that came back, which have to be discovered again.
+    pub(super) fn check_roots(&mut self) -> Vec<PathBuf> {
+        self.last_root_check = Some(Instant::now());
+
+        let mut restored = Vec::new();
+
+        for root in self.base_paths.clone() {
+            let available = self.root_available(&root);
+
+            if available != self.offline_roots.contains_key(&root) {
+                continue;
+            }
+
+            if available {
+                info!("Library folder {:?} is available again", root);
+                task::block_on(self.set_root_offline(&root, false));
+                restored.push(root);
+            } else {
+                warn!(
+                    "Library folder {:?} is missing or offline, keeping its tracks until it's back",
+                    root
+                );
+                task::block_on(self.set_root_offline(&root, true));
+            }
+        }
+
+        self.send_library_changes();
+
+        restored
+    }
+
+    /// Starts watching and discovering roots that have become available again.
+    fn restore_roots(&mut self, roots: Vec<PathBuf>) {
+        for root in roots {
+            if let Some(watcher) = self.watcher.as_mut() {
+                if let Err(e) = watcher.watcher.watch(&root, RecursiveMode::Recursive) {
+                    warn!("Could not watch {:?} for changes: {:?}", root, e);
+                }
+            }
+
+            self.discovered.push(root);
+        }
+    }
+
+    /// Returns whether the tracks in an offline root have been kept for longer than the grace
+    /// period, and should be removed from the library.
+    pub(super) fn offline_expired(&self, since: &DateTime<Utc>) -> bool {
+        self.settings
+            .offline_grace_days
+            .is_some_and(|days| Utc::now() - *since > chrono::Duration::days(days as i64))
+    }
+
+    fn save_roots(&mut self) {
+        self.settings.roots = Some(self.base_paths.clone());
+
+        if let Some(path) = &self.settings_path {
+            save_scan_settings(path, &self.settings);
+        }
+    }
+
+    pub(super) fn add_root(&mut self, path: PathBuf) {
+        if self.base_paths.contains(&path) {
+            return;
+        }
+
+        info!("Adding library folder {:?}", path);
+        self.base_paths.push(path.clone());
+        self.save_roots();
+
+        // tracks kept from when the folder was last in the library are available again
+        task::block_on(self.clear_tracks_unmatched(&path));
+
+        if let Some(watcher) = self.watcher.as_mut() {
+            if let Err(e) = watcher.watcher.watch(&path, RecursiveMode::Recursive) {
+                warn!("Could not watch {:?} for changes: {:?}", path, e);
+            }
+        }
+
+        // a scan that's already running discovers the folder once its current files are scanned
+        self.discovered.push(path);
+
+        if self.scan_state == ScanState::Idle {
+            self.scanned = 0;
+            self.discovered_total = 0;
+            self.scan_state = ScanState::Discovering;
+        }
+    }
+
+    pub(super) fn remove_root(&mut self, path: &Path) {
+        if !self.base_paths.iter().any(|v| v == path) {
+            return;
+        }
+
+        info!("Removing library folder {:?}", path);
+        self.base_paths.retain(|v| v != path);
+        self.save_roots();
+
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.watcher.unwatch(path).ok();
+        }
+
+        // nothing inside of the folder should be scanned or added back
+        self.discovered.retain(|v| !v.starts_with(path));
+        self.to_process.retain(|v| !v.starts_with(path));
+
+        if let Some(workers) = &self.workers {
+            workers
+                .queue
+                .lock()
+                .unwrap()
+                .retain(|v| !v.starts_with(path));
+        }
+
+        let removed: Vec<PathBuf> = task::block_on(self.find_library_files())
+            .into_iter()
+            .filter(|v| v.starts_with(path))
+            .collect();
+
+        for location in removed {
+            task::block_on(self.delete_track(&location));
+        }
+
+        self.forget_support_files(path);
+
+        if self.offline_roots.remove(path).is_some() {
+            task::block_on(self.delete_offline_root(path));
+        }
+
+        self.send_library_changes();
+    }
+
+    pub(super) async fn load_offline_roots(&mut self) {
+        let result: Result<Vec<(String, DateTime<Utc>)>, sqlx::Error> =
+            sqlx::query_as(include_str!("../../../queries/scan/find_offline_roots.sql"))
+                .fetch_all(&self.pool)
+                .await;
+
+        match result {
+            Ok(roots) => {
+                self.offline_roots = roots
+                    .into_iter()
+                    .map(|(path, since)| (PathBuf::from(path), since))
+                    .collect();
+            }
+            Err(e) => error!("Database error while loading offline folders: {:?}", e),
+        }
+    }
+
+    /// Marks the tracks in a root as offline (or back online), and records when the root went
+    /// offline so its tracks can be removed once the grace period is over.
+    async fn set_root_offline(&mut self, root: &Path, offline: bool) {
+        let prefix = root_prefix(root);
+
+        let root_query = if offline {
+            include_str!("../../../queries/scan/create_offline_root.sql")
+        } else {
+            include_str!("../../../queries/scan/delete_offline_root.sql")
+        };
+
+        let result = async {
+            let mut tx = self.pool.begin().await?;
+
+            let albums: Vec<(Option<i64>,)> =
+                sqlx::query_as(include_str!("../../../queries/scan/set_tracks_offline.sql"))
+                    .bind(&prefix)
+                    .bind(offline)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+            sqlx::query(root_query)
+                .bind(root.to_str())
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+
+            Ok::<_, sqlx::Error>(albums)
+        }
+        .await;
+
+        match result {
+            Ok(albums) => {
+                self.changed_albums
+                    .extend(albums.into_iter().filter_map(|(album,)| album));
+
+                if offline {
+                    self.offline_roots.insert(root.to_path_buf(), Utc::now());
+                } else {
+                    self.offline_roots.remove(root);
+                }
+            }
+            Err(e) => error!("Database error while updating offline folder: {:?}", e),
+        }
+    }
+
+    /// Keeps the tracks of a file outside of every library folder as offline. Returns whether
+    /// they've been kept for longer than the grace period, or the file doesn't have any tracks,
+    /// and it should be removed from the library.
+    pub(super) async fn set_track_unmatched(&mut self, path: &Path) -> bool {
+        let result: Result<Vec<UnmatchedTrackRow>, sqlx::Error> = sqlx::query_as(include_str!(
+            "../../../queries/scan/set_track_unmatched.sql"
+        ))
+        .bind(path.to_str())
+        .fetch_all(&self.pool)
+        .await;
+
+        match result {
+            Ok(tracks) => {
+                let expired = tracks.is_empty()
+                    || tracks.iter().any(|(_, since)| self.offline_expired(since));
+
+                self.changed_albums
+                    .extend(tracks.into_iter().filter_map(|(album, _)| album));
+
+                expired
+            }
+            Err(e) => {
+                error!(
+                    "Database error while keeping track outside of library: {:?}",
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Brings back the tracks in a root that were kept after being outside of every library
+    /// folder.
+    pub(super) async fn clear_tracks_unmatched(&mut self, root: &Path) {
+        let result: Result<Vec<(Option<i64>,)>, sqlx::Error> = sqlx::query_as(include_str!(
+            "../../../queries/scan/clear_tracks_unmatched.sql"
+        ))
+        .bind(root_prefix(root))
+        .fetch_all(&self.pool)
+        .await;
+
+        match result {
+            Ok(albums) => {
+                self.changed_albums
+                    .extend(albums.into_iter().filter_map(|(album,)| album));
+            }
+            Err(e) => error!("Database error while restoring tracks: {:?}", e),
+        }
+    }
+
+    async fn delete_offline_root(&self, root: &Path) {
+        let result = sqlx::query(include_str!(
+            "../../../queries/scan/delete_offline_root.sql"
+        ))
+        .bind(root.to_str())
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = result {
+            error!("Database error while deleting offline folder: {:?}", e);
+        }
+    }
+
+    /// Starts watching the base paths for changes, unless they're already being watched. Returns
+    /// true if at least one of them is being watched.
+    pub(super) fn start_watching(&mut self) -> bool {
+        if self.watcher.is_some() {
+            return true;
+        }
+
+        let (events_tx, events_rx) = mpsc::channel();
+
+        let mut watcher = match notify::recommended_watcher(events_tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                warn!("Could not start watching the library: {:?}", e);
+                return false;
+            }
+        };
+
+        let mut watching = false;
+
+        for path in self.available_roots() {
+            match watcher.watch(&path, RecursiveMode::Recursive) {
+                Ok(()) => watching = true,
+                Err(e) => warn!("Could not watch {:?} for changes: {:?}", path, e),
+            }
+        }
+
+        if watching {
+            info!("Watching library for changes");
+            self.watcher = Some(LibraryWatcher { watcher, events_rx });
+        }
+
+        watching
+    }
+
+    /// Collects the changes reported by the watcher, and applies them once they stop arriving.
+    pub(super) fn watch(&mut self) {
+        if self
+            .last_root_check
+            .is_none_or(|v| v.elapsed() >= ROOT_CHECK_INTERVAL)
+        {
+            let restored = self.check_roots();
+
+            if !restored.is_empty() {
+                self.visited.clear();
+                self.scanned = 0;
+                self.discovered_total = 0;
+                self.restore_roots(restored);
+                self.scan_state = ScanState::Discovering;
+                return;
+            }
+        }
+
+        let Some(watcher) = self.watcher.as_ref() else {
+            std::thread::sleep(Duration::from_millis(100));
+            return;
+        };
+
+        let events: Vec<_> = watcher
+            .events_rx
+            .recv_timeout(Duration::from_millis(100))
+            .into_iter()
+            .chain(watcher.events_rx.try_iter())
+            .collect();
+
+        for event in events {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    error!("Error while watching library: {:?}", e);
+                    continue;
+                }
+            };
+
+            // files being read (by playback, for example) don't change the library
+            let relevant = match event.kind {
+                EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+                EventKind::Access(_) => false,
+                _ => true,
+            };
+
+            if relevant {
+                self.pending_changes
+                    .extend(event.paths.iter().map(|v| canonical_event_path(v)));
+                self.last_change = Some(Instant::now());
+            }
+        }
+
+        if self
+            .last_change
+            .is_some_and(|v| v.elapsed() >= WATCH_DEBOUNCE)
+        {
+            self.apply_changes();
+        }
+    }
+
+    /// Applies the changes collected by the watcher. Removed files are deleted from the library
+    /// straight away, and the directories containing new or modified files are discovered again,
+    /// which only rescans the files whose timestamps changed.
+    fn apply_changes(&mut self) {
+        self.last_change = None;
+
+        let changes: Vec<PathBuf> = self.pending_changes.drain().collect();
+        let mut archives = AHashMap::new();
+        let mut directories = AHashSet::new();
+
+        debug!("Applying {} library changes", changes.len());
+
+        // unmounting a drive shows up as its files being removed
+        let restored = self.check_roots();
+        self.restore_roots(restored);
+
+        for path in changes {
+            if path.is_dir() {
+                // new (or moved) directories have to be discovered recursively
+                self.discovered.push(path);
+                continue;
+            }
+
+            if !path.exists() {
+                self.remove_missing(&path, &mut archives);
+                self.forget_support_files(&path);
+            }
+
+            // art and CUE sheets affect every file in their directory
+            if let Some(parent) = path.parent().filter(|v| v.is_dir()) {
+                directories.insert(parent.to_path_buf());
+            }
+        }
+
+        self.visited.clear();
+        self.scanned = 0;
+        self.discovered_total = 0;
+
+        for directory in directories {
+            self.discover_directory(&directory, false);
+        }
+
+        if self.discovered.is_empty() && self.to_process.is_empty() {
+            self.delete_missing();
+            self.send_library_changes();
+        } else {
+            self.scan_state = ScanState::Discovering;
+        }
+    }
+
+    /// Marks the tracks at or below a path that no longer exists as missing. They're deleted once
+    /// the changes have been scanned, unless they turn up somewhere else.
+    fn remove_missing(
+        &mut self,
+        path: &Path,
+        archives: &mut AHashMap<PathBuf, Option<AHashSet<String>>>,
+    ) {
+        let prefix = path.to_string_lossy().to_string();
+
+        // archive entries are stored as <archive>!/<entry>, so they're matched by prefix too
+        let missing: Vec<PathBuf> = self
+            .scan_record
+            .keys()
+            .filter(|v| v.to_string_lossy().starts_with(&prefix))
+            .filter(|v| !self.is_offline(v))
+            .filter(|v| !location_exists(v, archives))
+            .cloned()
+            .collect();
+
+        self.missing.extend(missing);
+    }
+
+    /// Makes sure the files in the directory of a deleted CUE sheet are scanned again, so that the
+    /// tracks it split them into are replaced.
+    pub(super) fn rescan_for_deleted_sheet(&mut self, path: &Path) {
+        if let Some(parent) = path.parent().filter(|_| is_cue_sheet(path)) {
+            self.rescan_directories.insert(parent.to_path_buf());
+        }
+    }
+
+    /// Deletes the tracks whose files disappeared and weren't found somewhere else.
+    pub(super) fn delete_missing(&mut self) {
+        let mut archives = AHashMap::new();
+
+        for location in std::mem::take(&mut self.missing) {
+            // the file can come back (unchanged) before the scan finishes
+            if location_exists(&location, &mut archives) {
+                continue;
+            }
+
+            task::block_on(self.delete_track(&location));
+        }
+    }
+}
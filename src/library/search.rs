@@ -0,0 +1,162 @@
+/// The fields that can be searched on their own, e.g. `artist:coltrane`, and the columns of the
+/// search index they correspond to.
+const FIELDS: &[(&str, &str)] = &[
+    ("title", "title"),
+    ("track", "title"),
+    ("album", "album"),
+    ("artist", "artist"),
+    ("genre", "genre"),
+    ("label", "label"),
+    ("year", "year"),
+];
+
+/// A single term of a search query.
+#[derive(Debug, PartialEq)]
+struct Term {
+    column: Option<&'static str>,
+    value: String,
+    /// Quoted terms are matched as exact phrases; other terms also match words they're the start
+    /// of, so that results show up while the query is being typed.
+    quoted: bool,
+}
+
+fn split_terms(query: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut prefix = String::new();
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+
+        while let Some(c) = chars.next_if(|c| in_quotes || !c.is_whitespace()) {
+            match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    quoted = true;
+                }
+                ':' if !quoted && prefix.is_empty() => {
+                    let field = value.to_ascii_lowercase();
+
+                    match FIELDS.iter().any(|(name, _)| *name == field) {
+                        true => prefix = std::mem::take(&mut value),
+                        false => value.push(c),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+
+        let column = FIELDS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&prefix))
+            .map(|(_, column)| *column);
+
+        // terms without any letters or digits aren't indexed, and can't match anything
+        if value.chars().any(char::is_alphanumeric) {
+            terms.push(Term {
+                column,
+                value,
+                quoted,
+            });
+        }
+    }
+
+    terms
+}
+
+/// Translates a search query into an FTS5 query for the `library_search` table. Every term is
+/// quoted, so that punctuation in the query can't be mistaken for FTS5 syntax. Returns None if the
+/// query doesn't contain any terms.
+pub fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = split_terms(query)
+        .into_iter()
+        .map(|term| {
+            let mut fts = String::new();
+
+            if let Some(column) = term.column {
+                fts.push_str(column);
+                fts.push_str(" : ");
+            }
+
+            fts.push('"');
+            fts.push_str(&term.value.replace('"', "\"\""));
+            fts.push('"');
+
+            if !term.quoted && term.column != Some("year") {
+                fts.push('*');
+            }
+
+            fts
+        })
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_start_of_words() {
+        assert_eq!(
+            fts_query("blue  train").as_deref(),
+            Some("\"blue\"* \"train\"*")
+        );
+    }
+
+    #[test]
+    fn reads_field_prefixes() {
+        assert_eq!(
+            fts_query("Artist:coltrane track:naima").as_deref(),
+            Some("artist : \"coltrane\"* title : \"naima\"*")
+        );
+
+        // years are matched exactly, since 19* would match the whole century
+        assert_eq!(fts_query("year:1959").as_deref(), Some("year : \"1959\""));
+
+        // only the first colon can start a field, and unknown fields are part of the value
+        assert_eq!(
+            fts_query("title:a:b composer:bach").as_deref(),
+            Some("title : \"a:b\"* \"composer:bach\"*")
+        );
+    }
+
+    #[test]
+    fn quoted_terms_are_phrases() {
+        assert_eq!(
+            fts_query("\"a love supreme\" album:\"kind of blue\"").as_deref(),
+            Some("\"a love supreme\" album : \"kind of blue\"")
+        );
+
+        // a colon inside quotes doesn't start a field
+        assert_eq!(fts_query("\"artist:x\"").as_deref(), Some("\"artist:x\""));
+    }
+
+    #[test]
+    fn escapes_fts_syntax() {
+        assert_eq!(
+            fts_query("NOT ac/dc NEAR(").as_deref(),
+            Some("\"NOT\"* \"ac/dc\"* \"NEAR(\"*")
+        );
+    }
+
+    #[test]
+    fn ignores_terms_without_words() {
+        assert_eq!(fts_query(""), None);
+        assert_eq!(fts_query("  - \"\" artist: "), None);
+        assert_eq!(fts_query("- miles").as_deref(), Some("\"miles\"*"));
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ScanSettings {
+    /// The directories the library is built from. If this isn't set, the system's music directory
+    /// is used.
+    pub roots: Option<Vec<PathBuf>>,
+    /// Glob patterns for paths that should be left out of the library.
+    pub exclude: Vec<String>,
+    /// Regular expressions for paths that should be left out of the library.
+    pub exclude_regex: Vec<String>,
+    /// File names (without extensions) that are treated as album art when found next to audio
+    /// files. Earlier names take priority over later ones. Matching is case-insensitive.
+    pub art_file_names: Vec<String>,
+    /// Whether a file found in the album directory should replace embedded art when it has a
+    /// higher resolution than the embedded image.
+    pub prefer_larger_external_art: bool,
+    /// The number of threads used to read files during a scan. 0 uses one thread per CPU core.
+    pub scan_threads: usize,
+    /// The number of days tracks in an unavailable library folder are kept before they're
+    /// removed from the library. If this isn't set, they're kept until the folder is removed.
+    pub offline_grace_days: Option<u64>,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self {
+            roots: None,
+            exclude: Vec::new(),
+            exclude_regex: Vec::new(),
+            art_file_names: vec![
+                "cover".to_string(),
+                "folder".to_string(),
+                "front".to_string(),
+                "album".to_string(),
+                "albumart".to_string(),
+            ],
+            prefer_larger_external_art: false,
+            scan_threads: 0,
+            offline_grace_days: Some(30),
+        }
+    }
+}
+
+pub fn load_scan_settings(path: &Path) -> ScanSettings {
+    if let Ok(file) = File::open(path) {
+        let reader = BufReader::new(file);
+
+        if let Ok(settings) = serde_json::from_reader(reader) {
+            settings
+        } else {
+            warn!("Scan settings file exists but it could not be loaded, using default");
+            ScanSettings::default()
+        }
+    } else {
+        ScanSettings::default()
+    }
+}
+
+pub fn save_scan_settings(path: &Path, settings: &ScanSettings) {
+    let result = serde_json::to_string_pretty(settings)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| Ok(File::create(path)?.write_all(data.as_bytes())?));
+
+    if let Err(e) = result {
+        error!("Could not save scan settings: {:?}", e);
+    }
+}
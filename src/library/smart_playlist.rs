@@ -0,0 +1,420 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+
+/// How a text field is compared. Comparisons ignore ASCII case.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMatch {
+    Is(String),
+    Contains(String),
+    StartsWith(String),
+}
+
+/// An inclusive range of values. A range without either bound matches any track that has a
+/// value for the field, so `{"not": {"rating": {}}}` matches unrated tracks.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Range {
+    #[serde(default)]
+    pub min: Option<i64>,
+    #[serde(default)]
+    pub max: Option<i64>,
+}
+
+/// A rule that decides whether a track belongs in a smart playlist. Rules are stored as JSON, e.g.
+/// `{"all": [{"genre": {"contains": "jazz"}}, {"year": {"min": 1955, "max": 1965}}]}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// Matches tracks that match every rule in the group. An empty group matches every track.
+    All(Vec<Rule>),
+    /// Matches tracks that match at least one rule in the group. An empty group matches nothing.
+    Any(Vec<Rule>),
+    Not(Box<Rule>),
+    Title(TextMatch),
+    /// The album artist, since tracks don't have artists of their own.
+    Artist(TextMatch),
+    Album(TextMatch),
+    Genre(TextMatch),
+    Label(TextMatch),
+    Codec(TextMatch),
+    Container(TextMatch),
+    /// The year the album was released.
+    Year(Range),
+    /// The length of the track in seconds.
+    Duration(Range),
+    /// The rating of the track, from 1 to 5.
+    Rating(Range),
+    SampleRate(Range),
+    BitDepth(Range),
+    Channels(Range),
+    /// The average bitrate of the file in bits per second.
+    Bitrate(Range),
+    /// Matches tracks added to the library in the last N days.
+    AddedWithinDays(u32),
+    /// The number of times the track was played without being skipped, so
+    /// `{"play_count": {"max": 0}}` matches tracks that have never been played.
+    PlayCount(Range),
+    SkipCount(Range),
+    /// Matches tracks played (without being skipped) in the last N days.
+    PlayedWithinDays(u32),
+    Lossless(bool),
+    Audiobook(bool),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Title,
+    Artist,
+    Album,
+    Year,
+    Added,
+    Duration,
+    Rating,
+    PlayCount,
+    LastPlayed,
+    Random,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOrder {
+    pub field: SortField,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// The definition of a smart playlist: which tracks it contains, in what order, and how many.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SmartPlaylistRules {
+    pub rule: Rule,
+    /// Tracks that compare equal are listed in album order.
+    #[serde(default)]
+    pub sort: Vec<SortOrder>,
+    /// The maximum number of tracks in the playlist.
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl TryFrom<String> for SmartPlaylistRules {
+    type Error = serde_json::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        serde_json::from_str(&value)
+    }
+}
+
+const PLAY_COUNT: &str = "(SELECT count(*) FROM play_event \
+    WHERE play_event.track_id = track.id AND NOT play_event.skipped)";
+const SKIP_COUNT: &str = "(SELECT count(*) FROM play_event \
+    WHERE play_event.track_id = track.id AND play_event.skipped)";
+const LAST_PLAYED: &str = "(SELECT max(datetime(play_event.started_at)) FROM play_event \
+    WHERE play_event.track_id = track.id AND NOT play_event.skipped)";
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn push_text(query: &mut QueryBuilder<'static, Sqlite>, column: &str, value: &TextMatch) {
+    query.push(column);
+
+    match value {
+        TextMatch::Is(value) => {
+            query
+                .push(" = ")
+                .push_bind(value.clone())
+                .push(" COLLATE NOCASE");
+        }
+        TextMatch::Contains(value) => {
+            let pattern = format!("%{}%", escape_like(value));
+            query.push(" LIKE ").push_bind(pattern).push(" ESCAPE '\\'");
+        }
+        TextMatch::StartsWith(value) => {
+            let pattern = format!("{}%", escape_like(value));
+            query.push(" LIKE ").push_bind(pattern).push(" ESCAPE '\\'");
+        }
+    }
+}
+
+fn push_range(query: &mut QueryBuilder<'static, Sqlite>, column: &str, range: &Range) {
+    query.push("(").push(column).push(" IS NOT NULL");
+
+    if let Some(min) = range.min {
+        query.push(" AND ").push(column).push(" >= ").push_bind(min);
+    }
+
+    if let Some(max) = range.max {
+        query.push(" AND ").push(column).push(" <= ").push_bind(max);
+    }
+
+    query.push(")");
+}
+
+fn push_group(query: &mut QueryBuilder<'static, Sqlite>, rules: &[Rule], separator: &str) {
+    query.push("(");
+
+    for (i, rule) in rules.iter().enumerate() {
+        if i > 0 {
+            query.push(separator);
+        }
+
+        rule.push_sql(query);
+    }
+
+    query.push(")");
+}
+
+impl Rule {
+    /// Appends the rule to a query as a boolean expression over the `track`, `album` and `artist`
+    /// tables. The expression is never NULL, so that missing values don't leak through `not`.
+    fn push_sql(&self, query: &mut QueryBuilder<'static, Sqlite>) {
+        query.push("coalesce(");
+
+        match self {
+            Rule::All(rules) if rules.is_empty() => {
+                query.push("1");
+            }
+            Rule::Any(rules) if rules.is_empty() => {
+                query.push("0");
+            }
+            Rule::All(rules) => push_group(query, rules, " AND "),
+            Rule::Any(rules) => push_group(query, rules, " OR "),
+            Rule::Not(rule) => {
+                query.push("NOT ");
+                rule.push_sql(query);
+            }
+            Rule::Title(value) => push_text(query, "track.title", value),
+            Rule::Artist(value) => push_text(query, "artist.name", value),
+            Rule::Album(value) => push_text(query, "album.title", value),
+            Rule::Genre(value) => push_text(query, "track.genres", value),
+            Rule::Label(value) => push_text(query, "album.label", value),
+            Rule::Codec(value) => push_text(query, "track.codec", value),
+            Rule::Container(value) => push_text(query, "track.container", value),
+            Rule::Year(range) => push_range(
+                query,
+                "CAST(substr(album.release_date, 1, 4) AS INTEGER)",
+                range,
+            ),
+            Rule::Duration(range) => push_range(query, "track.duration", range),
+            Rule::Rating(range) => push_range(query, "track.rating", range),
+            Rule::SampleRate(range) => push_range(query, "track.sample_rate", range),
+            Rule::BitDepth(range) => push_range(query, "track.bit_depth", range),
+            Rule::Channels(range) => push_range(query, "track.channels", range),
+            Rule::Bitrate(range) => push_range(query, "track.bitrate", range),
+            Rule::AddedWithinDays(days) => {
+                query
+                    .push("track.created_at >= datetime('now', ")
+                    .push_bind(format!("-{} days", days))
+                    .push(")");
+            }
+            Rule::PlayCount(range) => push_range(query, PLAY_COUNT, range),
+            Rule::SkipCount(range) => push_range(query, SKIP_COUNT, range),
+            Rule::PlayedWithinDays(days) => {
+                query
+                    .push(LAST_PLAYED)
+                    .push(" >= datetime('now', ")
+                    .push_bind(format!("-{} days", days))
+                    .push(")");
+            }
+            Rule::Lossless(value) => {
+                query.push("track.lossless = ").push_bind(*value);
+            }
+            Rule::Audiobook(value) => {
+                query.push("track.audiobook = ").push_bind(*value);
+            }
+        }
+
+        query.push(", 0)");
+    }
+}
+
+impl SortField {
+    fn column(&self) -> &'static str {
+        match self {
+            SortField::Title => "track.title_sortable",
+            SortField::Artist => "artist.name_sortable",
+            SortField::Album => "album.title_sortable",
+            SortField::Year => "album.release_date",
+            SortField::Added => "track.created_at",
+            SortField::Duration => "track.duration",
+            SortField::Rating => "track.rating",
+            SortField::PlayCount => PLAY_COUNT,
+            SortField::LastPlayed => LAST_PLAYED,
+            SortField::Random => "random()",
+        }
+    }
+}
+
+impl SmartPlaylistRules {
+    /// Builds the query that lists the tracks in the playlist.
+    pub fn query(&self) -> QueryBuilder<'static, Sqlite> {
+        let mut query = QueryBuilder::new(
+            "SELECT track.* FROM track \
+            LEFT JOIN album ON album.id = track.album_id \
+            LEFT JOIN artist ON artist.id = album.artist_id \
+            WHERE ",
+        );
+
+        self.rule.push_sql(&mut query);
+        query.push(" ORDER BY ");
+
+        for order in &self.sort {
+            query.push(order.field.column());
+            query.push(if order.descending {
+                " DESC, "
+            } else {
+                " ASC, "
+            });
+        }
+
+        query.push(
+            "artist.name_sortable ASC, album.release_date ASC, album.title_sortable ASC, \
+            track.disc_number ASC, track.track_number ASC, track.id ASC",
+        );
+
+        if let Some(limit) = self.limit {
+            query.push(" LIMIT ").push_bind(limit as i64);
+        }
+
+        query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sql(rule: Rule) -> String {
+        let mut query = QueryBuilder::new("");
+        rule.push_sql(&mut query);
+        query.sql().to_string()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules = SmartPlaylistRules::try_from(
+            r#"{
+                "rule": {"all": [{"genre": {"contains": "jazz"}}, {"not": {"rating": {}}}]},
+                "sort": [{"field": "play_count", "descending": true}, {"field": "title"}],
+                "limit": 50
+            }"#
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules,
+            SmartPlaylistRules {
+                rule: Rule::All(vec![
+                    Rule::Genre(TextMatch::Contains("jazz".to_string())),
+                    Rule::Not(Box::new(Rule::Rating(Range::default()))),
+                ]),
+                sort: vec![
+                    SortOrder {
+                        field: SortField::PlayCount,
+                        descending: true,
+                    },
+                    SortOrder {
+                        field: SortField::Title,
+                        descending: false,
+                    },
+                ],
+                limit: Some(50),
+            }
+        );
+    }
+
+    #[test]
+    fn compiles_text_matches() {
+        assert_eq!(
+            sql(Rule::Title(TextMatch::Is("So What".to_string()))),
+            "coalesce(track.title = ? COLLATE NOCASE, 0)"
+        );
+        assert_eq!(
+            sql(Rule::Genre(TextMatch::Contains("jazz".to_string()))),
+            "coalesce(track.genres LIKE ? ESCAPE '\\', 0)"
+        );
+        assert_eq!(
+            sql(Rule::Artist(TextMatch::StartsWith("Miles".to_string()))),
+            "coalesce(artist.name LIKE ? ESCAPE '\\', 0)"
+        );
+    }
+
+    #[test]
+    fn escapes_like_patterns() {
+        assert_eq!(escape_like("plain"), "plain");
+        assert_eq!(escape_like("100%_pure"), "100\\%\\_pure");
+        // the escape character itself comes first, so escapes aren't doubled
+        assert_eq!(escape_like("a\\%"), "a\\\\\\%");
+    }
+
+    #[test]
+    fn compiles_ranges() {
+        assert_eq!(
+            sql(Rule::Year(Range {
+                min: Some(1955),
+                max: Some(1965),
+            })),
+            "coalesce((CAST(substr(album.release_date, 1, 4) AS INTEGER) IS NOT NULL \
+            AND CAST(substr(album.release_date, 1, 4) AS INTEGER) >= ? \
+            AND CAST(substr(album.release_date, 1, 4) AS INTEGER) <= ?), 0)"
+        );
+        assert_eq!(
+            sql(Rule::PlayCount(Range {
+                min: None,
+                max: Some(0),
+            })),
+            format!("coalesce(({0} IS NOT NULL AND {0} <= ?), 0)", PLAY_COUNT)
+        );
+    }
+
+    #[test]
+    fn negations_are_never_null() {
+        // without the inner coalesce, NOT NULL would drop unrated tracks instead of matching them
+        assert_eq!(
+            sql(Rule::Not(Box::new(Rule::Rating(Range::default())))),
+            "coalesce(NOT coalesce((track.rating IS NOT NULL), 0), 0)"
+        );
+        assert_eq!(
+            sql(Rule::Not(Box::new(Rule::Label(TextMatch::Is(
+                "Blue Note".to_string()
+            ))))),
+            "coalesce(NOT coalesce(album.label = ? COLLATE NOCASE, 0), 0)"
+        );
+    }
+
+    #[test]
+    fn compiles_groups() {
+        assert_eq!(sql(Rule::All(vec![])), "coalesce(1, 0)");
+        assert_eq!(sql(Rule::Any(vec![])), "coalesce(0, 0)");
+        assert_eq!(
+            sql(Rule::All(vec![
+                Rule::Lossless(true),
+                Rule::Any(vec![Rule::Audiobook(false), Rule::AddedWithinDays(30)]),
+            ])),
+            "coalesce((coalesce(track.lossless = ?, 0) AND \
+            coalesce((coalesce(track.audiobook = ?, 0) OR \
+            coalesce(track.created_at >= datetime('now', ?), 0)), 0)), 0)"
+        );
+    }
+
+    #[test]
+    fn builds_the_playlist_query() {
+        let rules = SmartPlaylistRules {
+            rule: Rule::All(vec![]),
+            sort: vec![SortOrder {
+                field: SortField::Rating,
+                descending: true,
+            }],
+            limit: Some(25),
+        };
+        let query = rules.query();
+        let sql = query.sql();
+
+        assert!(sql.contains(" WHERE coalesce(1, 0) ORDER BY track.rating DESC, "));
+        // ties fall back to album order, and the limit comes last
+        assert!(sql.ends_with("track.track_number ASC, track.id ASC LIMIT ?"));
+    }
+}
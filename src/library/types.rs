@@ -8,6 +8,8 @@ use sqlx::{Database, Decode, Sqlite, Type};
 
 use crate::util::rgb_to_bgr;
 
+use super::smart_playlist::SmartPlaylistRules;
+
 #[derive(sqlx::FromRow)]
 pub struct Artist {
     pub id: i64,
@@ -224,4 +226,194 @@ pub struct Track {
     #[sqlx(skip)]
     pub tags: Option<Vec<DBString>>,
     pub location: String,
+    #[sqlx(default)]
+    pub start_offset: Option<i64>,
+    #[sqlx(default)]
+    pub end_offset: Option<i64>,
+    #[sqlx(default)]
+    pub audiobook: bool,
+    #[sqlx(default)]
+    pub codec: Option<String>,
+    #[sqlx(default)]
+    pub container: Option<String>,
+    #[sqlx(default)]
+    pub sample_rate: Option<i64>,
+    #[sqlx(default)]
+    pub bit_depth: Option<i64>,
+    #[sqlx(default)]
+    pub channels: Option<i64>,
+    #[sqlx(default)]
+    pub bitrate: Option<i64>,
+    #[sqlx(default)]
+    pub file_size: Option<i64>,
+    #[sqlx(default)]
+    pub lossless: Option<bool>,
+    /// Whether the library folder containing the track is currently unavailable.
+    #[sqlx(default)]
+    pub offline: bool,
+    /// The user's rating of the track, from 1 to 5.
+    #[sqlx(default)]
+    pub rating: Option<i64>,
+}
+
+impl Track {
+    /// A short description of the track's format, such as "FLAC 24/96", "MP3 320kbps" or
+    /// "DSD64". Lossless formats show their bit depth and sample rate, lossy formats their
+    /// bitrate.
+    pub fn format_label(&self) -> Option<String> {
+        let codec = self.codec.as_deref()?;
+
+        let name = match codec {
+            "dsd" => {
+                let multiple = self.sample_rate? / 44100;
+                return Some(format!("DSD{}", multiple));
+            }
+            "vorbis" => "Vorbis".to_string(),
+            "opus" => "Opus".to_string(),
+            "wavpack" => "WavPack".to_string(),
+            v if v.starts_with("pcm_") => match self.container.as_deref() {
+                Some("aif") | Some("aiff") | Some("aifc") => "AIFF".to_string(),
+                Some("wav") | Some("wave") => "WAV".to_string(),
+                _ => "PCM".to_string(),
+            },
+            v => v.to_ascii_uppercase(),
+        };
+
+        match self.lossless {
+            Some(true) => {
+                let (Some(depth), Some(rate)) = (self.bit_depth, self.sample_rate) else {
+                    return Some(name);
+                };
+
+                // 44100 becomes 44.1, 96000 becomes 96
+                let khz = rate as f64 / 1000.0;
+                Some(format!("{} {}/{}", name, depth, khz))
+            }
+            _ => match self.bitrate {
+                Some(bitrate) => Some(format!(
+                    "{} {}kbps",
+                    name,
+                    (bitrate as f64 / 1000.0).round()
+                )),
+                None => Some(name),
+            },
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Clone)]
+pub struct TrackChapter {
+    pub id: i64,
+    pub track_id: i64,
+    pub chapter_index: i64,
+    #[sqlx(default)]
+    pub title: Option<DBString>,
+    pub start_offset: i64,
+    #[sqlx(default)]
+    pub end_offset: Option<i64>,
+}
+
+#[derive(sqlx::FromRow, Clone)]
+pub struct Station {
+    pub id: i64,
+    pub name: DBString,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow, Clone)]
+pub struct Playlist {
+    pub id: i64,
+    pub name: DBString,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub item_count: i64,
+}
+
+/// An entry in a playlist. The same track may appear in a playlist more than once.
+#[derive(sqlx::FromRow, Clone)]
+pub struct PlaylistItem {
+    pub id: i64,
+    pub playlist_id: i64,
+    /// The library track, or None for streams and files outside of the library.
+    #[sqlx(default)]
+    pub track_id: Option<i64>,
+    pub location: String,
+    pub position: i64,
+    #[sqlx(default)]
+    pub title: Option<DBString>,
+    /// The length of the track in seconds, if it is in the library.
+    #[sqlx(default)]
+    pub duration: Option<i64>,
+}
+
+/// A playlist defined by rules. Its tracks aren't stored; they're found by evaluating the rules
+/// against the library, so the playlist always reflects the library's current contents.
+#[derive(sqlx::FromRow, Clone)]
+pub struct SmartPlaylist {
+    pub id: i64,
+    pub name: DBString,
+    #[sqlx(try_from = "String")]
+    pub rules: SmartPlaylistRules,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The results of a library search, best matches first. Artists and albums are listed by ID, like
+/// the album list, so that they can be read through the cache.
+#[derive(Clone, Default)]
+pub struct SearchResults {
+    pub artists: Vec<i64>,
+    pub albums: Vec<i64>,
+    pub tracks: Vec<Track>,
+}
+
+/// A single play of a track or stream, from the play history.
+#[derive(sqlx::FromRow, Clone)]
+pub struct PlayEvent {
+    pub id: i64,
+    /// The library track, or None for streams and files outside of the library.
+    #[sqlx(default)]
+    pub track_id: Option<i64>,
+    pub location: String,
+    pub started_at: DateTime<Utc>,
+    /// How long the track was listened to, in seconds.
+    pub listened: i64,
+    pub skipped: bool,
+    /// What playback was started from (album, playlist or smart_playlist).
+    #[sqlx(default)]
+    pub source: Option<String>,
+    #[sqlx(default)]
+    pub source_id: Option<i64>,
+}
+
+/// Statistics for a single track. Skipped plays aren't counted as plays.
+#[derive(sqlx::FromRow, Clone, Default)]
+pub struct TrackStats {
+    pub play_count: i64,
+    pub skip_count: i64,
+    #[sqlx(default)]
+    pub last_played: Option<DateTime<Utc>>,
+    /// The total time the track was listened to, in seconds.
+    pub listened: i64,
+}
+
+/// How many times an artist, album or track was played in a period, and for how long (in seconds).
+#[derive(sqlx::FromRow, Clone)]
+pub struct PlayCount {
+    pub id: i64,
+    pub play_count: i64,
+    pub listened: i64,
+}
+
+/// A file that couldn't be added to the library during the last scan that tried to read it.
+#[derive(sqlx::FromRow, Clone)]
+pub struct ScanFailure {
+    pub id: i64,
+    pub location: String,
+    pub stage: String,
+    pub message: String,
+    pub first_failed_at: DateTime<Utc>,
+    pub failed_at: DateTime<Utc>,
 }
@@ -1,5 +1,11 @@
+pub mod archive;
 pub mod builtin;
+pub mod chapters;
+pub mod cue;
 pub mod errors;
 pub mod metadata;
 pub mod playback;
+pub mod playlist;
+pub mod source;
+pub mod stream;
 pub mod traits;
@@ -0,0 +1,616 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use ahash::AHashMap;
+use sevenz_rust::{BlockDecoder, SevenZArchiveEntry};
+use tracing::warn;
+use zip::{CompressionMethod, ZipArchive};
+
+use super::{
+    errors::OpenError,
+    source::{OpenedSource, RangeSource},
+};
+
+/// Separates the path of an archive from the path of an entry inside of it, e.g.
+/// `/music/album.zip!/disc1/01.flac`.
+pub const ARCHIVE_SEPARATOR: &str = "!/";
+
+const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "7z"];
+
+/// The most memory reserved up front for an entry. The sizes in an archive's headers aren't
+/// trusted beyond this, the buffer grows as the entry is read instead.
+const MAX_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+/// The most decompressed data an ArchiveCache holds on to at once.
+const MAX_CACHED_SIZE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    SevenZip,
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match ext.as_str() {
+        "zip" => Some(ArchiveKind::Zip),
+        "7z" => Some(ArchiveKind::SevenZip),
+        _ => None,
+    }
+}
+
+/// Returns true if the path refers to an archive that can be read as if it were a directory.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ARCHIVE_EXTENSIONS
+            .iter()
+            .any(|v| ext.eq_ignore_ascii_case(v))
+    })
+}
+
+/// Creates the location of an entry inside of an archive.
+pub fn archive_location(archive: &Path, entry: &str) -> String {
+    format!(
+        "{}{}{}",
+        archive.to_string_lossy(),
+        ARCHIVE_SEPARATOR,
+        entry.trim_start_matches('/')
+    )
+}
+
+/// Splits an archive entry location into the path of the archive and the name of the entry.
+/// Returns None if the location doesn't point inside of an archive.
+pub fn split_archive_location(location: &str) -> Option<(&Path, &str)> {
+    location
+        .match_indices(ARCHIVE_SEPARATOR)
+        .find_map(|(i, _)| {
+            let archive = Path::new(&location[..i]);
+            let entry = &location[i + ARCHIVE_SEPARATOR.len()..];
+
+            (is_archive(archive) && !entry.is_empty()).then_some((archive, entry))
+        })
+}
+
+/// Lists the files in an archive. Directories are not included, since they're implied by the
+/// names of the files inside of them.
+pub fn list_entries(archive: &Path) -> Option<Vec<String>> {
+    let result = match archive_kind(archive)? {
+        ArchiveKind::Zip => list_zip_entries(archive),
+        ArchiveKind::SevenZip => list_7z_entries(archive),
+    };
+
+    match result {
+        Ok(entries) => Some(entries),
+        Err(e) => {
+            warn!("Could not read archive {:?}: {}", archive, e);
+            None
+        }
+    }
+}
+
+fn list_zip_entries(archive: &Path) -> anyhow::Result<Vec<String>> {
+    let zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+
+    Ok(zip
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(|name| name.to_string())
+        .collect())
+}
+
+fn list_7z_entries(archive: &Path) -> anyhow::Result<Vec<String>> {
+    let archive = sevenz_rust::Archive::open(archive)?;
+
+    Ok(archive
+        .files
+        .iter()
+        .filter(|file| !file.is_directory() && !file.is_anti_item())
+        .map(|file| file.name().to_string())
+        .collect())
+}
+
+/// Opens an entry inside of an archive. Uncompressed ZIP entries are read straight from the
+/// archive; anything else has to be decompressed into memory first, since neither format allows
+/// seeking inside of a compressed entry.
+pub fn open_entry(archive: &Path, entry: &str) -> Result<OpenedSource, OpenError> {
+    open_entry_in(archive, entry, None)
+}
+
+fn open_entry_in(
+    archive: &Path,
+    entry: &str,
+    cache: Option<&ArchiveCache>,
+) -> Result<OpenedSource, OpenError> {
+    let ext = Path::new(entry)
+        .extension()
+        .and_then(|v| v.to_str())
+        .map(|v| v.to_ascii_lowercase());
+
+    let kind = archive_kind(archive)
+        .ok_or_else(|| OpenError::UnsupportedFormat("not a ZIP or 7z archive".to_string()))?;
+
+    if kind == ArchiveKind::Zip {
+        if let Some((start, len)) = stored_zip_entry(archive, entry)? {
+            let file = File::open(archive)?;
+            let source = RangeSource::new(file, start, len)?;
+
+            return Ok(OpenedSource {
+                source: Box::new(source),
+                ext,
+                icy: None,
+            });
+        }
+    }
+
+    let data = read_entry_in(archive, entry, cache).ok_or_else(|| {
+        OpenError::FileCorrupt(format!("could not extract {} from the archive", entry))
+    })?;
+
+    Ok(OpenedSource::memory(data, ext))
+}
+
+/// Returns the position and length of the data of a ZIP entry, if it's stored without
+/// compression.
+fn stored_zip_entry(archive: &Path, entry: &str) -> Result<Option<(u64, u64)>, OpenError> {
+    let file = File::open(archive)?;
+
+    let mut zip =
+        ZipArchive::new(BufReader::new(file)).map_err(|e| OpenError::FileCorrupt(e.to_string()))?;
+    let file = zip.by_name(entry).map_err(|e| match e {
+        zip::result::ZipError::FileNotFound => OpenError::NotFound,
+        e => OpenError::UnsupportedFormat(e.to_string()),
+    })?;
+
+    Ok(
+        (file.compression() == CompressionMethod::Stored && !file.encrypted())
+            .then(|| (file.data_start(), file.size())),
+    )
+}
+
+/// Reads (and decompresses) an entry inside of an archive into memory.
+pub fn read_entry(archive: &Path, entry: &str) -> Option<Vec<u8>> {
+    read_entry_in(archive, entry, None)
+}
+
+fn read_entry_in(archive: &Path, entry: &str, cache: Option<&ArchiveCache>) -> Option<Vec<u8>> {
+    let result = match (archive_kind(archive)?, cache) {
+        (ArchiveKind::Zip, _) => read_zip_entry(archive, entry),
+        (ArchiveKind::SevenZip, Some(cache)) => cache.read_7z_entry(archive, entry),
+        (ArchiveKind::SevenZip, None) => read_7z_entry(archive, entry),
+    };
+
+    match result {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Could not read {} from archive {:?}: {}", entry, archive, e);
+            None
+        }
+    }
+}
+
+fn read_zip_entry(archive: &Path, entry: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+
+    let mut file = match zip.by_name(entry) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let size = file.size();
+
+    Ok(Some(read_to_vec(&mut file, size)?))
+}
+
+/// Reads an entry whose headers claim it's `size` bytes long.
+fn read_to_vec(reader: &mut dyn Read, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize);
+    reader.read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+/// Decompresses the entries in a 7z block in order, until the callback returns false.
+fn decode_7z_block(
+    archive: &Path,
+    info: &sevenz_rust::Archive,
+    block: usize,
+    mut callback: impl FnMut(&SevenZArchiveEntry, &mut dyn Read) -> io::Result<bool>,
+) -> anyhow::Result<()> {
+    let mut source = BufReader::new(File::open(archive)?);
+    let decoder = BlockDecoder::new(block, info, &[], &mut source);
+
+    decoder.for_each_entries(&mut |file, reader| Ok(callback(file, reader)?))?;
+
+    Ok(())
+}
+
+fn read_7z_entry(archive: &Path, entry: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let info = sevenz_rust::Archive::open(archive)?;
+
+    let Some(index) = info.files.iter().position(|v| v.name() == entry) else {
+        return Ok(None);
+    };
+
+    // entries without a block are empty
+    let Some(block) = info.stream_map.file_folder_index[index] else {
+        return Ok(Some(Vec::new()));
+    };
+
+    read_7z_block_entry(archive, &info, block, entry)
+}
+
+/// Reads a single entry from a 7z block. Entries in a solid block can only be decompressed after
+/// everything before them in the block, so the other entries are read through and discarded.
+fn read_7z_block_entry(
+    archive: &Path,
+    info: &sevenz_rust::Archive,
+    block: usize,
+    entry: &str,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut data = None;
+
+    decode_7z_block(archive, info, block, |file, reader| {
+        if file.name() == entry {
+            data = Some(read_to_vec(reader, file.size())?);
+
+            Ok(false)
+        } else {
+            io::copy(reader, &mut io::sink())?;
+
+            Ok(true)
+        }
+    })?;
+
+    Ok(data)
+}
+
+/// The entries of a 7z block that have been decompressed, by name.
+type BlockEntries = AHashMap<String, Vec<u8>>;
+
+/// A 7z archive opened by an ArchiveCache.
+struct CachedSevenZip {
+    info: sevenz_rust::Archive,
+    /// The entries of each block that have been decompressed but not read yet, or None for blocks
+    /// that haven't been decompressed.
+    blocks: Vec<Mutex<Option<BlockEntries>>>,
+}
+
+/// Keeps archives open while they're being scanned, so that the headers of an archive are only
+/// read once. Solid 7z blocks are decompressed in full the first time an entry in them is read,
+/// and the other entries are kept until they're read, instead of decompressing the block again
+/// for every entry.
+pub struct ArchiveCache {
+    seven_zip: Mutex<AHashMap<PathBuf, Arc<CachedSevenZip>>>,
+    /// The size of the entries that have been decompressed but not read yet.
+    cached_size: AtomicU64,
+    /// The blocks holding entries that haven't been read yet, oldest first. Entries that are never
+    /// read (because they haven't changed, or aren't audio) are dropped from the oldest blocks to
+    /// make room for newer ones.
+    cached_blocks: Mutex<VecDeque<(Arc<CachedSevenZip>, usize)>>,
+    max_cached_size: u64,
+}
+
+impl Default for ArchiveCache {
+    fn default() -> Self {
+        ArchiveCache::with_limit(MAX_CACHED_SIZE)
+    }
+}
+
+impl ArchiveCache {
+    fn with_limit(max_cached_size: u64) -> Self {
+        ArchiveCache {
+            seven_zip: Mutex::new(AHashMap::new()),
+            cached_size: AtomicU64::new(0),
+            cached_blocks: Mutex::new(VecDeque::new()),
+            max_cached_size,
+        }
+    }
+
+    /// Closes every archive and drops the entries that were never read, once a scan is finished.
+    pub fn clear(&self) {
+        self.seven_zip.lock().unwrap().clear();
+        self.cached_blocks.lock().unwrap().clear();
+        self.cached_size.store(0, Ordering::Relaxed);
+    }
+
+    fn release(&self, size: u64) {
+        // entries read after the cache was cleared were already discounted
+        self.cached_size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                Some(v.saturating_sub(size))
+            })
+            .ok();
+    }
+
+    /// Drops the unread entries of the oldest blocks until `size` more bytes fit in the cache.
+    /// Returns false if they still don't fit. Blocks that are being read by another thread are
+    /// skipped rather than waited for.
+    fn make_room(&self, size: u64) -> bool {
+        let mut blocks = self.cached_blocks.lock().unwrap();
+        let mut busy = Vec::new();
+
+        while self.cached_size.load(Ordering::Relaxed) + size > self.max_cached_size {
+            let Some((opened, block)) = blocks.pop_front() else {
+                break;
+            };
+
+            let Ok(mut cached) = opened.blocks[block].try_lock() else {
+                busy.push((opened.clone(), block));
+                continue;
+            };
+
+            // the block stays marked as decompressed, so that its entries are read from the
+            // archive again if they're needed after all
+            if let Some(entries) = cached.as_mut() {
+                self.release(entries.drain().map(|(_, v)| v.len() as u64).sum());
+            }
+        }
+
+        for block in busy.into_iter().rev() {
+            blocks.push_front(block);
+        }
+
+        self.cached_size.load(Ordering::Relaxed) + size <= self.max_cached_size
+    }
+
+    /// Opens an entry inside of an archive, like `open_entry`.
+    pub fn open_entry(&self, archive: &Path, entry: &str) -> Result<OpenedSource, OpenError> {
+        open_entry_in(archive, entry, Some(self))
+    }
+
+    /// Reads an entry inside of an archive into memory, like `read_entry`.
+    pub fn read_entry(&self, archive: &Path, entry: &str) -> Option<Vec<u8>> {
+        read_entry_in(archive, entry, Some(self))
+    }
+
+    fn open_7z(&self, archive: &Path) -> anyhow::Result<Arc<CachedSevenZip>> {
+        if let Some(opened) = self.seven_zip.lock().unwrap().get(archive) {
+            return Ok(opened.clone());
+        }
+
+        // the headers are read without holding the lock, so that other archives can be read in
+        // the meantime
+        let info = sevenz_rust::Archive::open(archive)?;
+        let blocks = info.folders.iter().map(|_| Mutex::new(None)).collect();
+        let opened = Arc::new(CachedSevenZip { info, blocks });
+
+        Ok(self
+            .seven_zip
+            .lock()
+            .unwrap()
+            .entry(archive.to_path_buf())
+            .or_insert(opened)
+            .clone())
+    }
+
+    fn read_7z_entry(&self, archive: &Path, entry: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let opened = self.open_7z(archive)?;
+        let info = &opened.info;
+
+        let Some(index) = info.files.iter().position(|v| v.name() == entry) else {
+            return Ok(None);
+        };
+
+        let Some(block) = info.stream_map.file_folder_index[index] else {
+            return Ok(Some(Vec::new()));
+        };
+
+        // other readers of the same block wait here until it has been decompressed
+        let mut cached = opened.blocks[block].lock().unwrap();
+
+        if let Some(entries) = cached.as_mut() {
+            if let Some(data) = entries.remove(entry) {
+                self.release(data.len() as u64);
+                return Ok(Some(data));
+            }
+
+            // the entry has been read before, or didn't fit in the cache
+            return read_7z_block_entry(archive, info, block, entry);
+        }
+
+        let mut entries = AHashMap::new();
+        let mut data = None;
+
+        decode_7z_block(archive, info, block, |file, reader| {
+            if file.name() == entry {
+                data = Some(read_to_vec(reader, file.size())?);
+            } else if self.make_room(file.size()) {
+                let buf = read_to_vec(reader, file.size())?;
+                self.cached_size
+                    .fetch_add(buf.len() as u64, Ordering::Relaxed);
+                entries.insert(file.name().to_string(), buf);
+            } else {
+                io::copy(reader, &mut io::sink())?;
+            }
+
+            Ok(true)
+        })?;
+
+        if !entries.is_empty() {
+            self.cached_blocks
+                .lock()
+                .unwrap()
+                .push_back((opened.clone(), block));
+        }
+
+        *cached = Some(entries);
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use sevenz_rust::{SeqReader, SevenZWriter, SourceReader};
+
+    use super::*;
+
+    /// Writes a 7z archive with the entries in a single solid block.
+    fn write_7z(path: &Path, entries: &[(&str, Vec<u8>)]) {
+        let mut writer = SevenZWriter::create(path).unwrap();
+
+        let files = entries
+            .iter()
+            .map(|(name, _)| {
+                let mut entry = SevenZArchiveEntry::new();
+                entry.name = name.to_string();
+                entry.has_stream = true;
+                entry
+            })
+            .collect();
+        let readers = entries
+            .iter()
+            .map(|(_, data)| SourceReader::new(Cursor::new(data.clone())))
+            .collect();
+
+        writer
+            .push_archive_entries(files, SeqReader::new(readers))
+            .unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn entry_data(seed: u8, len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(seed)).collect()
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "muzak-archive-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn splits_archive_locations() {
+        assert_eq!(
+            split_archive_location("/music/album.zip!/disc 1/01.flac"),
+            Some((Path::new("/music/album.zip"), "disc 1/01.flac"))
+        );
+        assert_eq!(
+            split_archive_location("/music/album.7z!/inner.zip!/01.flac"),
+            Some((Path::new("/music/album.7z"), "inner.zip!/01.flac"))
+        );
+        assert_eq!(
+            split_archive_location("/music/wow!/album.zip!/01.flac"),
+            Some((Path::new("/music/wow!/album.zip"), "01.flac"))
+        );
+        assert_eq!(split_archive_location("/music/wow!/01.flac"), None);
+        assert_eq!(split_archive_location("/music/album.zip!/"), None);
+        assert_eq!(split_archive_location("/music/album.zip"), None);
+    }
+
+    #[test]
+    fn archive_locations_round_trip() {
+        let archive = Path::new("/music/wow!/album.zip");
+        let location = archive_location(archive, "/disc 1/01.flac");
+
+        assert_eq!(location, "/music/wow!/album.zip!/disc 1/01.flac");
+        assert_eq!(
+            split_archive_location(&location),
+            Some((archive, "disc 1/01.flac"))
+        );
+    }
+
+    #[test]
+    fn reads_cached_entries_once() {
+        let dir = test_dir("cached");
+        let archive = dir.join("album.7z");
+        let entries: Vec<_> = (1..=3)
+            .map(|i| (format!("{:02}.flac", i), entry_data(i, 1000)))
+            .collect();
+        let named: Vec<_> = entries
+            .iter()
+            .map(|(n, d)| (n.as_str(), d.clone()))
+            .collect();
+        write_7z(&archive, &named);
+
+        let cache = ArchiveCache::default();
+
+        assert_eq!(
+            cache.read_entry(&archive, "01.flac"),
+            Some(entries[0].1.clone())
+        );
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 2000);
+
+        assert_eq!(
+            cache.read_entry(&archive, "02.flac"),
+            Some(entries[1].1.clone())
+        );
+        assert_eq!(
+            cache.read_entry(&archive, "03.flac"),
+            Some(entries[2].1.clone())
+        );
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 0);
+
+        // entries that were already taken from the cache are decompressed again
+        assert_eq!(
+            cache.read_entry(&archive, "02.flac"),
+            Some(entries[1].1.clone())
+        );
+        assert_eq!(cache.read_entry(&archive, "missing.flac"), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn drops_unread_entries_for_newer_blocks() {
+        let dir = test_dir("evict");
+        let first = dir.join("first.7z");
+        let second = dir.join("second.7z");
+        write_7z(
+            &first,
+            &[
+                ("a1", entry_data(3, 1000)),
+                ("a2", entry_data(5, 1000)),
+                ("a3", entry_data(7, 1000)),
+            ],
+        );
+        write_7z(
+            &second,
+            &[
+                ("b1", entry_data(9, 1000)),
+                ("b2", entry_data(11, 1000)),
+                ("b3", entry_data(13, 1000)),
+            ],
+        );
+
+        let cache = ArchiveCache::with_limit(2500);
+
+        cache.read_entry(&first, "a1").unwrap();
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 2000);
+
+        // the unread entries of the first archive make room for the second one
+        cache.read_entry(&second, "b1").unwrap();
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 2000);
+        assert_eq!(cache.read_entry(&second, "b2"), Some(entry_data(11, 1000)));
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 1000);
+
+        // dropped entries are still read from the archive
+        assert_eq!(cache.read_entry(&first, "a2"), Some(entry_data(5, 1000)));
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 1000);
+
+        cache.clear();
+        assert_eq!(cache.cached_size.load(Ordering::Relaxed), 0);
+        assert!(cache.seven_zip.lock().unwrap().is_empty());
+        assert!(cache.cached_blocks.lock().unwrap().is_empty());
+
+        assert_eq!(cache.read_entry(&second, "b3"), Some(entry_data(13, 1000)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1 +1,27 @@
+use super::traits::{MediaPlugin, MediaProvider};
+
+pub mod dsd;
+pub mod opus;
 pub mod symphonia;
+
+/// Returns the name of the built-in provider that decodes files with the specified extension.
+pub fn provider_name(ext: Option<&str>) -> &'static str {
+    match ext {
+        Some(ext)
+            if dsd::DsdProvider::SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|v| ext.eq_ignore_ascii_case(v)) =>
+        {
+            dsd::DsdProvider::NAME
+        }
+        _ => symphonia::SymphoniaProvider::NAME,
+    }
+}
+
+/// Creates the built-in provider with the specified name, as returned by provider_name.
+pub fn create_provider(name: &str) -> Box<dyn MediaProvider> {
+    match name {
+        dsd::DsdProvider::NAME => Box::new(dsd::DsdProvider::default()),
+        _ => Box::new(symphonia::SymphoniaProvider::default()),
+    }
+}
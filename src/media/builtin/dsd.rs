@@ -0,0 +1,797 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use symphonia::core::{
+    io::BufReader,
+    meta::{MetadataBuilder, StandardVisualKey, Visual},
+};
+use symphonia_metadata::id3v2::read_id3v2;
+use tracing::warn;
+
+use crate::media::{
+    builtin::symphonia::{break_tag, visual_usage},
+    errors::{
+        CloseError, FrameDurationError, MetadataError, OpenError, PlaybackReadError,
+        PlaybackStartError, PlaybackStopError, SeekError, TrackDurationError,
+    },
+    metadata::{Chapter, EmbeddedImage, Metadata, StreamInfo},
+    playback::{PlaybackFrame, Samples},
+    source::MediaSource,
+    traits::{MediaPlugin, MediaProvider},
+};
+
+/// The number of bytes read from each channel of a DFF file at a time.
+const DFF_FRAME_BYTES: usize = 4096;
+
+/// How the DSD data of a file is laid out.
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    /// DSF files store a block of `block_size` bytes for each channel in turn.
+    Dsf { block_size: usize, lsb_first: bool },
+    /// DFF files interleave single bytes, most significant bit first.
+    Dff,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DsdStream {
+    layout: Layout,
+    rate: u32,
+    channels: usize,
+    /// The number of samples in each channel.
+    samples: u64,
+    data_start: u64,
+    data_len: u64,
+}
+
+impl DsdStream {
+    /// The number of samples (bits) per channel in each frame.
+    fn frame_samples(&self) -> u64 {
+        match self.layout {
+            Layout::Dsf { block_size, .. } => block_size as u64 * 8,
+            Layout::Dff => DFF_FRAME_BYTES as u64 * 8,
+        }
+    }
+}
+
+fn read_u32_le(source: &mut dyn MediaSource) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    source.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64_le(source: &mut dyn MediaSource) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    source.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u64_be(source: &mut dyn MediaSource) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    source.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn read_id(source: &mut dyn MediaSource) -> io::Result<[u8; 4]> {
+    let mut buf = [0; 4];
+    source.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_vec(source: &mut dyn MediaSource, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    (&mut *source).take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads until the buffer is full or the source ends, returning the number of bytes read.
+fn read_full(source: &mut dyn MediaSource, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match source.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(read)
+}
+
+/// Reads the headers of a DSF file, along with the ID3v2 tag at the end of the file (if there is
+/// one).
+fn read_dsf(source: &mut dyn MediaSource) -> io::Result<(DsdStream, Option<Vec<u8>>)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a valid DSF file");
+
+    if &read_id(source)? != b"DSD " || read_u64_le(source)? != 28 {
+        return Err(invalid());
+    }
+
+    let _file_size = read_u64_le(source)?;
+    let metadata_offset = read_u64_le(source)?;
+
+    if &read_id(source)? != b"fmt " {
+        return Err(invalid());
+    }
+
+    let fmt_size = read_u64_le(source)?;
+    let _version = read_u32_le(source)?;
+    let format_id = read_u32_le(source)?;
+    let _channel_type = read_u32_le(source)?;
+    let channels = read_u32_le(source)? as usize;
+    let rate = read_u32_le(source)?;
+    let bits_per_sample = read_u32_le(source)?;
+    let samples = read_u64_le(source)?;
+    let block_size = read_u32_le(source)? as usize;
+
+    // only raw DSD is defined by the spec
+    if format_id != 0 || channels == 0 || rate == 0 || block_size == 0 {
+        return Err(invalid());
+    }
+
+    source.seek(SeekFrom::Start(28 + fmt_size))?;
+
+    if &read_id(source)? != b"data" {
+        return Err(invalid());
+    }
+
+    let data_len = read_u64_le(source)?.saturating_sub(12);
+    let data_start = source.stream_position()?;
+
+    let tag = if metadata_offset != 0 {
+        source.seek(SeekFrom::Start(metadata_offset))?;
+        Some(read_vec(source, u64::MAX)?)
+    } else {
+        None
+    };
+
+    let stream = DsdStream {
+        layout: Layout::Dsf {
+            block_size,
+            lsb_first: bits_per_sample == 1,
+        },
+        rate,
+        channels,
+        samples,
+        data_start,
+        data_len,
+    };
+
+    Ok((stream, tag))
+}
+
+/// Reads the chunks of a DFF (DSDIFF) file. The title and artist from the edited master
+/// information are copied into the metadata, and the (unofficial) ID3v2 chunk is returned if
+/// there is one.
+fn read_dff(
+    source: &mut dyn MediaSource,
+    metadata: &mut Metadata,
+) -> io::Result<(DsdStream, Option<Vec<u8>>)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a valid DFF file");
+
+    if &read_id(source)? != b"FRM8" {
+        return Err(invalid());
+    }
+
+    let form_end = read_u64_be(source)? + 12;
+
+    if &read_id(source)? != b"DSD " {
+        return Err(invalid());
+    }
+
+    let mut rate = None;
+    let mut channels = None;
+    let mut data = None;
+    let mut tag = None;
+
+    while source.stream_position()? + 12 <= form_end {
+        let id = read_id(source)?;
+        let size = read_u64_be(source)?;
+        let start = source.stream_position()?;
+        // chunks are padded to an even length
+        let end = start + size + size % 2;
+
+        match &id {
+            b"PROP" if &read_id(source)? == b"SND " => {
+                while source.stream_position()? + 12 <= start + size {
+                    let id = read_id(source)?;
+                    let size = read_u64_be(source)?;
+                    let start = source.stream_position()?;
+
+                    match &id {
+                        b"FS  " => {
+                            let mut buf = [0; 4];
+                            source.read_exact(&mut buf)?;
+                            rate = Some(u32::from_be_bytes(buf));
+                        }
+                        b"CHNL" => {
+                            let mut buf = [0; 2];
+                            source.read_exact(&mut buf)?;
+                            channels = Some(u16::from_be_bytes(buf) as usize);
+                        }
+                        // DST compressed files would need a DST decoder
+                        b"CMPR" if &read_id(source)? != b"DSD " => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::Unsupported,
+                                "compressed DFF files are not supported",
+                            ));
+                        }
+                        _ => (),
+                    }
+
+                    source.seek(SeekFrom::Start(start + size + size % 2))?;
+                }
+            }
+            b"DSD " => data = Some((start, size)),
+            b"DIIN" => {
+                while source.stream_position()? + 12 <= start + size {
+                    let id = read_id(source)?;
+                    let size = read_u64_be(source)?;
+                    let start = source.stream_position()?;
+
+                    if matches!(&id, b"DITI" | b"DIAR") {
+                        let mut buf = [0; 4];
+                        source.read_exact(&mut buf)?;
+                        let len = u32::from_be_bytes(buf) as u64;
+                        let text = read_vec(source, len.min(size.saturating_sub(4)))?;
+                        let text = String::from_utf8_lossy(&text).trim().to_string();
+
+                        if &id == b"DITI" {
+                            metadata.name = Some(text);
+                        } else {
+                            metadata.artist = Some(text);
+                        }
+                    }
+
+                    source.seek(SeekFrom::Start(start + size + size % 2))?;
+                }
+            }
+            b"ID3 " => tag = Some(read_vec(source, size)?),
+            _ => (),
+        }
+
+        source.seek(SeekFrom::Start(end))?;
+    }
+
+    let (Some(rate), Some(channels), Some((data_start, data_len))) = (rate, channels, data) else {
+        return Err(invalid());
+    };
+
+    if channels == 0 || rate == 0 {
+        return Err(invalid());
+    }
+
+    let stream = DsdStream {
+        layout: Layout::Dff,
+        rate,
+        channels,
+        samples: data_len / channels as u64 * 8,
+        data_start,
+        data_len,
+    };
+
+    Ok((stream, tag))
+}
+
+/// A MediaProvider for DSD audio, stored as DSF (Sony) or DFF (Philips DSDIFF) files. The samples
+/// are returned as-is, one bit per sample, and it's up to the device layer to turn them into
+/// something the device can play.
+#[derive(Default)]
+pub struct DsdProvider {
+    source: Option<Box<dyn MediaSource>>,
+    stream: Option<DsdStream>,
+    started: bool,
+    /// The current position in each channel, in samples.
+    position: u64,
+    current_metadata: Metadata,
+    pending_metadata_update: bool,
+    last_image: Option<Visual>,
+    images: Vec<Visual>,
+}
+
+impl DsdProvider {
+    fn read_tag(&mut self, data: &[u8]) {
+        let mut builder = MetadataBuilder::new();
+
+        // tags are frequently truncated, so whatever was read before the error is still used
+        if let Err(e) = read_id3v2(&mut BufReader::new(data), &mut builder) {
+            warn!("Could not read ID3v2 tag of DSD file: {}", e);
+        }
+
+        let revision = builder.metadata();
+
+        for tag in revision.tags() {
+            break_tag(&mut self.current_metadata, tag);
+        }
+
+        self.images.extend_from_slice(revision.visuals());
+    }
+
+    /// Reads the next frame of samples from the file, and splits it into channels.
+    fn read_frame(&mut self, stream: DsdStream) -> io::Result<Vec<Vec<bool>>> {
+        let source = self.source.as_mut().expect("source disappeared");
+        let frame_samples = stream.frame_samples();
+        let frame_bytes = frame_samples as usize / 8 * stream.channels;
+
+        let frame_start = match stream.layout {
+            // every frame is a whole group of blocks, so positions are always block-aligned
+            Layout::Dsf { .. } => self.position / frame_samples * frame_bytes as u64,
+            Layout::Dff => self.position / 8 * stream.channels as u64,
+        };
+
+        let remaining = stream.data_len.saturating_sub(frame_start);
+        let mut buf = vec![0; frame_bytes.min(remaining as usize)];
+
+        source.seek(SeekFrom::Start(stream.data_start + frame_start))?;
+        let read = read_full(source.as_mut(), &mut buf)?;
+        buf.truncate(read / stream.channels * stream.channels);
+
+        let unpack = |bytes: &mut dyn Iterator<Item = &u8>, lsb_first: bool| -> Vec<bool> {
+            bytes
+                .flat_map(|byte| {
+                    (0..8).map(move |i| {
+                        let shift = if lsb_first { i } else { 7 - i };
+                        byte >> shift & 1 == 1
+                    })
+                })
+                .collect()
+        };
+
+        let samples = (0..stream.channels)
+            .map(|channel| match stream.layout {
+                Layout::Dsf {
+                    block_size,
+                    lsb_first,
+                } => {
+                    let start = (channel * block_size).min(buf.len());
+                    let end = ((channel + 1) * block_size).min(buf.len());
+
+                    unpack(&mut buf[start..end].iter(), lsb_first)
+                }
+                Layout::Dff => unpack(
+                    &mut buf.iter().skip(channel).step_by(stream.channels),
+                    false,
+                ),
+            })
+            .collect();
+
+        Ok(samples)
+    }
+}
+
+impl MediaProvider for DsdProvider {
+    fn open(
+        &mut self,
+        mut source: Box<dyn MediaSource>,
+        ext: Option<String>,
+    ) -> Result<(), OpenError> {
+        // the tags of DSF files are at the end of the file
+        if !source.is_seekable() {
+            return Err(OpenError::UnsupportedFormat(
+                "DSD files can only be read from seekable sources".to_string(),
+            ));
+        }
+
+        self.close().ok();
+
+        let mut magic = [0; 4];
+        source
+            .read_exact(&mut magic)
+            .map_err(|_| OpenError::FileCorrupt("file is too short".to_string()))?;
+        source.rewind()?;
+
+        let result = match (&magic, ext.as_deref()) {
+            (b"DSD ", _) => read_dsf(source.as_mut()),
+            (b"FRM8", _) => read_dff(source.as_mut(), &mut self.current_metadata),
+            (_, Some("dsf" | "dff")) => {
+                return Err(OpenError::FileCorrupt(
+                    "missing DSF or DFF header".to_string(),
+                ))
+            }
+            _ => {
+                return Err(OpenError::UnsupportedFormat(
+                    "not a DSF or DFF file".to_string(),
+                ))
+            }
+        };
+
+        let (stream, tag) = result.map_err(|e| match e.kind() {
+            io::ErrorKind::Unsupported => OpenError::UnsupportedFormat(e.to_string()),
+            io::ErrorKind::InvalidData => OpenError::FileCorrupt(e.to_string()),
+            io::ErrorKind::UnexpectedEof => {
+                OpenError::FileCorrupt("unexpected end of file".to_string())
+            }
+            _ => OpenError::from(e),
+        })?;
+
+        if let Some(tag) = tag {
+            self.read_tag(&tag);
+        }
+
+        // files with more than one image frequently don't list the front cover first
+        self.last_image = self
+            .images
+            .iter()
+            .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+            .or(self.images.first())
+            .cloned();
+
+        self.source = Some(source);
+        self.stream = Some(stream);
+        self.pending_metadata_update = true;
+
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), CloseError> {
+        self.stop_playback().expect("invalid outcome");
+        self.source = None;
+        self.stream = None;
+        self.current_metadata = Metadata::default();
+        self.last_image = None;
+        self.images.clear();
+        Ok(())
+    }
+
+    fn start_playback(&mut self) -> Result<(), PlaybackStartError> {
+        if self.stream.is_none() {
+            return Err(PlaybackStartError::NothingOpen);
+        }
+
+        self.started = true;
+        self.position = 0;
+
+        Ok(())
+    }
+
+    fn stop_playback(&mut self) -> Result<(), PlaybackStopError> {
+        self.started = false;
+        self.position = 0;
+
+        Ok(())
+    }
+
+    fn seek(&mut self, time: f64) -> Result<f64, SeekError> {
+        let Some(stream) = self.stream else {
+            return Err(SeekError::NothingOpen);
+        };
+
+        let target = ((time.max(0.0) * stream.rate as f64) as u64).min(stream.samples);
+
+        // DSF can only be read a block at a time, DFF a byte at a time
+        self.position = match stream.layout {
+            Layout::Dsf { .. } => target / stream.frame_samples() * stream.frame_samples(),
+            Layout::Dff => target / 8 * 8,
+        };
+
+        Ok(self.position as f64 / stream.rate as f64)
+    }
+
+    fn read_samples(&mut self) -> Result<PlaybackFrame, PlaybackReadError> {
+        let Some(stream) = self.stream else {
+            return Err(PlaybackReadError::NothingOpen);
+        };
+
+        if !self.started {
+            return Err(PlaybackReadError::NeverStarted);
+        }
+
+        if self.position >= stream.samples {
+            return Err(PlaybackReadError::EOF);
+        }
+
+        let mut samples = self
+            .read_frame(stream)
+            .map_err(|_| PlaybackReadError::DecodeFatal)?;
+
+        let len = samples.first().map(|v| v.len()).unwrap_or_default() as u64;
+
+        if len == 0 {
+            return Err(PlaybackReadError::EOF);
+        }
+
+        // the last block of a DSF file is padded
+        let len = len.min(stream.samples - self.position);
+        samples.iter_mut().for_each(|v| v.truncate(len as usize));
+
+        self.position += len;
+
+        Ok(PlaybackFrame {
+            samples: Samples::DSD(samples),
+            rate: stream.rate,
+        })
+    }
+
+    fn frame_duration(&self) -> Result<u64, FrameDurationError> {
+        self.stream
+            .map(|v| v.frame_samples())
+            .ok_or(FrameDurationError::NothingOpen)
+    }
+
+    fn read_metadata(&mut self) -> Result<&Metadata, MetadataError> {
+        self.pending_metadata_update = false;
+
+        if self.stream.is_some() {
+            Ok(&self.current_metadata)
+        } else {
+            Err(MetadataError::NothingOpen)
+        }
+    }
+
+    fn metadata_updated(&self) -> bool {
+        self.pending_metadata_update
+    }
+
+    fn read_image(&mut self) -> Result<Option<Box<[u8]>>, MetadataError> {
+        if self.stream.is_some() {
+            Ok(self.last_image.take().map(|v| v.data))
+        } else {
+            Err(MetadataError::NothingOpen)
+        }
+    }
+
+    fn read_images(&mut self) -> Result<Vec<EmbeddedImage>, MetadataError> {
+        if self.stream.is_some() {
+            Ok(self
+                .images
+                .iter()
+                .map(|visual| EmbeddedImage {
+                    usage: visual_usage(visual.usage),
+                    mime: visual.media_type.clone(),
+                    data: visual.data.clone(),
+                })
+                .collect())
+        } else {
+            Err(MetadataError::NothingOpen)
+        }
+    }
+
+    fn chapters(&mut self) -> Result<Vec<Chapter>, MetadataError> {
+        if self.stream.is_some() {
+            Ok(Vec::new())
+        } else {
+            Err(MetadataError::NothingOpen)
+        }
+    }
+
+    fn probe_info(&mut self) -> Result<StreamInfo, PlaybackStartError> {
+        let stream = self.stream.ok_or(PlaybackStartError::NothingOpen)?;
+
+        Ok(StreamInfo {
+            codec: Some("dsd".to_string()),
+            sample_rate: Some(stream.rate),
+            bit_depth: Some(1),
+            channels: Some(stream.channels as u16),
+            bitrate: Some(stream.rate * stream.channels as u32),
+            lossless: Some(true),
+            duration: Some(stream.samples / stream.rate as u64),
+        })
+    }
+
+    fn duration_secs(&self) -> Result<u64, TrackDurationError> {
+        match self.stream {
+            Some(stream) if self.started => Ok(stream.samples / stream.rate as u64),
+            Some(_) => Err(TrackDurationError::NeverStarted),
+            None => Err(TrackDurationError::NothingOpen),
+        }
+    }
+
+    fn position_secs(&self) -> Result<u64, TrackDurationError> {
+        match self.stream {
+            Some(stream) if self.started => Ok(self.position / stream.rate as u64),
+            Some(_) => Err(TrackDurationError::NeverStarted),
+            None => Err(TrackDurationError::NothingOpen),
+        }
+    }
+}
+
+impl MediaPlugin for DsdProvider {
+    const NAME: &'static str = "DSD";
+
+    const VERSION: &'static str = "0.1.0";
+
+    const SUPPORTED_MIMETYPES: &'static [&'static str] = &["audio/dsf", "audio/dff"];
+
+    const PROVIDES_DECODING: bool = true;
+    const PROVIDES_METADATA: bool = true;
+    const ALWAYS_CHECK_METADATA: bool = false;
+
+    const SUPPORTED_EXTENSIONS: &'static [&'static str] = &["dsf", "dff"];
+    const INDEXING_SUPPORTED: bool = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const RATE: u32 = 2822400;
+
+    /// Builds a DSF file whose samples are stored least significant bit first.
+    fn dsf(channels: u32, block_size: u32, samples: u64, data: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+
+        file.extend_from_slice(b"DSD ");
+        file.extend_from_slice(&28u64.to_le_bytes());
+        file.extend_from_slice(&(92 + data.len() as u64).to_le_bytes());
+        file.extend_from_slice(&0u64.to_le_bytes());
+
+        file.extend_from_slice(b"fmt ");
+        file.extend_from_slice(&52u64.to_le_bytes());
+        for value in [1, 0, 2, channels, RATE, 1] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file.extend_from_slice(&samples.to_le_bytes());
+        file.extend_from_slice(&block_size.to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&(12 + data.len() as u64).to_le_bytes());
+        file.extend_from_slice(data);
+
+        file
+    }
+
+    /// Builds a DFF chunk, padded to an even length.
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u64).to_be_bytes());
+        chunk.extend_from_slice(body);
+
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+
+        chunk
+    }
+
+    fn dff(channels: u16, compression: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut cmpr = compression.to_vec();
+        cmpr.push(14);
+        cmpr.extend_from_slice(b"not compressed");
+
+        let mut chnl = channels.to_be_bytes().to_vec();
+        for _ in 0..channels {
+            chnl.extend_from_slice(b"SLFT");
+        }
+
+        let mut prop = b"SND ".to_vec();
+        prop.extend(chunk(b"FS  ", &RATE.to_be_bytes()));
+        prop.extend(chunk(b"CHNL", &chnl));
+        prop.extend(chunk(b"CMPR", &cmpr));
+
+        let mut diti = 5u32.to_be_bytes().to_vec();
+        diti.extend_from_slice(b"Title");
+
+        let mut form = b"DSD ".to_vec();
+        form.extend(chunk(b"FVER", &[1, 5, 0, 0]));
+        form.extend(chunk(b"PROP", &prop));
+        form.extend(chunk(b"DIIN", &chunk(b"DITI", &diti)));
+        form.extend(chunk(b"DSD ", data));
+
+        chunk(b"FRM8", &form)
+    }
+
+    fn open(file: Vec<u8>, ext: &str) -> Result<DsdProvider, OpenError> {
+        let mut provider = DsdProvider::default();
+        provider.open(Box::new(Cursor::new(file)), Some(ext.to_string()))?;
+        provider.start_playback().unwrap();
+        Ok(provider)
+    }
+
+    /// Reads every frame, packing the samples of each channel back into bytes.
+    fn read_bytes(provider: &mut DsdProvider, lsb_first: bool) -> Vec<Vec<u8>> {
+        let mut channels: Vec<Vec<bool>> = Vec::new();
+
+        loop {
+            let samples = match provider.read_samples() {
+                Ok(PlaybackFrame {
+                    samples: Samples::DSD(samples),
+                    rate,
+                }) => {
+                    assert_eq!(rate, RATE);
+                    samples
+                }
+                Ok(_) => panic!("DSD files should be read as DSD"),
+                Err(PlaybackReadError::EOF) => break,
+                Err(e) => panic!("could not read samples: {:?}", e),
+            };
+
+            channels.resize(samples.len(), Vec::new());
+            channels
+                .iter_mut()
+                .zip(samples)
+                .for_each(|(channel, bits)| channel.extend(bits));
+        }
+
+        channels
+            .iter()
+            .map(|bits| {
+                bits.chunks(8)
+                    .map(|byte| {
+                        byte.iter().enumerate().fold(0, |value, (i, bit)| {
+                            let shift = if lsb_first { i } else { 7 - i };
+                            value | (*bit as u8) << shift
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_dsf_blocks() {
+        // two blocks for each channel, the last of which is padded
+        let data = [
+            0x01, 0x02, 0x03, 0x04, 0x81, 0x82, 0x83, 0x84, 0x05, 0x06, 0x07, 0x00, 0x85, 0x86,
+            0x87, 0x00,
+        ];
+        let mut provider = open(dsf(2, 4, 56, &data), "dsf").unwrap();
+
+        let info = provider.probe_info().unwrap();
+        assert_eq!(info.sample_rate, Some(RATE));
+        assert_eq!(info.channels, Some(2));
+        assert_eq!(provider.frame_duration().unwrap(), 32);
+
+        assert_eq!(
+            read_bytes(&mut provider, true),
+            vec![
+                vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07],
+                vec![0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87],
+            ]
+        );
+    }
+
+    #[test]
+    fn seeks_dsf_to_blocks() {
+        let data = [0x01, 0x02, 0x81, 0x82, 0x03, 0x04, 0x83, 0x84];
+        let mut provider = open(dsf(2, 2, 32, &data), "dsf").unwrap();
+
+        // 20 samples in is inside of the second block
+        provider.seek(20.0 / RATE as f64).unwrap();
+
+        assert_eq!(
+            read_bytes(&mut provider, true),
+            vec![vec![0x03, 0x04], vec![0x83, 0x84]]
+        );
+    }
+
+    #[test]
+    fn reads_dff_chunks() {
+        let data = [0x10, 0x90, 0x20, 0xA0, 0x30, 0xB0];
+        let mut provider = open(dff(2, b"DSD ", &data), "dff").unwrap();
+
+        assert_eq!(
+            provider.read_metadata().unwrap().name.as_deref(),
+            Some("Title")
+        );
+
+        let info = provider.probe_info().unwrap();
+        assert_eq!(info.sample_rate, Some(RATE));
+        assert_eq!(info.channels, Some(2));
+
+        assert_eq!(
+            read_bytes(&mut provider, false),
+            vec![vec![0x10, 0x20, 0x30], vec![0x90, 0xA0, 0xB0]]
+        );
+    }
+
+    #[test]
+    fn rejects_compressed_dff() {
+        assert!(matches!(
+            open(dff(2, b"DST ", &[0; 4]), "dff"),
+            Err(OpenError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let file = dsf(2, 4, 56, &[0; 16]);
+
+        assert!(matches!(
+            open(file[..40].to_vec(), "dsf"),
+            Err(OpenError::FileCorrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Mutex;
+
+use audiopus::{
+    coder::{Decoder as LibopusDecoder, GenericCtl},
+    packet::Packet as OpusPacket,
+    Channels as OpusChannels, MutSignals, SampleRate,
+};
+use symphonia::core::{
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels, Signal, SignalSpec},
+    codecs::{
+        CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS,
+    },
+    errors::{decode_error, unsupported_error, Error, Result},
+    formats::Packet,
+    support_codec,
+};
+
+/// The longest frame an Opus packet can contain (120ms at 48kHz).
+const MAX_FRAME_LENGTH: usize = 5760;
+
+/// A Symphonia decoder for Opus, backed by libopus. Symphonia can already demux Opus from OGG and
+/// Matroska/WebM containers, but doesn't ship a decoder for it, so this is registered alongside
+/// the built-in codecs.
+///
+/// Only mono and stereo streams are supported. Surround streams use Opus' multistream mode, which
+/// isn't exposed by the bindings.
+pub struct OpusDecoder {
+    params: CodecParameters,
+    // libopus decoders can be sent between threads but not shared, and Symphonia requires both
+    decoder: Mutex<LibopusDecoder>,
+    channels: usize,
+    /// The number of samples at the start of the stream that are only there to prime the decoder,
+    /// and have to be discarded.
+    pre_skip: u64,
+    interleaved: Vec<f32>,
+    buf: AudioBuffer<f32>,
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _: &DecoderOptions) -> Result<Self> {
+        // the OpusHead identification header, as stored by both OGG and Matroska
+        let head = params
+            .extra_data
+            .as_deref()
+            .filter(|v| v.len() >= 19 && v.starts_with(b"OpusHead"));
+
+        let channels = params
+            .channels
+            .map(|v| v.count())
+            .or_else(|| head.map(|v| v[9] as usize))
+            .unwrap_or(2);
+
+        let (opus_channels, layout) = match channels {
+            1 => (OpusChannels::Mono, Channels::FRONT_LEFT),
+            2 => (
+                OpusChannels::Stereo,
+                Channels::FRONT_LEFT | Channels::FRONT_RIGHT,
+            ),
+            _ => return unsupported_error("opus: surround streams are not supported"),
+        };
+
+        // mapping families other than 0 can split even a stereo stream into several streams
+        if head.is_some_and(|v| v[18] != 0 && v.get(19) != Some(&1)) {
+            return unsupported_error("opus: multistream streams are not supported");
+        }
+
+        let decoder = LibopusDecoder::new(SampleRate::Hz48000, opus_channels)
+            .map_err(|_| Error::Unsupported("opus: could not create decoder"))?;
+
+        // the output gain is a Q7.8 fixed-point number of decibels that has to be applied to all
+        // decoded audio
+        if let Some(head) = head {
+            let gain = i16::from_le_bytes([head[16], head[17]]);
+
+            if gain != 0 && decoder.set_gain(gain as i32).is_err() {
+                return decode_error("opus: invalid output gain");
+            }
+        }
+
+        Ok(OpusDecoder {
+            params: params.clone(),
+            decoder: Mutex::new(decoder),
+            channels,
+            pre_skip: head
+                .map(|v| u16::from_le_bytes([v[10], v[11]]) as u64)
+                .unwrap_or_default(),
+            interleaved: vec![0.0; MAX_FRAME_LENGTH * channels],
+            buf: AudioBuffer::new(MAX_FRAME_LENGTH as u64, SignalSpec::new(48_000, layout)),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus")]
+    }
+
+    fn reset(&mut self) {
+        if let Ok(decoder) = self.decoder.get_mut() {
+            decoder.reset_state().ok();
+        }
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        self.buf.clear();
+
+        let Ok(input) = OpusPacket::try_from(packet.buf()) else {
+            return decode_error("opus: empty packet");
+        };
+
+        let Ok(output) = MutSignals::try_from(&mut self.interleaved[..]) else {
+            return decode_error("opus: invalid output buffer");
+        };
+
+        let decoder = self
+            .decoder
+            .get_mut()
+            .map_err(|_| Error::DecodeError("opus: decoder poisoned"))?;
+
+        let frames = decoder
+            .decode_float(Some(input), output, false)
+            .map_err(|_| Error::DecodeError("opus: invalid packet"))?;
+
+        self.buf.render_reserved(Some(frames));
+
+        for channel in 0..self.channels {
+            let samples = self.interleaved[..frames * self.channels]
+                .iter()
+                .skip(channel)
+                .step_by(self.channels);
+
+            for (dest, sample) in self.buf.chan_mut(channel).iter_mut().zip(samples) {
+                *dest = *sample;
+            }
+        }
+
+        // Symphonia only trims the padding at the end of the stream, not the pre-skip
+        let skip = match packet.trim_start() {
+            0 => self.pre_skip.saturating_sub(packet.ts()).min(frames as u64) as usize,
+            trim => trim as usize,
+        };
+
+        self.buf.trim(skip, packet.trim_end() as usize);
+
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        FinalizeResult::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buf.as_audio_buffer_ref()
+    }
+}
@@ -1,30 +1,150 @@
-use std::fs::File;
+use std::{
+    collections::BTreeMap,
+    io::{self, Seek},
+    sync::OnceLock,
+};
 
+use chrono::{DateTime, TimeZone, Utc};
 use intx::{I24, U24};
 use symphonia::{
     core::{
         audio::{AudioBufferRef, Signal},
-        codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+        codecs::{
+            CodecRegistry, CodecType, Decoder, DecoderOptions, CODEC_TYPE_AAC, CODEC_TYPE_ALAC,
+            CODEC_TYPE_FLAC, CODEC_TYPE_MONKEYS_AUDIO, CODEC_TYPE_NULL, CODEC_TYPE_PCM_ALAW,
+            CODEC_TYPE_PCM_MULAW, CODEC_TYPE_TTA, CODEC_TYPE_WAVPACK,
+        },
         errors::Error,
         formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
-        io::MediaSourceStream,
-        meta::{MetadataOptions, StandardTagKey, Tag, Value, Visual},
+        io::{MediaSource, MediaSourceStream},
+        meta::{MetadataOptions, StandardTagKey, StandardVisualKey, Tag, Value, Visual},
         probe::{Hint, ProbeResult},
         units::{Time, TimeBase},
     },
-    default::get_codecs,
+    default::register_enabled_codecs,
 };
 
 use crate::media::{
+    builtin::opus::OpusDecoder,
+    chapters::{finish_vorbis_chapters, read_container_chapters, read_vorbis_chapter},
     errors::{
         CloseError, FrameDurationError, MetadataError, OpenError, PlaybackReadError,
         PlaybackStartError, PlaybackStopError, SeekError, TrackDurationError,
     },
-    metadata::Metadata,
+    metadata::{Chapter, EmbeddedImage, ImageUsage, Metadata, StreamInfo},
     playback::{PlaybackFrame, Samples},
     traits::{MediaPlugin, MediaProvider},
 };
 
+/// Returns Symphonia's built-in codecs, along with the decoders we provide for codecs Symphonia can
+/// demux but not decode.
+fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+
+    CODECS.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        register_enabled_codecs(&mut registry);
+        registry.register_all::<OpusDecoder>();
+        registry
+    })
+}
+
+/// Converts an error from Symphonia's probe into an OpenError.
+fn open_error(error: Error) -> OpenError {
+    match error {
+        Error::Unsupported(_) => OpenError::UnsupportedFormat(error.to_string()),
+        Error::IoError(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            OpenError::FileCorrupt("unexpected end of file".to_string())
+        }
+        Error::IoError(e) => OpenError::from(e),
+        Error::DecodeError(_) | Error::LimitError(_) => OpenError::FileCorrupt(error.to_string()),
+        _ => OpenError::Unknown,
+    }
+}
+
+fn is_lossless(codec: CodecType, short_name: &str) -> bool {
+    match codec {
+        CODEC_TYPE_FLAC
+        | CODEC_TYPE_ALAC
+        | CODEC_TYPE_WAVPACK
+        | CODEC_TYPE_MONKEYS_AUDIO
+        | CODEC_TYPE_TTA => true,
+        // A-law and μ-law are companded, so they lose precision
+        CODEC_TYPE_PCM_ALAW | CODEC_TYPE_PCM_MULAW => false,
+        _ => short_name.starts_with("pcm_"),
+    }
+}
+
+/// Reads the bit depth and channel count from an ALAC magic cookie, which is the only place the
+/// MP4 demuxer leaves them. The cookie is sometimes wrapped in `frma` and `alac` atoms.
+fn alac_cookie_format(cookie: &[u8]) -> Option<(u32, u16)> {
+    let config = match cookie.get(4..8) {
+        Some(b"frma") => cookie.get(24..)?,
+        _ => cookie,
+    };
+
+    Some((*config.get(5)? as u32, *config.get(9)? as u16))
+}
+
+/// Reads the channel count from an AAC AudioSpecificConfig, which the MP4 demuxer leaves for the
+/// decoder to parse. Layouts described by a program config element (configuration 0) aren't read.
+fn aac_config_channels(config: &[u8]) -> Option<u16> {
+    let mut bytes = [0; 8];
+    let len = config.len().min(8);
+    bytes[..len].copy_from_slice(&config[..len]);
+    let bits = u64::from_be_bytes(bytes);
+
+    // the object type and the sample rate index are both followed by more bits when escaped
+    let mut offset = if bits >> 59 == 31 { 11 } else { 5 };
+    offset += if (bits >> (60 - offset)) & 0xf == 15 {
+        28
+    } else {
+        4
+    };
+
+    if len * 8 < offset + 4 {
+        return None;
+    }
+
+    match (bits >> (60 - offset)) & 0xf {
+        v @ 1..=6 => Some(v as u16),
+        7 => Some(8),
+        _ => None,
+    }
+}
+
+/// Adds up the durations of every packet in a track. This is only needed for files whose headers
+/// don't include the length of the stream (such as MP3 files without a Xing header), and only
+/// requires demuxing the file, not decoding it. The reader is returned to the start of the file
+/// afterwards.
+fn count_frames(format: &mut dyn FormatReader, track_id: u32) -> Result<u64, PlaybackStartError> {
+    let mut frames = 0;
+
+    let error = loop {
+        match format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => frames += packet.dur,
+            Ok(_) => (),
+            Err(e) => break e,
+        }
+    };
+
+    // files that are damaged part of the way through are still indexed, the same as they'd play
+    let reached_end = matches!(
+        &error,
+        Error::IoError(e) if e.kind() == io::ErrorKind::UnexpectedEof
+    ) || matches!(error, Error::ResetRequired);
+
+    if !reached_end && frames == 0 {
+        return Err(PlaybackStartError::BrokenContainer(error.to_string()));
+    }
+
+    format
+        .seek(SeekMode::Coarse, SeekTo::TimeStamp { ts: 0, track_id })
+        .ok();
+
+    Ok(frames)
+}
+
 #[derive(Default)]
 pub struct SymphoniaProvider {
     format: Option<Box<dyn FormatReader>>,
@@ -32,123 +152,179 @@ pub struct SymphoniaProvider {
     current_track: u32,
     current_duration: u64,
     current_length: Option<u64>,
+    /// The size of the current file in bytes, if it's known (live streams have no size).
+    current_byte_len: Option<u64>,
     current_position: u64,
     current_timebase: Option<TimeBase>,
     decoder: Option<Box<dyn Decoder>>,
     pending_metadata_update: bool,
     last_image: Option<Visual>,
+    images: Vec<Visual>,
+    chapters: Vec<Chapter>,
+    vorbis_chapters: BTreeMap<String, Chapter>,
+}
+
+pub(crate) fn visual_usage(usage: Option<StandardVisualKey>) -> ImageUsage {
+    match usage {
+        Some(StandardVisualKey::FrontCover) => ImageUsage::FrontCover,
+        Some(StandardVisualKey::BackCover) => ImageUsage::BackCover,
+        Some(StandardVisualKey::Leaflet) => ImageUsage::Booklet,
+        Some(StandardVisualKey::Media) => ImageUsage::Media,
+        Some(StandardVisualKey::LeadArtistPerformerSoloist)
+        | Some(StandardVisualKey::ArtistPerformer)
+        | Some(StandardVisualKey::BandOrchestra) => ImageUsage::Artist,
+        _ => ImageUsage::Other,
+    }
+}
+
+/// Reads a tag that's either set or not. Vorbis comments and APEv2 store these as text.
+fn flag_value(value: &Value) -> bool {
+    match value {
+        Value::Boolean(v) => *v,
+        Value::Flag => true,
+        Value::String(v) => v == "1" || v.eq_ignore_ascii_case("true"),
+        Value::UnsignedInt(v) => *v != 0,
+        _ => false,
+    }
+}
+
+/// Parses a release date. Many files only store the year, which isn't accepted by `dateparser`.
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    dateparser::parse(value).ok().or_else(|| {
+        let year = value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|_| value.trim().len() == 4)?;
+        Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).single()
+    })
+}
+
+/// Copies a tag into the matching field of the metadata, if there is one.
+pub(crate) fn break_tag(metadata: &mut Metadata, tag: &Tag) {
+    match tag.std_key {
+        Some(StandardTagKey::TrackTitle) => metadata.name = Some(tag.value.to_string()),
+        Some(StandardTagKey::Artist) => metadata.artist = Some(tag.value.to_string()),
+        Some(StandardTagKey::AlbumArtist) => metadata.album_artist = Some(tag.value.to_string()),
+        Some(StandardTagKey::OriginalArtist) => {
+            metadata.original_artist = Some(tag.value.to_string())
+        }
+        Some(StandardTagKey::Composer) => metadata.composer = Some(tag.value.to_string()),
+        Some(StandardTagKey::Album) => metadata.album = Some(tag.value.to_string()),
+        Some(StandardTagKey::Genre) => metadata.genre = Some(tag.value.to_string()),
+        Some(StandardTagKey::ContentGroup) => metadata.grouping = Some(tag.value.to_string()),
+        Some(StandardTagKey::Bpm) => {
+            metadata.bpm = match &tag.value {
+                Value::String(v) => v.clone().parse().ok(),
+                Value::UnsignedInt(v) => Some(*v),
+                _ => None,
+            }
+        }
+        Some(StandardTagKey::Compilation) => metadata.compilation = flag_value(&tag.value),
+        Some(StandardTagKey::Date) => metadata.date = parse_date(&tag.value.to_string()),
+        Some(StandardTagKey::TrackNumber) => {
+            metadata.track_current = match &tag.value {
+                Value::String(v) => v.clone().parse().ok(),
+                Value::UnsignedInt(v) => Some(*v),
+                _ => None,
+            }
+        }
+        Some(StandardTagKey::TrackTotal) => {
+            metadata.track_max = match &tag.value {
+                Value::String(v) => v.clone().parse().ok(),
+                Value::UnsignedInt(v) => Some(*v),
+                _ => None,
+            }
+        }
+        Some(StandardTagKey::DiscNumber) => {
+            metadata.disc_current = match &tag.value {
+                Value::String(v) => v.clone().parse().ok(),
+                Value::UnsignedInt(v) => Some(*v),
+                _ => None,
+            }
+        }
+        Some(StandardTagKey::DiscTotal) => {
+            metadata.disc_max = match &tag.value {
+                Value::String(v) => v.clone().parse().ok(),
+                Value::UnsignedInt(v) => Some(*v),
+                _ => None,
+            }
+        }
+        Some(StandardTagKey::Label) => metadata.label = Some(tag.value.to_string()),
+        Some(StandardTagKey::IdentCatalogNumber) => metadata.catalog = Some(tag.value.to_string()),
+        Some(StandardTagKey::IdentIsrc) => metadata.isrc = Some(tag.value.to_string()),
+        Some(StandardTagKey::MusicBrainzAlbumId) => {
+            metadata.release_mbid = Some(tag.value.to_string())
+        }
+        Some(StandardTagKey::SortAlbum) => metadata.sort_album = Some(tag.value.to_string()),
+        Some(StandardTagKey::SortAlbumArtist) => metadata.artist_sort = Some(tag.value.to_string()),
+        // Symphonia doesn't map the compilation flag for Vorbis comments
+        None if tag.key.eq_ignore_ascii_case("compilation") => {
+            metadata.compilation = flag_value(&tag.value)
+        }
+        None if tag.key.eq_ignore_ascii_case("cuesheet") => {
+            metadata.cuesheet = Some(tag.value.to_string())
+        }
+        _ => (),
+    }
 }
 
 impl SymphoniaProvider {
     fn break_metadata(&mut self, tags: &[Tag]) {
         for tag in tags {
-            match tag.std_key {
-                Some(StandardTagKey::TrackTitle) => {
-                    self.current_metadata.name = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::Artist) => {
-                    self.current_metadata.artist = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::AlbumArtist) => {
-                    self.current_metadata.album_artist = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::OriginalArtist) => {
-                    self.current_metadata.original_artist = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::Composer) => {
-                    self.current_metadata.composer = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::Album) => {
-                    self.current_metadata.album = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::Genre) => {
-                    self.current_metadata.genre = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::ContentGroup) => {
-                    self.current_metadata.grouping = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::Bpm) => {
-                    self.current_metadata.bpm = match &tag.value {
-                        Value::String(v) => v.clone().parse().ok(),
-                        Value::UnsignedInt(v) => Some(*v),
-                        _ => None,
-                    }
-                }
-                Some(StandardTagKey::Compilation) => {
-                    self.current_metadata.compilation = match tag.value {
-                        Value::Boolean(v) => v,
-                        Value::Flag => true,
-                        _ => false,
-                    }
-                }
-                Some(StandardTagKey::Date) => {
-                    self.current_metadata.date =
-                        Some(dateparser::parse(&tag.value.to_string()).ok()).flatten();
-                }
-                Some(StandardTagKey::TrackNumber) => {
-                    self.current_metadata.track_current = match &tag.value {
-                        Value::String(v) => v.clone().parse().ok(),
-                        Value::UnsignedInt(v) => Some(*v),
-                        _ => None,
-                    }
-                }
-                Some(StandardTagKey::TrackTotal) => {
-                    self.current_metadata.track_max = match &tag.value {
-                        Value::String(v) => v.clone().parse().ok(),
-                        Value::UnsignedInt(v) => Some(*v),
-                        _ => None,
-                    }
-                }
-                Some(StandardTagKey::DiscNumber) => {
-                    self.current_metadata.disc_current = match &tag.value {
-                        Value::String(v) => v.clone().parse().ok(),
-                        Value::UnsignedInt(v) => Some(*v),
-                        _ => None,
-                    }
-                }
-                Some(StandardTagKey::DiscTotal) => {
-                    self.current_metadata.disc_max = match &tag.value {
-                        Value::String(v) => v.clone().parse().ok(),
-                        Value::UnsignedInt(v) => Some(*v),
-                        _ => None,
-                    }
-                }
-                Some(StandardTagKey::Label) => {
-                    self.current_metadata.label = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::IdentCatalogNumber) => {
-                    self.current_metadata.catalog = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::IdentIsrc) => {
-                    self.current_metadata.isrc = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::SortAlbum) => {
-                    self.current_metadata.sort_album = Some(tag.value.to_string())
-                }
-                Some(StandardTagKey::SortAlbumArtist) => {
-                    self.current_metadata.artist_sort = Some(tag.value.to_string())
-                }
-                _ => (),
+            if tag.std_key.is_none() && tag.key.to_ascii_uppercase().starts_with("CHAPTER") {
+                read_vorbis_chapter(&mut self.vorbis_chapters, &tag.key, &tag.value.to_string());
+            } else {
+                break_tag(&mut self.current_metadata, tag);
             }
         }
     }
 
+    fn select_front_cover(&mut self) {
+        // files with more than one image frequently don't list the front cover first
+        self.last_image = self
+            .images
+            .iter()
+            .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+            .or(self.images.first())
+            .cloned();
+    }
+
+    /// Replaces the current metadata with a new revision read during playback. Each revision is
+    /// complete, so nothing from the previous revision is kept.
+    fn apply_metadata_revision(&mut self, tags: &[Tag], visuals: Vec<Visual>) {
+        self.current_metadata = Metadata::default();
+        self.break_metadata(tags);
+
+        // chapters are only read when the file is opened
+        self.vorbis_chapters.clear();
+
+        self.images = visuals;
+        self.select_front_cover();
+
+        self.pending_metadata_update = true;
+    }
+
     fn read_base_metadata(&mut self, probed: &mut ProbeResult) {
         self.current_metadata = Metadata::default();
         self.last_image = None;
+        self.images.clear();
+        self.vorbis_chapters.clear();
 
         if let Some(metadata) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
             self.break_metadata(metadata.tags());
-            if !metadata.visuals().is_empty() {
-                self.last_image = Some(metadata.visuals()[0].clone());
-            }
+            self.images.extend_from_slice(metadata.visuals());
         }
 
         if let Some(metadata) = probed.format.metadata().current() {
             self.break_metadata(metadata.tags());
-            if !metadata.visuals().is_empty() {
-                self.last_image = Some(metadata.visuals()[0].clone());
-            }
+            self.images.extend_from_slice(metadata.visuals());
+        }
+
+        self.select_front_cover();
+
+        if self.chapters.is_empty() {
+            self.chapters = finish_vorbis_chapters(std::mem::take(&mut self.vorbis_chapters));
         }
 
         self.pending_metadata_update = true;
@@ -156,10 +332,29 @@ impl SymphoniaProvider {
 }
 
 impl MediaProvider for SymphoniaProvider {
-    fn open(&mut self, file: File, ext: Option<String>) -> Result<(), OpenError> {
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    fn open(
+        &mut self,
+        mut source: Box<dyn MediaSource>,
+        ext: Option<String>,
+    ) -> Result<(), OpenError> {
+        // symphonia doesn't expose ID3 or MP4 chapters, so they're read from the file directly
+        if source.is_seekable() {
+            self.chapters = read_container_chapters(&mut source);
+            source.rewind()?;
+        } else {
+            self.chapters.clear();
+        }
+
+        self.current_byte_len = source.byte_len();
+
+        let mss = MediaSourceStream::new(source, Default::default());
         let meta_opts: MetadataOptions = Default::default();
-        let fmt_opts: FormatOptions = Default::default();
+        // Opus (and MP3) streams are padded out to a whole number of frames at the end, which
+        // Symphonia only trims when gapless playback is on
+        let fmt_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
 
         let mut probed = if let Some(ext) = ext {
             let mut hint = Hint::new();
@@ -167,13 +362,13 @@ impl MediaProvider for SymphoniaProvider {
 
             symphonia::default::get_probe()
                 .format(&hint, mss, &fmt_opts, &meta_opts)
-                .map_err(|_| OpenError::UnsupportedFormat)?
+                .map_err(open_error)?
         } else {
             let hint = Hint::new();
 
             symphonia::default::get_probe()
                 .format(&hint, mss, &fmt_opts, &meta_opts)
-                .map_err(|_| OpenError::UnsupportedFormat)?
+                .map_err(open_error)?
         };
 
         self.read_base_metadata(&mut probed);
@@ -188,6 +383,8 @@ impl MediaProvider for SymphoniaProvider {
     fn close(&mut self) -> Result<(), CloseError> {
         self.stop_playback().expect("invalid outcome");
         self.current_metadata = Metadata::default();
+        self.images.clear();
+        self.chapters.clear();
         self.format = None;
         Ok(())
     }
@@ -200,21 +397,27 @@ impl MediaProvider for SymphoniaProvider {
                 .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
                 .ok_or(PlaybackStartError::NothingToPlay)?;
 
-            if let Some(frame_count) = track.codec_params.n_frames {
-                if let Some(tb) = track.codec_params.time_base {
+            if let Some(tb) = track.codec_params.time_base {
+                self.current_timebase = Some(tb);
+
+                // streams don't have a known length
+                if let Some(frame_count) = track.codec_params.n_frames {
                     self.current_length = Some(tb.calc_time(frame_count).seconds);
-                    self.current_timebase = Some(tb);
                 }
             }
 
             self.current_track = track.id;
 
             let dec_opts: DecoderOptions = Default::default();
-            self.decoder = Some(
-                get_codecs()
-                    .make(&track.codec_params, &dec_opts)
-                    .map_err(|_| PlaybackStartError::Undecodable)?,
-            );
+            self.decoder = Some(codecs().make(&track.codec_params, &dec_opts).map_err(|e| {
+                match codecs().get_codec(track.codec_params.codec) {
+                    Some(_) => PlaybackStartError::Undecodable(e.to_string()),
+                    None => PlaybackStartError::ContainerSupportedButNotCodec(format!(
+                        "codec {}",
+                        track.codec_params.codec
+                    )),
+                }
+            })?);
 
             Ok(())
         } else {
@@ -230,210 +433,222 @@ impl MediaProvider for SymphoniaProvider {
     }
 
     fn read_samples(&mut self) -> Result<PlaybackFrame, PlaybackReadError> {
-        if let Some(format) = &mut self.format {
-            // this has a loop because the next packet may not be from the current track
-            loop {
-                let packet = match format.next_packet() {
-                    Ok(packet) => packet,
-                    Err(Error::ResetRequired) => return Err(PlaybackReadError::EOF),
-                    Err(_) => {
-                        // TODO: Handle better
-                        return Err(PlaybackReadError::EOF);
-                    }
-                };
+        if self.format.is_none() {
+            return Err(PlaybackReadError::NothingOpen);
+        }
 
-                while !format.metadata().is_latest() {
-                    // TODO: handle metadata updates
-                    format.metadata().pop();
+        // this has a loop because the next packet may not be from the current track
+        loop {
+            let format = self.format.as_mut().expect("format disappeared");
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::ResetRequired) => return Err(PlaybackReadError::EOF),
+                Err(_) => {
+                    // TODO: Handle better
+                    return Err(PlaybackReadError::EOF);
                 }
+            };
 
-                if packet.track_id() != self.current_track {
-                    continue;
-                }
+            // chained streams (e.g. OGG) and some live streams change their tags mid-stream
+            let mut revision = None;
 
-                if let Some(decoder) = &mut self.decoder {
-                    match decoder.decode(&packet) {
-                        Ok(decoded) => {
-                            let rate = decoded.spec().rate;
-                            let channel_count = decoded.spec().channels.count();
-                            self.current_duration = decoded.capacity() as u64;
+            while !format.metadata().is_latest() {
+                format.metadata().pop();
 
-                            if let Some(tb) = &self.current_timebase {
-                                self.current_position = tb.calc_time(packet.ts()).seconds;
-                            }
+                revision = format
+                    .metadata()
+                    .current()
+                    .map(|v| (v.tags().to_vec(), v.visuals().to_vec()));
+            }
 
-                            match decoded {
-                                AudioBufferRef::U8(v) => {
-                                    let mut samples: Vec<Vec<u8>> = Vec::new();
+            if let Some((tags, visuals)) = revision {
+                self.apply_metadata_revision(&tags, visuals);
+            }
 
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
-                                    }
+            if packet.track_id() != self.current_track {
+                continue;
+            }
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Unsigned8(samples),
-                                    });
-                                }
-                                AudioBufferRef::U16(v) => {
-                                    let mut samples: Vec<Vec<u16>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
-                                    }
+            if let Some(decoder) = &mut self.decoder {
+                match decoder.decode(&packet) {
+                    Ok(decoded) => {
+                        let rate = decoded.spec().rate;
+                        let channel_count = decoded.spec().channels.count();
+                        self.current_duration = decoded.capacity() as u64;
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Unsigned16(samples),
-                                    });
-                                }
-                                AudioBufferRef::U24(v) => {
-                                    let mut samples: Vec<Vec<U24>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(
-                                                U24::try_from(sample.0)
-                                                    .expect("24bit number is not 24bits long"),
-                                            );
-                                        }
-                                    }
+                        if let Some(tb) = &self.current_timebase {
+                            self.current_position = tb.calc_time(packet.ts()).seconds;
+                        }
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Unsigned24(samples),
-                                    });
-                                }
-                                AudioBufferRef::U32(v) => {
-                                    let mut samples: Vec<Vec<u32>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
-                                    }
+                        match decoded {
+                            AudioBufferRef::U8(v) => {
+                                let mut samples: Vec<Vec<u8>> = Vec::new();
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Unsigned32(samples),
-                                    });
-                                }
-                                AudioBufferRef::S8(v) => {
-                                    let mut samples: Vec<Vec<i8>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
                                     }
-
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Signed8(samples),
-                                    });
                                 }
-                                AudioBufferRef::S16(v) => {
-                                    let mut samples: Vec<Vec<i16>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
-                                    }
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Signed16(samples),
-                                    });
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Unsigned8(samples),
+                                });
+                            }
+                            AudioBufferRef::U16(v) => {
+                                let mut samples: Vec<Vec<u16>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
+                                    }
                                 }
-                                AudioBufferRef::S24(v) => {
-                                    let mut samples: Vec<Vec<I24>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(
-                                                I24::try_from(sample.0)
-                                                    .expect("24bit number is not 24bits long"),
-                                            );
-                                        }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Unsigned16(samples),
+                                });
+                            }
+                            AudioBufferRef::U24(v) => {
+                                let mut samples: Vec<Vec<U24>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(
+                                            U24::try_from(sample.0)
+                                                .expect("24bit number is not 24bits long"),
+                                        );
                                     }
+                                }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Unsigned24(samples),
+                                });
+                            }
+                            AudioBufferRef::U32(v) => {
+                                let mut samples: Vec<Vec<u32>> = Vec::new();
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Signed24(samples),
-                                    });
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
+                                    }
                                 }
-                                AudioBufferRef::S32(v) => {
-                                    let mut samples: Vec<Vec<i32>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Unsigned32(samples),
+                                });
+                            }
+                            AudioBufferRef::S8(v) => {
+                                let mut samples: Vec<Vec<i8>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
                                     }
+                                }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Signed8(samples),
+                                });
+                            }
+                            AudioBufferRef::S16(v) => {
+                                let mut samples: Vec<Vec<i16>> = Vec::new();
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Signed32(samples),
-                                    });
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
+                                    }
                                 }
-                                AudioBufferRef::F32(v) => {
-                                    let mut samples: Vec<Vec<f32>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Signed16(samples),
+                                });
+                            }
+                            AudioBufferRef::S24(v) => {
+                                let mut samples: Vec<Vec<I24>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(
+                                            I24::try_from(sample.0)
+                                                .expect("24bit number is not 24bits long"),
+                                        );
                                     }
+                                }
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Float32(samples),
-                                    });
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Signed24(samples),
+                                });
+                            }
+                            AudioBufferRef::S32(v) => {
+                                let mut samples: Vec<Vec<i32>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
+                                    }
                                 }
-                                AudioBufferRef::F64(v) => {
-                                    let mut samples: Vec<Vec<f64>> = Vec::new();
-
-                                    for i in 0..channel_count {
-                                        samples.push(Vec::new());
-                                        for sample in v.chan(i) {
-                                            samples[i].push(*sample);
-                                        }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Signed32(samples),
+                                });
+                            }
+                            AudioBufferRef::F32(v) => {
+                                let mut samples: Vec<Vec<f32>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
                                     }
+                                }
 
-                                    return Ok(PlaybackFrame {
-                                        rate,
-                                        samples: Samples::Float64(samples),
-                                    });
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Float32(samples),
+                                });
+                            }
+                            AudioBufferRef::F64(v) => {
+                                let mut samples: Vec<Vec<f64>> = Vec::new();
+
+                                for i in 0..channel_count {
+                                    samples.push(Vec::new());
+                                    for sample in v.chan(i) {
+                                        samples[i].push(*sample);
+                                    }
                                 }
+
+                                return Ok(PlaybackFrame {
+                                    rate,
+                                    samples: Samples::Float64(samples),
+                                });
                             }
                         }
-                        Err(Error::IoError(_)) | Err(Error::DecodeError(_)) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(PlaybackReadError::DecodeFatal);
-                        }
                     }
-                } else {
-                    return Err(PlaybackReadError::NeverStarted);
+                    Err(Error::IoError(_)) | Err(Error::DecodeError(_)) => {
+                        continue;
+                    }
+                    Err(_) => {
+                        return Err(PlaybackReadError::DecodeFatal);
+                    }
                 }
+            } else {
+                return Err(PlaybackReadError::NeverStarted);
             }
-        } else {
-            Err(PlaybackReadError::NothingOpen)
         }
     }
 
@@ -475,6 +690,88 @@ impl MediaProvider for SymphoniaProvider {
         }
     }
 
+    fn read_images(&mut self) -> Result<Vec<EmbeddedImage>, MetadataError> {
+        if self.format.is_some() {
+            Ok(self
+                .images
+                .iter()
+                .map(|visual| EmbeddedImage {
+                    usage: visual_usage(visual.usage),
+                    mime: visual.media_type.clone(),
+                    data: visual.data.clone(),
+                })
+                .collect())
+        } else {
+            Err(MetadataError::NothingOpen)
+        }
+    }
+
+    fn chapters(&mut self) -> Result<Vec<Chapter>, MetadataError> {
+        if self.format.is_some() {
+            Ok(self.chapters.clone())
+        } else {
+            Err(MetadataError::NothingOpen)
+        }
+    }
+
+    fn probe_info(&mut self) -> Result<StreamInfo, PlaybackStartError> {
+        let format = self
+            .format
+            .as_mut()
+            .ok_or(PlaybackStartError::NothingOpen)?;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(PlaybackStartError::NothingToPlay)?;
+        let track_id = track.id;
+        let params = track.codec_params.clone();
+
+        let codec = codecs().get_codec(params.codec).ok_or_else(|| {
+            PlaybackStartError::ContainerSupportedButNotCodec(format!("codec {}", params.codec))
+        })?;
+
+        // counting packets would never finish on a live stream, and would interrupt playback
+        let frames = match params.n_frames {
+            Some(frames) => Some(frames),
+            None if self.current_byte_len.is_some() && self.decoder.is_none() => {
+                Some(count_frames(format.as_mut(), track_id)?)
+            }
+            None => None,
+        };
+
+        let length = frames.zip(params.time_base).map(|(n, tb)| tb.calc_time(n));
+        let bitrate = length
+            .map(|v| v.seconds as f64 + v.frac)
+            .filter(|v| *v > 0.0)
+            .zip(self.current_byte_len)
+            .map(|(secs, len)| (len as f64 * 8.0 / secs) as u32);
+
+        let alac_format = match params.codec {
+            CODEC_TYPE_ALAC => params.extra_data.as_deref().and_then(alac_cookie_format),
+            _ => None,
+        };
+        let aac_channels = match params.codec {
+            CODEC_TYPE_AAC => params.extra_data.as_deref().and_then(aac_config_channels),
+            _ => None,
+        };
+
+        Ok(StreamInfo {
+            codec: Some(codec.short_name.to_string()),
+            sample_rate: params.sample_rate,
+            bit_depth: params.bits_per_sample.or(alac_format.map(|(bits, _)| bits)),
+            channels: params
+                .channels
+                .map(|v| v.count() as u16)
+                .or(alac_format.map(|(_, channels)| channels))
+                .or(aac_channels),
+            bitrate,
+            lossless: Some(is_lossless(params.codec, codec.short_name)),
+            duration: length.map(|v| v.seconds),
+        })
+    }
+
     fn duration_secs(&self) -> Result<u64, TrackDurationError> {
         if self.decoder.is_none() {
             Err(TrackDurationError::NothingOpen)
@@ -488,14 +785,14 @@ impl MediaProvider for SymphoniaProvider {
     fn position_secs(&self) -> Result<u64, TrackDurationError> {
         if self.decoder.is_none() {
             Err(TrackDurationError::NothingOpen)
-        } else if self.current_length.is_none() {
+        } else if self.current_timebase.is_none() {
             Err(TrackDurationError::NeverStarted)
         } else {
             Ok(self.current_position)
         }
     }
 
-    fn seek(&mut self, time: f64) -> Result<(), SeekError> {
+    fn seek(&mut self, time: f64) -> Result<f64, SeekError> {
         let timebase = self.current_timebase;
         if let Some(format) = &mut self.format {
             let seek = format
@@ -511,11 +808,14 @@ impl MediaProvider for SymphoniaProvider {
                 )
                 .map_err(|_| SeekError::Unknown)?;
 
-            if let Some(timebase) = timebase {
-                self.current_position = timebase.calc_time(seek.actual_ts).seconds;
-            }
+            let Some(timebase) = timebase else {
+                return Ok(time);
+            };
 
-            Ok(())
+            let reached = timebase.calc_time(seek.actual_ts);
+            self.current_position = reached.seconds;
+
+            Ok(reached.seconds as f64 + reached.frac)
         } else {
             Err(SeekError::NothingOpen)
         }
@@ -535,13 +835,194 @@ impl MediaPlugin for SymphoniaProvider {
         "audio/mpeg",
         "audio/m4a",
         "audio/x-aiff",
+        "audio/opus",
+        "audio/webm",
+        "audio/x-matroska",
     ];
 
     const PROVIDES_DECODING: bool = true;
     const PROVIDES_METADATA: bool = true;
     const ALWAYS_CHECK_METADATA: bool = false;
 
-    const SUPPORTED_EXTENSIONS: &'static [&'static str] =
-        &["ogg", "aac", "flac", "wav", "mp3", "m4a", "aiff"];
+    const SUPPORTED_EXTENSIONS: &'static [&'static str] = &[
+        "ogg", "oga", "opus", "webm", "mka", "aac", "flac", "wav", "mp3", "m4a", "m4b", "aiff",
+    ];
     const INDEXING_SUPPORTED: bool = true;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, io::Cursor};
+
+    use super::*;
+
+    /// Opens one of the fixtures in `tests/fixtures`, which are one second of a 440Hz sine wave.
+    fn open_fixture(data: &'static [u8], ext: &str) -> SymphoniaProvider {
+        let mut provider = SymphoniaProvider::default();
+        provider
+            .open(Box::new(Cursor::new(data)), Some(ext.to_string()))
+            .expect("could not open fixture");
+        provider
+    }
+
+    /// Decodes the rest of the file, returning the samples of each channel as floats.
+    fn decode_all(provider: &mut SymphoniaProvider) -> Vec<Vec<f64>> {
+        let mut channels: Vec<Vec<f64>> = Vec::new();
+
+        loop {
+            let frame = match provider.read_samples() {
+                Ok(frame) => frame,
+                Err(PlaybackReadError::EOF) => break,
+                Err(e) => panic!("could not decode fixture: {:?}", e),
+            };
+
+            let samples: Vec<Vec<f64>> = match frame.samples {
+                Samples::Float32(v) => v
+                    .into_iter()
+                    .map(|c| c.into_iter().map(|s| s as f64).collect())
+                    .collect(),
+                Samples::Signed16(v) => v
+                    .into_iter()
+                    .map(|c| c.into_iter().map(|s| s as f64 / 32768.0).collect())
+                    .collect(),
+                Samples::Signed32(v) => v
+                    .into_iter()
+                    .map(|c| c.into_iter().map(|s| s as f64 / 2147483648.0).collect())
+                    .collect(),
+                _ => panic!("unexpected sample format"),
+            };
+
+            channels.resize(samples.len(), Vec::new());
+
+            for (channel, samples) in channels.iter_mut().zip(samples) {
+                channel.extend(samples);
+            }
+        }
+
+        channels
+    }
+
+    #[test]
+    fn scans_and_decodes_opus() {
+        let mut provider =
+            open_fixture(include_bytes!("../../../tests/fixtures/sine.opus"), "opus");
+
+        let metadata = provider.read_metadata().unwrap().clone();
+        assert_eq!(metadata.name.as_deref(), Some("Sine"));
+        assert_eq!(metadata.artist.as_deref(), Some("Muzak"));
+
+        let info = provider.probe_info().unwrap();
+        assert_eq!(info.codec.as_deref(), Some("opus"));
+        assert_eq!(info.sample_rate, Some(48000));
+        assert_eq!(info.channels, Some(2));
+        assert_eq!(info.duration, Some(1));
+        assert_eq!(info.lossless, Some(false));
+
+        provider.start_playback().unwrap();
+        let channels = decode_all(&mut provider);
+
+        // the pre-skip at the start is trimmed, so exactly one second is decoded
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].len(), 48000);
+
+        // the encoder needs a few milliseconds to settle, after which the output should follow
+        // the sine wave closely
+        let error = channels[0][4800..]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v - 0.5 * (2.0 * PI * 440.0 * (i + 4800) as f64 / 48000.0).sin()).abs())
+            .fold(0.0, f64::max);
+        assert!(error < 0.1, "decoded audio differs by {}", error);
+    }
+
+    #[test]
+    fn scans_and_decodes_m4a() {
+        let mut provider = open_fixture(include_bytes!("../../../tests/fixtures/sine.m4a"), "m4a");
+
+        let metadata = provider.read_metadata().unwrap().clone();
+        assert_eq!(metadata.name.as_deref(), Some("Sine"));
+        assert_eq!(metadata.artist.as_deref(), Some("Muzak"));
+
+        let info = provider.probe_info().unwrap();
+        assert_eq!(info.codec.as_deref(), Some("alac"));
+        assert_eq!(info.sample_rate, Some(8000));
+        assert_eq!(info.channels, Some(1));
+        assert_eq!(info.bit_depth, Some(16));
+        assert_eq!(info.duration, Some(1));
+        assert_eq!(info.lossless, Some(true));
+
+        provider.start_playback().unwrap();
+        let channels = decode_all(&mut provider);
+
+        // ALAC is lossless, so the samples come back exactly as they were generated
+        let expected: Vec<f64> = (0..8000)
+            .map(|i| (0.5 * 32767.0 * (2.0 * PI * 440.0 * i as f64 / 8000.0).sin()) as i16)
+            .map(|v| v as f64 / 32768.0)
+            .collect();
+
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0], expected);
+    }
+
+    #[test]
+    fn scans_and_decodes_aac() {
+        let mut provider = open_fixture(
+            include_bytes!("../../../tests/fixtures/sine-aac.m4a"),
+            "m4a",
+        );
+
+        let metadata = provider.read_metadata().unwrap().clone();
+        assert_eq!(metadata.name.as_deref(), Some("Sine"));
+        assert_eq!(metadata.artist.as_deref(), Some("Muzak"));
+
+        let info = provider.probe_info().unwrap();
+        assert_eq!(info.codec.as_deref(), Some("aac"));
+        assert_eq!(info.sample_rate, Some(8000));
+        assert_eq!(info.channels, Some(1));
+        assert_eq!(info.duration, Some(1));
+        assert_eq!(info.lossless, Some(false));
+
+        provider.start_playback().unwrap();
+        let channels = decode_all(&mut provider);
+
+        // the fixture has no edit list, so the first frame of encoder delay is decoded as well
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].len(), 9216);
+
+        let error = channels[0][1024..9024]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v - 0.5 * (2.0 * PI * 440.0 * i as f64 / 8000.0).sin()).abs())
+            .fold(0.0, f64::max);
+        assert!(error < 0.01, "decoded audio differs by {}", error);
+    }
+
+    /// Scanning reads the stream info from the container headers instead of starting playback,
+    /// so both have to agree.
+    #[test]
+    fn probe_info_matches_playback() {
+        let fixtures: [(&'static [u8], &str); 3] = [
+            (include_bytes!("../../../tests/fixtures/sine.opus"), "opus"),
+            (include_bytes!("../../../tests/fixtures/sine.m4a"), "m4a"),
+            (
+                include_bytes!("../../../tests/fixtures/sine-aac.m4a"),
+                "m4a",
+            ),
+        ];
+
+        for (data, ext) in fixtures {
+            let mut provider = open_fixture(data, ext);
+            let info = provider.probe_info().unwrap();
+
+            provider.start_playback().unwrap();
+
+            let params = provider.decoder.as_ref().unwrap().codec_params();
+            let codec = codecs().get_codec(params.codec).unwrap();
+            assert_eq!(info.codec.as_deref(), Some(codec.short_name));
+            assert_eq!(info.duration, Some(provider.duration_secs().unwrap()));
+
+            let frame = provider.read_samples().unwrap();
+            assert_eq!(info.sample_rate, Some(frame.rate), "{:?}", info.codec);
+        }
+    }
+}
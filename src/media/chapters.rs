@@ -0,0 +1,974 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use super::metadata::{Chapter, Metadata};
+
+/// Fills in the end of every chapter with the start of the chapter that follows it. The last
+/// chapter is left open-ended, as it runs until the end of the file.
+pub fn close_chapters(chapters: &mut [Chapter]) {
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    for i in 0..chapters.len() {
+        let next_start = chapters.get(i + 1).map(|v| v.start);
+
+        if chapters[i].end.is_none() || chapters[i].end <= Some(chapters[i].start) {
+            chapters[i].end = next_start;
+        }
+    }
+}
+
+/// Returns whether or not the file should be treated as an audiobook. Audiobooks are recognized
+/// by their extension (m4b) or by their genre.
+pub fn is_audiobook(path: &Path, metadata: &Metadata) -> bool {
+    let extension_matches = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m4b"));
+
+    let genre_matches = metadata.genre.as_ref().is_some_and(|genre| {
+        let genre = genre.to_lowercase();
+        genre.contains("audiobook") || genre.contains("audio book") || genre.contains("spoken")
+    });
+
+    extension_matches || genre_matches
+}
+
+/// Parses a Vorbis comment chapter timestamp (HH:MM:SS.mmm) into seconds.
+fn parse_vorbis_timestamp(value: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+
+    for part in value.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+
+    Some(seconds)
+}
+
+/// Reads a Vorbis comment chapter tag into `chapters`, which is keyed by chapter number. Chapters
+/// are stored as pairs of tags, where CHAPTERxxx contains the start time and CHAPTERxxxNAME
+/// contains the title. Tags that aren't chapter tags are ignored.
+pub fn read_vorbis_chapter(chapters: &mut BTreeMap<String, Chapter>, key: &str, value: &str) {
+    let key = key.to_ascii_uppercase();
+
+    let Some(rest) = key.strip_prefix("CHAPTER") else {
+        return;
+    };
+
+    let (number, is_name) = match rest.strip_suffix("NAME") {
+        Some(number) => (number, true),
+        None => (rest, false),
+    };
+
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return;
+    }
+
+    let chapter = chapters.entry(number.to_string()).or_insert(Chapter {
+        title: None,
+        start: f64::NAN,
+        end: None,
+    });
+
+    if is_name {
+        chapter.title = Some(value.to_string());
+    } else if let Some(start) = parse_vorbis_timestamp(value) {
+        chapter.start = start;
+    }
+}
+
+/// Turns the chapters collected by `read_vorbis_chapter` into a list. Chapters that only have a
+/// title are dropped.
+pub fn finish_vorbis_chapters(chapters: BTreeMap<String, Chapter>) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = chapters
+        .into_values()
+        .filter(|v| !v.start.is_nan())
+        .collect();
+
+    close_chapters(&mut chapters);
+
+    chapters
+}
+
+/// Reads the chapters stored in the container of a file. This currently supports ID3v2 chapter
+/// frames (CHAP), QuickTime chapter tracks and Nero-style MP4 chapters (chpl), none of which are
+/// exposed by Symphonia. Returns an empty list if the file doesn't contain any chapters.
+pub fn read_container_chapters<R: Read + Seek>(reader: &mut R) -> Vec<Chapter> {
+    let mut header = [0u8; 10];
+
+    if reader.seek(SeekFrom::Start(0)).is_err() || reader.read_exact(&mut header).is_err() {
+        return Vec::new();
+    }
+
+    let mut chapters = if &header[0..3] == b"ID3" {
+        read_id3_chapters(reader, &header).unwrap_or_default()
+    } else if &header[4..8] == b"ftyp" {
+        read_mp4_chapters(reader).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    close_chapters(&mut chapters);
+
+    chapters
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, byte| (acc << 7) | (*byte & 0x7F) as usize)
+}
+
+fn be_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?))
+}
+
+fn be_u16(bytes: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(0..2)?.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(0..8)?.try_into().ok()?))
+}
+
+fn decode_id3_text(data: &[u8]) -> Option<String> {
+    let (encoding, text) = data.split_first()?;
+
+    let decode_utf16 = |text: &[u8], big_endian: bool| {
+        let units: Vec<u16> = text
+            .chunks_exact(2)
+            .map(|v| {
+                if big_endian {
+                    u16::from_be_bytes([v[0], v[1]])
+                } else {
+                    u16::from_le_bytes([v[0], v[1]])
+                }
+            })
+            .collect();
+
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = match encoding {
+        0 => text.iter().map(|v| *v as char).collect(),
+        1 => match text {
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, true),
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, false),
+            _ => decode_utf16(text, false),
+        },
+        2 => decode_utf16(text, true),
+        3 => String::from_utf8_lossy(text).to_string(),
+        _ => return None,
+    };
+
+    let text = text.trim_end_matches('\0').to_string();
+
+    (!text.is_empty()).then_some(text)
+}
+
+/// Iterates over the frames in an ID3v2 frame block, calling the callback with the ID and body
+/// of each frame.
+fn for_each_id3_frame(data: &[u8], major: u8, mut callback: impl FnMut(&[u8], &[u8])) {
+    let mut offset = 0;
+
+    while offset + 10 <= data.len() {
+        let id = &data[offset..offset + 4];
+
+        // the rest of the tag is padding
+        if id[0] == 0 {
+            break;
+        }
+
+        let size = if major >= 4 {
+            syncsafe(&data[offset + 4..offset + 8])
+        } else {
+            be_u32(&data[offset + 4..offset + 8]).unwrap_or_default() as usize
+        };
+
+        let body_start = offset + 10;
+        let body_end = body_start + size;
+
+        if body_end > data.len() {
+            break;
+        }
+
+        callback(id, &data[body_start..body_end]);
+
+        offset = body_end;
+    }
+}
+
+fn read_id3_chapters<R: Read + Seek>(reader: &mut R, header: &[u8; 10]) -> Option<Vec<Chapter>> {
+    let major = header[3];
+    let flags = header[5];
+
+    // ID3v2.2 doesn't support chapters
+    if major < 3 {
+        return None;
+    }
+
+    // the size is at most 256MB, but it's still read as it arrives rather than trusted up front
+    let size = syncsafe(&header[6..10]) as u64;
+    let mut data = Vec::new();
+    reader.take(size).read_to_end(&mut data).ok()?;
+
+    if data.len() as u64 != size {
+        return None;
+    }
+
+    // ID3v2.3 applies unsynchronisation to the entire tag
+    if major == 3 && flags & 0x80 != 0 {
+        let mut resynced = Vec::with_capacity(data.len());
+
+        for (i, byte) in data.iter().enumerate() {
+            if *byte == 0x00 && i > 0 && data[i - 1] == 0xFF {
+                continue;
+            }
+
+            resynced.push(*byte);
+        }
+
+        data = resynced;
+    }
+
+    // skip the extended header
+    if flags & 0x40 != 0 {
+        let size = if major >= 4 {
+            syncsafe(data.get(0..4)?)
+        } else {
+            be_u32(data.get(0..4)?)? as usize + 4
+        };
+
+        data.drain(0..size.min(data.len()));
+    }
+
+    let mut chapters = Vec::new();
+
+    for_each_id3_frame(&data, major, |id, body| {
+        if id != b"CHAP" {
+            return;
+        }
+
+        let Some(id_end) = body.iter().position(|v| *v == 0) else {
+            return;
+        };
+
+        let times = &body[id_end + 1..];
+
+        let (Some(start), Some(end)) = (be_u32(times), times.get(4..).and_then(be_u32)) else {
+            return;
+        };
+
+        let mut title = None;
+
+        // the chapter's title is stored in an embedded TIT2 frame, after the byte offsets
+        if let Some(subframes) = times.get(16..) {
+            for_each_id3_frame(subframes, major, |id, body| {
+                if id == b"TIT2" {
+                    title = decode_id3_text(body);
+                }
+            });
+        }
+
+        chapters.push(Chapter {
+            title,
+            start: start as f64 / 1000.0,
+            end: (end > start).then_some(end as f64 / 1000.0),
+        });
+    });
+
+    Some(chapters)
+}
+
+/// The largest chpl atom that is read. A chpl atom lists at most 255 chapters with titles of up to
+/// 255 bytes each, so anything larger than this is corrupt.
+const MAX_CHPL_SIZE: u64 = 256 * 1024;
+
+/// The largest sample table of a chapter track that is read. Chapter tracks have one sample per
+/// chapter, so their tables are tiny, and anything larger is corrupt.
+const MAX_CHAPTER_TABLE_SIZE: u64 = 1024 * 1024;
+
+/// The most chapters read from a QuickTime chapter track.
+const MAX_CHAPTERS: usize = 10_000;
+
+/// The header of an MP4 atom.
+#[derive(Debug, Clone, Copy)]
+struct Mp4Atom {
+    name: [u8; 4],
+    /// The offset of the atom's body in the file.
+    body: u64,
+    /// The size of the atom's body.
+    size: u64,
+}
+
+impl Mp4Atom {
+    fn end(&self) -> u64 {
+        self.body + self.size
+    }
+}
+
+/// Reads the header of the MP4 atom at `offset`, which has to end at or before `end`.
+fn read_mp4_atom<R: Read + Seek>(reader: &mut R, offset: u64, end: u64) -> Option<Mp4Atom> {
+    reader.seek(SeekFrom::Start(offset)).ok()?;
+
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+
+    let (header_size, size) = match be_u32(&header)? {
+        0 => (8, end - offset),
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large).ok()?;
+            (16, u64::from_be_bytes(large))
+        }
+        size => (8, size as u64),
+    };
+
+    // the sizes come from the file, so they can't be trusted to fit inside of it
+    if size < header_size || size > end - offset {
+        return None;
+    }
+
+    Some(Mp4Atom {
+        name: header[4..8].try_into().ok()?,
+        body: offset + header_size,
+        size: size - header_size,
+    })
+}
+
+/// Finds an MP4 atom among the atoms between the reader's position and `end`, without reading the
+/// atoms it skips over. On success, the reader is left at the start of the atom's body and the
+/// size of the body is returned.
+fn seek_mp4_atom<R: Read + Seek>(reader: &mut R, end: u64, name: &[u8]) -> Option<u64> {
+    let mut offset = reader.stream_position().ok()?;
+
+    while offset + 8 <= end {
+        let atom = read_mp4_atom(reader, offset, end)?;
+
+        if atom.name == name {
+            reader.seek(SeekFrom::Start(atom.body)).ok()?;
+            return Some(atom.size);
+        }
+
+        offset = atom.end();
+    }
+
+    None
+}
+
+/// Lists the atoms inside of an atom, without reading their bodies. Atoms after a corrupt one are
+/// left out.
+fn mp4_children<R: Read + Seek>(reader: &mut R, parent: &Mp4Atom) -> Vec<Mp4Atom> {
+    let mut children = Vec::new();
+    let mut offset = parent.body;
+
+    while offset + 8 <= parent.end() {
+        let Some(atom) = read_mp4_atom(reader, offset, parent.end()) else {
+            break;
+        };
+
+        offset = atom.end();
+        children.push(atom);
+    }
+
+    children
+}
+
+fn find_mp4_atom<'a>(atoms: &'a [Mp4Atom], name: &[u8]) -> Option<&'a Mp4Atom> {
+    atoms.iter().find(|atom| atom.name == name)
+}
+
+/// Reads the body of an atom, as long as it's no larger than `max`.
+fn read_mp4_body<R: Read + Seek>(reader: &mut R, atom: &Mp4Atom, max: u64) -> Option<Vec<u8>> {
+    if atom.size > max {
+        return None;
+    }
+
+    reader.seek(SeekFrom::Start(atom.body)).ok()?;
+
+    let mut body = Vec::new();
+    reader
+        .by_ref()
+        .take(atom.size)
+        .read_to_end(&mut body)
+        .ok()?;
+
+    (body.len() as u64 == atom.size).then_some(body)
+}
+
+/// Reads the entries of a sample table atom (stts, stsc, stco, etc.) in the specified sample
+/// table, which follow the version, flags and entry count.
+fn read_mp4_table<R: Read + Seek>(
+    reader: &mut R,
+    tables: &[Mp4Atom],
+    name: &[u8],
+    entry_size: usize,
+) -> Option<Vec<Vec<u8>>> {
+    let body = read_mp4_body(reader, find_mp4_atom(tables, name)?, MAX_CHAPTER_TABLE_SIZE)?;
+    let count = be_u32(body.get(4..)?)? as usize;
+
+    Some(
+        body.get(8..)?
+            .chunks_exact(entry_size)
+            .take(count)
+            .map(|v| v.to_vec())
+            .collect(),
+    )
+}
+
+/// Reads the ID of the track in a trak atom from its header.
+fn mp4_track_id<R: Read + Seek>(reader: &mut R, trak: &Mp4Atom) -> Option<u32> {
+    let tkhd = *find_mp4_atom(&mp4_children(reader, trak), b"tkhd")?;
+    let body = read_mp4_body(reader, &tkhd, 256)?;
+
+    // the creation and modification times before the ID are 64-bit in version 1
+    match body.first()? {
+        1 => be_u32(body.get(20..)?),
+        _ => be_u32(body.get(12..)?),
+    }
+}
+
+/// Returns the IDs of the tracks that a trak atom refers to as its chapter tracks.
+fn mp4_chapter_refs<R: Read + Seek>(reader: &mut R, trak: &Mp4Atom) -> Vec<u32> {
+    let Some(tref) = find_mp4_atom(&mp4_children(reader, trak), b"tref").copied() else {
+        return Vec::new();
+    };
+
+    let Some(chap) = find_mp4_atom(&mp4_children(reader, &tref), b"chap").copied() else {
+        return Vec::new();
+    };
+
+    read_mp4_body(reader, &chap, MAX_CHAPTER_TABLE_SIZE)
+        .map(|body| body.chunks_exact(4).filter_map(be_u32).collect())
+        .unwrap_or_default()
+}
+
+/// Decodes the text of a QuickTime text sample, which is a 16-bit length followed by UTF-8 text,
+/// or UTF-16 text if it starts with a byte order mark.
+fn decode_text_sample(sample: &[u8]) -> Option<String> {
+    let len = be_u16(sample)? as usize;
+    let text = sample.get(2..2 + len).unwrap_or(&sample[2..]);
+
+    let text = match text {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units: Vec<u16> = rest.chunks_exact(2).filter_map(be_u16).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).to_string(),
+    };
+
+    (!text.is_empty()).then_some(text)
+}
+
+/// Reads the chapters from a QuickTime chapter track, which is a text track with one sample per
+/// chapter. Each sample contains the chapter's title, and lasts as long as the chapter.
+fn read_mp4_chapter_track<R: Read + Seek>(reader: &mut R, trak: &Mp4Atom) -> Option<Vec<Chapter>> {
+    let mdia = *find_mp4_atom(&mp4_children(reader, trak), b"mdia")?;
+    let mdia_children = mp4_children(reader, &mdia);
+
+    let mdhd = read_mp4_body(reader, find_mp4_atom(&mdia_children, b"mdhd")?, 256)?;
+    let timescale = match mdhd.first()? {
+        1 => be_u32(mdhd.get(20..)?)?,
+        _ => be_u32(mdhd.get(12..)?)?,
+    };
+
+    if timescale == 0 {
+        return None;
+    }
+
+    let minf = *find_mp4_atom(&mdia_children, b"minf")?;
+    let stbl = *find_mp4_atom(&mp4_children(reader, &minf), b"stbl")?;
+    let tables = mp4_children(reader, &stbl);
+
+    let stsz = read_mp4_body(
+        reader,
+        find_mp4_atom(&tables, b"stsz")?,
+        MAX_CHAPTER_TABLE_SIZE,
+    )?;
+    let sample_size = be_u32(stsz.get(4..)?)?;
+    let sample_count = (be_u32(stsz.get(8..)?)? as usize).min(MAX_CHAPTERS);
+
+    let sizes: Vec<u32> = if sample_size == 0 {
+        stsz.get(12..)?
+            .chunks_exact(4)
+            .take(sample_count)
+            .filter_map(be_u32)
+            .collect()
+    } else {
+        vec![sample_size; sample_count]
+    };
+
+    let chunk_offsets: Vec<u64> = match read_mp4_table(reader, &tables, b"stco", 4) {
+        Some(entries) => entries
+            .iter()
+            .filter_map(|v| be_u32(v))
+            .map(u64::from)
+            .collect(),
+        None => read_mp4_table(reader, &tables, b"co64", 8)?
+            .iter()
+            .filter_map(|v| be_u64(v))
+            .collect(),
+    };
+
+    // (first chunk, samples per chunk), where chunks are numbered from 1
+    let sample_to_chunk: Vec<(u32, u32)> = read_mp4_table(reader, &tables, b"stsc", 12)?
+        .iter()
+        .filter_map(|v| Some((be_u32(v)?, be_u32(v.get(4..)?)?)))
+        .collect();
+
+    let mut samples = Vec::new();
+
+    for (i, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let per_chunk = sample_to_chunk
+            .iter()
+            .rev()
+            .find(|(first, _)| *first as usize <= i + 1)
+            .map(|(_, count)| *count)
+            .unwrap_or_default();
+
+        let mut offset = *chunk_offset;
+
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.get(samples.len()) else {
+                break;
+            };
+
+            samples.push((offset, *size));
+            offset += *size as u64;
+        }
+    }
+
+    // (start, duration) of each sample, in units of the timescale
+    let mut times = Vec::new();
+    let mut time = 0u64;
+
+    for entry in read_mp4_table(reader, &tables, b"stts", 8)? {
+        let (Some(count), Some(delta)) = (be_u32(&entry), be_u32(&entry[4..])) else {
+            continue;
+        };
+
+        for _ in 0..count {
+            if times.len() >= samples.len() {
+                break;
+            }
+
+            times.push((time, delta as u64));
+            time += delta as u64;
+        }
+    }
+
+    let timescale = timescale as f64;
+    let mut chapters = Vec::new();
+
+    for ((offset, size), (start, duration)) in samples.into_iter().zip(times) {
+        let mut sample = Vec::new();
+
+        reader.seek(SeekFrom::Start(offset)).ok()?;
+        // titles are short, so anything past this is ignored
+        reader
+            .by_ref()
+            .take(size.min(1024) as u64)
+            .read_to_end(&mut sample)
+            .ok()?;
+
+        chapters.push(Chapter {
+            title: decode_text_sample(&sample),
+            start: start as f64 / timescale,
+            end: (duration > 0).then_some((start + duration) as f64 / timescale),
+        });
+    }
+
+    Some(chapters)
+}
+
+fn read_mp4_chapters<R: Read + Seek>(reader: &mut R) -> Option<Vec<Chapter>> {
+    let len = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+
+    // only the atoms on the way to the chapters are read, since the media data and sample tables
+    // can be very large
+    let size = seek_mp4_atom(reader, len, b"moov")?;
+    let moov = Mp4Atom {
+        name: *b"moov",
+        body: reader.stream_position().ok()?,
+        size,
+    };
+
+    // files written by iTunes and most audiobook tools have both, with the same chapters
+    read_quicktime_chapters(reader, &moov)
+        .filter(|v| !v.is_empty())
+        .or_else(|| read_nero_chapters(reader, &moov))
+}
+
+/// Reads the chapters from the QuickTime chapter track of a file, which is the text track that
+/// another track refers to with a chap reference. This is what most M4B audiobooks use.
+fn read_quicktime_chapters<R: Read + Seek>(reader: &mut R, moov: &Mp4Atom) -> Option<Vec<Chapter>> {
+    let traks: Vec<Mp4Atom> = mp4_children(reader, moov)
+        .into_iter()
+        .filter(|atom| &atom.name == b"trak")
+        .collect();
+
+    let refs: Vec<u32> = traks
+        .iter()
+        .flat_map(|trak| mp4_chapter_refs(reader, trak))
+        .collect();
+
+    if refs.is_empty() {
+        return None;
+    }
+
+    let chapter_trak = traks
+        .iter()
+        .find(|trak| mp4_track_id(reader, trak).is_some_and(|id| refs.contains(&id)))?;
+
+    read_mp4_chapter_track(reader, chapter_trak)
+}
+
+/// Reads the chapters from a Nero-style chpl atom in moov/udta.
+fn read_nero_chapters<R: Read + Seek>(reader: &mut R, moov: &Mp4Atom) -> Option<Vec<Chapter>> {
+    let udta = *find_mp4_atom(&mp4_children(reader, moov), b"udta")?;
+    let chpl = *find_mp4_atom(&mp4_children(reader, &udta), b"chpl")?;
+    let chpl = read_mp4_body(reader, &chpl, MAX_CHPL_SIZE)?;
+
+    let version = *chpl.first()?;
+    // version, flags, and (in version 1) four reserved bytes
+    let mut offset = if version == 1 { 8 } else { 4 };
+
+    let count = *chpl.get(offset)?;
+    offset += 1;
+
+    let mut chapters = Vec::new();
+
+    for _ in 0..count {
+        // timestamps are stored in 100ns units
+        let start = be_u64(chpl.get(offset..)?)?;
+        let title_len = *chpl.get(offset + 8)? as usize;
+        let title = chpl.get(offset + 9..offset + 9 + title_len)?;
+
+        chapters.push(Chapter {
+            title: Some(String::from_utf8_lossy(title).to_string()).filter(|v| !v.is_empty()),
+            start: start as f64 / 10_000_000.0,
+            end: None,
+        });
+
+        offset += 9 + title_len;
+    }
+
+    Some(chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chapter(title: &str, start: f64, end: Option<f64>) -> Chapter {
+        Chapter {
+            title: Some(title.to_string()),
+            start,
+            end,
+        }
+    }
+
+    fn id3_frame(major: u8, id: &[u8], body: &[u8]) -> Vec<u8> {
+        let size = body.len() as u32;
+        let size = if major >= 4 {
+            // syncsafe integers only use the lower 7 bits of each byte
+            (size & 0x7F)
+                | ((size & 0x3F80) << 1)
+                | ((size & 0x1FC000) << 2)
+                | ((size & 0xFE00000) << 3)
+        } else {
+            size
+        };
+
+        let mut frame = id.to_vec();
+        frame.extend(size.to_be_bytes());
+        frame.extend([0, 0]);
+        frame.extend(body);
+        frame
+    }
+
+    fn id3_chap(major: u8, id: &str, start: u32, end: u32, title: &[u8]) -> Vec<u8> {
+        let mut body = id.as_bytes().to_vec();
+        body.push(0);
+        body.extend(start.to_be_bytes());
+        body.extend(end.to_be_bytes());
+        // byte offsets, which aren't used
+        body.extend([0xFF; 8]);
+        body.extend(id3_frame(major, b"TIT2", title));
+
+        id3_frame(major, b"CHAP", &body)
+    }
+
+    fn id3_tag(major: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = frames.concat();
+        // padding
+        body.extend([0; 16]);
+
+        let size = body.len() as u32;
+        let size = [
+            (size >> 21) as u8 & 0x7F,
+            (size >> 14) as u8 & 0x7F,
+            (size >> 7) as u8 & 0x7F,
+            size as u8 & 0x7F,
+        ];
+
+        let mut tag = vec![b'I', b'D', b'3', major, 0, 0];
+        tag.extend(size);
+        tag.extend(body);
+        // the start of the audio
+        tag.extend([0xFF, 0xFB, 0x90, 0x00]);
+        tag
+    }
+
+    #[test]
+    fn reads_id3_chapters() {
+        for major in [3, 4] {
+            let tag = id3_tag(
+                major,
+                &[
+                    id3_frame(major, b"TIT2", b"\x03Book"),
+                    // listed out of order, and without an end time
+                    id3_chap(major, "ch1", 90_500, 0, b"\x03Chapter Two"),
+                    id3_chap(major, "ch0", 0, 90_500, b"\x01\xFF\xFEO\0n\0e\0"),
+                ],
+            );
+
+            assert_eq!(
+                read_container_chapters(&mut Cursor::new(tag)),
+                vec![
+                    chapter("One", 0.0, Some(90.5)),
+                    Chapter {
+                        title: Some("Chapter Two".to_string()),
+                        start: 90.5,
+                        end: None,
+                    },
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_truncated_id3_tags() {
+        let mut tag = id3_tag(4, &[id3_chap(4, "ch0", 0, 1000, b"\x03One")]);
+        tag.truncate(30);
+
+        assert_eq!(read_container_chapters(&mut Cursor::new(tag)), vec![]);
+    }
+
+    fn atom(name: &[u8], children: &[&[u8]]) -> Vec<u8> {
+        let body = children.concat();
+        let mut atom = (body.len() as u32 + 8).to_be_bytes().to_vec();
+        atom.extend(name);
+        atom.extend(body);
+        atom
+    }
+
+    fn ftyp() -> Vec<u8> {
+        atom(b"ftyp", &[b"M4B ", &[0; 4], b"M4B mp42isom"])
+    }
+
+    fn chpl(version: u8, chapters: &[(u64, &str)]) -> Vec<u8> {
+        let mut body = vec![version, 0, 0, 0];
+
+        if version == 1 {
+            body.extend([0; 4]);
+        }
+
+        body.push(chapters.len() as u8);
+
+        for (start, title) in chapters {
+            body.extend(start.to_be_bytes());
+            body.push(title.len() as u8);
+            body.extend(title.as_bytes());
+        }
+
+        atom(b"chpl", &[&body])
+    }
+
+    #[test]
+    fn reads_nero_chapters() {
+        for version in [0, 1] {
+            let chpl = chpl(version, &[(0, "Intro"), (1_234_000_000, "")]);
+            let udta = atom(b"udta", &[&chpl]);
+            let moov = atom(b"moov", &[&atom(b"mvhd", &[&[0; 100]]), &udta]);
+            let file = [ftyp(), moov, atom(b"mdat", &[&[0; 64]])].concat();
+
+            assert_eq!(
+                read_container_chapters(&mut Cursor::new(file)),
+                vec![
+                    chapter("Intro", 0.0, Some(123.4)),
+                    Chapter {
+                        title: None,
+                        start: 123.4,
+                        end: None,
+                    },
+                ]
+            );
+        }
+    }
+
+    fn full_atom(name: &[u8], version: u8, body: &[u8]) -> Vec<u8> {
+        atom(name, &[&[version, 0, 0, 0], body])
+    }
+
+    fn table(name: &[u8], entries: &[&[u32]]) -> Vec<u8> {
+        let mut body = (entries.len() as u32).to_be_bytes().to_vec();
+        body.extend(entries.concat().iter().flat_map(|v| v.to_be_bytes()));
+        full_atom(name, 0, &body)
+    }
+
+    fn tkhd(version: u8, id: u32) -> Vec<u8> {
+        let times = if version == 1 { 16 } else { 8 };
+        let mut body = vec![0; times];
+        body.extend(id.to_be_bytes());
+        body.extend([0; 68]);
+        full_atom(b"tkhd", version, &body)
+    }
+
+    /// Creates a file with an audio track and a QuickTime chapter track, whose samples are stored
+    /// in two chunks (with two samples and one sample each).
+    fn quicktime_file(titles: &[&[u8]], durations: &[u32], with_chpl: bool) -> Vec<u8> {
+        let samples: Vec<Vec<u8>> = titles
+            .iter()
+            .map(|title| {
+                let mut sample = (title.len() as u16).to_be_bytes().to_vec();
+                sample.extend(*title);
+                // the encoding atom that follows the text
+                sample.extend(atom(b"encd", &[&[0, 0, 1, 0]]));
+                sample
+            })
+            .collect();
+
+        let moov = |mdat_offset: u32| {
+            let audio = atom(
+                b"trak",
+                &[
+                    &tkhd(0, 1),
+                    &atom(b"tref", &[&atom(b"chap", &[&2u32.to_be_bytes()])]),
+                    &atom(b"mdia", &[&full_atom(b"mdhd", 0, &[0; 20])]),
+                ],
+            );
+
+            let sizes: Vec<u32> = samples.iter().map(|v| v.len() as u32).collect();
+            let second_chunk = mdat_offset + sizes[0] + sizes[1];
+            let stts: Vec<[u32; 2]> = durations.iter().map(|v| [1, *v]).collect();
+            let stts: Vec<&[u32]> = stts.iter().map(|v| v.as_slice()).collect();
+
+            // a sample size of 0, followed by the size of each sample
+            let mut stsz = vec![0, 0, 0, 0];
+            stsz.extend((sizes.len() as u32).to_be_bytes());
+            stsz.extend(sizes.iter().flat_map(|v| v.to_be_bytes()));
+
+            let stbl = atom(
+                b"stbl",
+                &[
+                    &table(b"stts", &stts),
+                    &table(b"stsc", &[&[1, 2, 1], &[2, 1, 1]]),
+                    &full_atom(b"stsz", 0, &stsz),
+                    &table(b"stco", &[&[mdat_offset], &[second_chunk]]),
+                ],
+            );
+
+            let mut mdhd = vec![0; 16];
+            mdhd.extend(1000u32.to_be_bytes());
+            mdhd.extend(0u64.to_be_bytes());
+            mdhd.extend([0; 4]);
+
+            let text = atom(
+                b"trak",
+                &[
+                    &tkhd(1, 2),
+                    &atom(
+                        b"mdia",
+                        &[&full_atom(b"mdhd", 1, &mdhd), &atom(b"minf", &[&stbl])],
+                    ),
+                ],
+            );
+
+            let mut children = vec![audio, text];
+
+            if with_chpl {
+                children.push(atom(b"udta", &[&chpl(0, &[(0, "Nero")])]));
+            }
+
+            let children: Vec<&[u8]> = children.iter().map(|v| v.as_slice()).collect();
+            atom(b"moov", &children)
+        };
+
+        // the size of moov doesn't depend on the offsets it contains
+        let mdat_offset = (ftyp().len() + moov(0).len() + 8) as u32;
+        let samples: Vec<&[u8]> = samples.iter().map(|v| v.as_slice()).collect();
+
+        [ftyp(), moov(mdat_offset), atom(b"mdat", &samples)].concat()
+    }
+
+    #[test]
+    fn reads_quicktime_chapters() {
+        let file = quicktime_file(
+            &[b"Opening", b"\xFE\xFF\0M\0i\0d", b""],
+            &[60_000, 90_250, 30_000],
+            true,
+        );
+
+        // the chapter track is preferred over the chpl atom
+        assert_eq!(
+            read_container_chapters(&mut Cursor::new(file)),
+            vec![
+                chapter("Opening", 0.0, Some(60.0)),
+                chapter("Mid", 60.0, Some(150.25)),
+                Chapter {
+                    title: None,
+                    start: 150.25,
+                    end: Some(180.25),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_nero_chapters() {
+        // a chapter track without any samples
+        let file = quicktime_file(&[b"a", b"b", b"c"], &[], true);
+
+        assert_eq!(
+            read_container_chapters(&mut Cursor::new(file)),
+            vec![Chapter {
+                title: Some("Nero".to_string()),
+                start: 0.0,
+                end: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn reads_vorbis_chapters() {
+        let mut chapters = BTreeMap::new();
+
+        for (key, value) in [
+            ("CHAPTER002", "01:02:03.500"),
+            ("chapter002name", "Second"),
+            ("CHAPTER001", "00:00:00.000"),
+            ("CHAPTER001NAME", "First"),
+            // a title without a start
+            ("CHAPTER003NAME", "Third"),
+            ("CHAPTER004", "not a time"),
+            ("CHAPTERS", "00:01:00.000"),
+            ("CHAPTER", "00:01:00.000"),
+            ("TITLE", "00:01:00.000"),
+        ] {
+            read_vorbis_chapter(&mut chapters, key, value);
+        }
+
+        assert_eq!(
+            finish_vorbis_chapters(chapters),
+            vec![
+                chapter("First", 0.0, Some(3723.5)),
+                chapter("Second", 3723.5, None),
+            ]
+        );
+    }
+}
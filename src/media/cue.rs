@@ -0,0 +1,575 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::metadata::Metadata;
+
+/// A parsed CUE sheet. Only the commands relevant to splitting a single-file rip into tracks are
+/// retained, everything else (flags, pregaps, CD-TEXT files, etc.) is ignored.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub catalog: Option<String>,
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct CueFile {
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct CueTrack {
+    pub number: u64,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub isrc: Option<String>,
+    /// The start of the track (INDEX 01) in seconds.
+    pub start: f64,
+    /// The end of the track in seconds. This is the start of the next track in the same file, or
+    /// None if the track runs until the end of the file.
+    pub end: Option<f64>,
+}
+
+/// The portion of a file that a virtual track occupies, in seconds.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TrackRange {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+// Timestamps are compared with some leeway, since they're round-tripped through text.
+const TIMESTAMP_EPSILON: f64 = 0.001;
+
+impl TrackRange {
+    /// Returns true if the other range starts where this one ends.
+    pub fn is_followed_by(&self, other: &TrackRange) -> bool {
+        self.end
+            .is_some_and(|end| (end - other.start).abs() < TIMESTAMP_EPSILON)
+    }
+}
+
+/// Parses a CUE sheet timestamp (MM:SS:FF, where there are 75 frames per second) into seconds.
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let mut parts = value.split(':');
+    let minutes: u64 = parts.next()?.trim().parse().ok()?;
+    let seconds: u64 = parts.next()?.trim().parse().ok()?;
+    let frames: u64 = parts.next()?.trim().parse().ok()?;
+
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / 75.0)
+}
+
+/// Removes surrounding quotes from a CUE sheet argument, if there are any.
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+
+    if let Some(stripped) = value.strip_prefix('"') {
+        match stripped.rfind('"') {
+            Some(end) => stripped[..end].to_string(),
+            None => stripped.to_string(),
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+/// Decodes the contents of a CUE sheet. CUE sheets are frequently not UTF-8: files starting with a
+/// UTF-16 byte order mark are read as UTF-16, and anything else that isn't valid UTF-8 is read as
+/// Latin-1.
+fn decode_text(data: Vec<u8>) -> String {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|v| from_bytes([v[0], v[1]]))
+            .collect();
+
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = match data.as_slice() {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => match String::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|v| *v as char).collect(),
+        },
+    };
+
+    text.trim_start_matches('\u{feff}').to_string()
+}
+
+fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim();
+
+    match line.split_once(char::is_whitespace) {
+        Some((command, rest)) => (command, rest.trim()),
+        None => (line, ""),
+    }
+}
+
+impl CueSheet {
+    /// Parses the text of a CUE sheet. Returns None if the sheet doesn't contain any tracks.
+    pub fn parse(text: &str) -> Option<CueSheet> {
+        let mut sheet = CueSheet::default();
+
+        for line in text.lines() {
+            let (command, rest) = split_command(line);
+
+            let current_track = sheet
+                .files
+                .last_mut()
+                .and_then(|file| file.tracks.last_mut());
+
+            match command.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    // the file type comes after the (possibly quoted) file name
+                    let name = if rest.starts_with('"') {
+                        unquote(rest)
+                    } else {
+                        rest.rsplit_once(char::is_whitespace)
+                            .map(|(name, _)| name.to_string())
+                            .unwrap_or_else(|| rest.to_string())
+                    };
+
+                    sheet.files.push(CueFile {
+                        name,
+                        tracks: Vec::new(),
+                    });
+                }
+                "TRACK" => {
+                    let Some(file) = sheet.files.last_mut() else {
+                        continue;
+                    };
+
+                    let number = split_command(rest).0.parse().unwrap_or_default();
+
+                    file.tracks.push(CueTrack {
+                        number,
+                        ..Default::default()
+                    });
+                }
+                "TITLE" => match current_track {
+                    Some(track) => track.title = Some(unquote(rest)),
+                    None => sheet.title = Some(unquote(rest)),
+                },
+                "PERFORMER" => match current_track {
+                    Some(track) => track.performer = Some(unquote(rest)),
+                    None => sheet.performer = Some(unquote(rest)),
+                },
+                "ISRC" => {
+                    if let Some(track) = current_track {
+                        track.isrc = Some(unquote(rest));
+                    }
+                }
+                "CATALOG" => sheet.catalog = Some(unquote(rest)),
+                "INDEX" => {
+                    let (index, timestamp) = split_command(rest);
+
+                    if let (Some(track), "01") = (current_track, index) {
+                        if let Some(start) = parse_timestamp(timestamp) {
+                            track.start = start;
+                        }
+                    }
+                }
+                "REM" => {
+                    let (key, value) = split_command(rest);
+
+                    match key.to_ascii_uppercase().as_str() {
+                        "GENRE" => sheet.genre = Some(unquote(value)),
+                        "DATE" => sheet.date = Some(unquote(value)),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for file in sheet.files.iter_mut() {
+            let starts: Vec<f64> = file.tracks.iter().map(|track| track.start).collect();
+
+            for (i, track) in file.tracks.iter_mut().enumerate() {
+                track.end = starts.get(i + 1).copied();
+            }
+        }
+
+        if sheet.files.iter().all(|file| file.tracks.is_empty()) {
+            None
+        } else {
+            Some(sheet)
+        }
+    }
+
+    /// Reads and parses a CUE sheet from disk.
+    pub fn read(path: &Path) -> Option<CueSheet> {
+        CueSheet::parse(&decode_text(fs::read(path).ok()?))
+    }
+
+    /// Returns the tracks the sheet lists for the specified audio file. Only the file name is
+    /// compared, since CUE sheets rarely contain usable paths.
+    pub fn tracks_for_file(&self, path: &Path) -> Option<&Vec<CueTrack>> {
+        let name = path.file_name()?.to_str()?;
+
+        if self.files.len() == 1 && self.files[0].name.eq_ignore_ascii_case(name) {
+            return Some(&self.files[0].tracks);
+        }
+
+        self.files
+            .iter()
+            .find(|file| {
+                Path::new(&file.name.replace('\\', "/"))
+                    .file_name()
+                    .and_then(|v| v.to_str())
+                    .is_some_and(|v| v.eq_ignore_ascii_case(name))
+            })
+            .map(|file| &file.tracks)
+    }
+
+    /// Returns the track in the specified file that starts at the specified time.
+    pub fn track_at(&self, path: &Path, start: f64) -> Option<&CueTrack> {
+        self.tracks_for_file(path)?
+            .iter()
+            .find(|track| (track.start - start).abs() < TIMESTAMP_EPSILON)
+    }
+
+    /// Overrides the file-level metadata with the information the sheet has about the track.
+    pub fn apply_to_metadata(&self, track: &CueTrack, track_count: usize, metadata: &mut Metadata) {
+        if let Some(title) = &track.title {
+            metadata.name = Some(title.clone());
+        }
+
+        if let Some(performer) = track.performer.as_ref().or(self.performer.as_ref()) {
+            metadata.artist = Some(performer.clone());
+        }
+
+        if let Some(performer) = &self.performer {
+            metadata.album_artist = Some(performer.clone());
+        }
+
+        if let Some(title) = &self.title {
+            metadata.album = Some(title.clone());
+        }
+
+        if metadata.genre.is_none() {
+            metadata.genre = self.genre.clone();
+        }
+
+        if metadata.date.is_none() {
+            metadata.date = self
+                .date
+                .as_ref()
+                .and_then(|date| dateparser::parse(date).ok());
+        }
+
+        if metadata.catalog.is_none() {
+            metadata.catalog = self.catalog.clone();
+        }
+
+        metadata.isrc = track.isrc.clone().or(metadata.isrc.take());
+        metadata.track_current = Some(track.number);
+        metadata.track_max = Some(track_count as u64);
+    }
+}
+
+/// Finds the CUE sheet describing the specified audio file. A sheet embedded in the file's
+/// metadata takes priority, followed by a sheet with the same name as the file, followed by any
+/// other sheet in the same directory that references the file.
+pub fn find_cue_sheet(path: &Path, metadata: &Metadata) -> Option<CueSheet> {
+    if let Some(sheet) = metadata.cuesheet.as_deref().and_then(CueSheet::parse) {
+        return Some(sheet);
+    }
+
+    let sheets: Vec<PathBuf> = fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|v| v.path()))
+        .filter(|entry| is_cue_sheet(entry))
+        .collect();
+
+    find_cue_sheet_in(path, metadata, &sheets)
+}
+
+/// Like `find_cue_sheet`, but only considers the CUE sheets listed in `sheets`, for callers that
+/// have already listed the file's directory.
+pub fn find_cue_sheet_in(path: &Path, metadata: &Metadata, sheets: &[PathBuf]) -> Option<CueSheet> {
+    if let Some(sheet) = metadata.cuesheet.as_deref().and_then(CueSheet::parse) {
+        return Some(sheet);
+    }
+
+    let same_name = path.with_extension("cue");
+
+    let mut candidates: Vec<&PathBuf> = sheets.iter().collect();
+    candidates.sort_by_key(|candidate| (**candidate != same_name, *candidate));
+
+    candidates
+        .into_iter()
+        .filter_map(|candidate| CueSheet::read(candidate))
+        .find(|sheet| sheet.tracks_for_file(path).is_some())
+}
+
+/// Returns true if the path has a `.cue` extension.
+pub fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// Creates the location of a virtual track. The range is appended to the path as a media fragment
+/// (e.g. `album.flac#t=312.400,587.133`), so that the track can be identified from its location
+/// alone.
+pub fn virtual_track_location(path: &str, range: TrackRange) -> String {
+    match range.end {
+        Some(end) => format!("{}#t={:.3},{:.3}", path, range.start, end),
+        None => format!("{}#t={:.3}", path, range.start),
+    }
+}
+
+/// Splits a track location into the path of the underlying file and, if the location refers to a
+/// virtual track, the range of the file it occupies. Files whose names happen to end in something
+/// that looks like a range (e.g. `mix#t=60`) are left alone, as long as they exist.
+pub fn split_location(location: &str) -> (&str, Option<TrackRange>) {
+    let Some((path, fragment)) = location.rsplit_once("#t=") else {
+        return (location, None);
+    };
+
+    if Path::new(location).is_file() {
+        return (location, None);
+    }
+
+    let range = match fragment.split_once(',') {
+        Some((start, end)) => start
+            .parse()
+            .ok()
+            .zip(end.parse().ok())
+            .map(|(start, end)| TrackRange {
+                start,
+                end: Some(end),
+            }),
+        None => fragment
+            .parse()
+            .ok()
+            .map(|start| TrackRange { start, end: None }),
+    };
+
+    match range {
+        Some(range) => (path, Some(range)),
+        None => (location, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(number: u64, title: &str, start: f64, end: Option<f64>) -> CueTrack {
+        CueTrack {
+            number,
+            title: Some(title.to_string()),
+            start,
+            end,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_single_file_sheet() {
+        let text = "REM GENRE \"Jazz\"\n\
+            REM DATE 1959\n\
+            CATALOG 5099706472724\n\
+            PERFORMER \"Miles Davis\"\n\
+            TITLE \"Kind of Blue\"\n\
+            FILE \"Kind of Blue.flac\" WAVE\n\
+            \x20 TRACK 01 AUDIO\n\
+            \x20   TITLE \"So What\"\n\
+            \x20   ISRC USSM15900113\n\
+            \x20   INDEX 01 00:00:00\n\
+            \x20 TRACK 02 AUDIO\n\
+            \x20   TITLE \"Freddie \"Freeloader\"\"\n\
+            \x20   PERFORMER \"Miles Davis Sextet\"\n\
+            \x20   INDEX 01 09:22:30\n";
+
+        let sheet = CueSheet::parse(text).unwrap();
+
+        assert_eq!(sheet.title.as_deref(), Some("Kind of Blue"));
+        assert_eq!(sheet.performer.as_deref(), Some("Miles Davis"));
+        assert_eq!(sheet.genre.as_deref(), Some("Jazz"));
+        assert_eq!(sheet.date.as_deref(), Some("1959"));
+        assert_eq!(sheet.catalog.as_deref(), Some("5099706472724"));
+        assert_eq!(sheet.files.len(), 1);
+        assert_eq!(sheet.files[0].name, "Kind of Blue.flac");
+
+        // quotes inside of a quoted title are kept
+        let tracks = &sheet.files[0].tracks;
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].isrc.as_deref(), Some("USSM15900113"));
+        assert_eq!(tracks[0].end, Some(562.4));
+        assert_eq!(tracks[1].title.as_deref(), Some("Freddie \"Freeloader\""));
+        assert_eq!(tracks[1].performer.as_deref(), Some("Miles Davis Sextet"));
+        assert_eq!(tracks[1].start, 562.4);
+        assert_eq!(tracks[1].end, None);
+    }
+
+    #[test]
+    fn parses_multiple_files() {
+        let text = "FILE disc1.wav WAVE\n\
+            TRACK 1 AUDIO\n\
+            TITLE One\n\
+            INDEX 01 00:00:00\n\
+            TRACK 2 AUDIO\n\
+            TITLE Two\n\
+            INDEX 01 03:00:00\n\
+            FILE \"subdir\\disc 2.wav\" WAVE\n\
+            TRACK 3 AUDIO\n\
+            TITLE Three\n\
+            INDEX 01 00:00:00\n";
+
+        let sheet = CueSheet::parse(text).unwrap();
+
+        assert_eq!(
+            sheet.files,
+            vec![
+                CueFile {
+                    name: "disc1.wav".to_string(),
+                    tracks: vec![
+                        track(1, "One", 0.0, Some(180.0)),
+                        track(2, "Two", 180.0, None)
+                    ],
+                },
+                CueFile {
+                    name: "subdir\\disc 2.wav".to_string(),
+                    tracks: vec![track(3, "Three", 0.0, None)],
+                },
+            ]
+        );
+
+        // tracks are only ended by the next track in the same file, and files are matched by name
+        // alone
+        let disc2 = sheet
+            .tracks_for_file(Path::new("/music/DISC 2.wav"))
+            .unwrap();
+        assert_eq!(disc2[0].title.as_deref(), Some("Three"));
+        assert!(sheet
+            .tracks_for_file(Path::new("/music/disc3.wav"))
+            .is_none());
+        assert_eq!(
+            sheet.track_at(Path::new("/music/disc1.wav"), 180.0),
+            Some(&track(2, "Two", 180.0, None))
+        );
+    }
+
+    #[test]
+    fn pregaps_belong_to_the_previous_track() {
+        let text = "FILE album.flac WAVE\n\
+            TRACK 01 AUDIO\n\
+            INDEX 01 00:00:00\n\
+            TRACK 02 AUDIO\n\
+            INDEX 00 04:10:00\n\
+            INDEX 01 04:12:37\n\
+            TRACK 03 AUDIO\n\
+            PREGAP 00:02:00\n\
+            INDEX 01 08:00:00\n";
+
+        let tracks = CueSheet::parse(text).unwrap().files.remove(0).tracks;
+
+        // 37 frames is 0.49333... seconds
+        let start = 252.0 + 37.0 / 75.0;
+        assert_eq!(tracks[0].end, Some(start));
+        assert_eq!(tracks[1].start, start);
+        assert_eq!(tracks[1].end, Some(480.0));
+        assert_eq!(tracks[2].start, 480.0);
+    }
+
+    #[test]
+    fn rejects_sheets_without_tracks() {
+        assert_eq!(CueSheet::parse(""), None);
+        assert_eq!(CueSheet::parse("TITLE Nothing\nFILE a.wav WAVE\n"), None);
+        // tracks before the first FILE have nothing to refer to
+        assert_eq!(CueSheet::parse("TRACK 01 AUDIO\nINDEX 01 00:00:00\n"), None);
+    }
+
+    #[test]
+    fn decodes_encodings() {
+        let sheet = "TITLE \"Café\"\r\nFILE a.wav WAVE\r\nTRACK 01 AUDIO\r\n";
+
+        let mut utf8 = "\u{feff}".as_bytes().to_vec();
+        utf8.extend(sheet.as_bytes());
+
+        let latin1: Vec<u8> = sheet.chars().map(|v| v as u8).collect();
+
+        let mut utf16le = vec![0xff, 0xfe];
+        utf16le.extend(sheet.encode_utf16().flat_map(|v| v.to_le_bytes()));
+
+        let mut utf16be = vec![0xfe, 0xff];
+        utf16be.extend(sheet.encode_utf16().flat_map(|v| v.to_be_bytes()));
+
+        for data in [utf8, latin1, utf16le, utf16be] {
+            let text = decode_text(data);
+            assert_eq!(text, sheet);
+
+            let parsed = CueSheet::parse(&text).unwrap();
+            assert_eq!(parsed.title.as_deref(), Some("Café"));
+            assert_eq!(parsed.files[0].name, "a.wav");
+        }
+    }
+
+    #[test]
+    fn round_trips_locations() {
+        let range = TrackRange {
+            start: 312.4,
+            end: Some(587.133),
+        };
+
+        let location = virtual_track_location("/music/album.flac", range);
+        assert_eq!(location, "/music/album.flac#t=312.400,587.133");
+        assert_eq!(
+            split_location(&location),
+            ("/music/album.flac", Some(range))
+        );
+
+        let range = TrackRange {
+            start: 60.0,
+            end: None,
+        };
+
+        let location = virtual_track_location("/music/album.flac", range);
+        assert_eq!(
+            split_location(&location),
+            ("/music/album.flac", Some(range))
+        );
+
+        assert_eq!(
+            split_location("/music/song.flac"),
+            ("/music/song.flac", None)
+        );
+        assert_eq!(
+            split_location("/music/song#t=abc.flac"),
+            ("/music/song#t=abc.flac", None)
+        );
+    }
+
+    #[test]
+    fn does_not_split_existing_files() {
+        let dir = std::env::temp_dir().join(format!("muzak-cue-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("mix#t=60");
+        fs::write(&path, b"").unwrap();
+
+        let location = path.to_str().unwrap();
+        let split = split_location(location);
+        let missing = split_location(dir.join("other#t=60").to_str().unwrap()).1;
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(split, (location, None));
+        assert_eq!(
+            missing,
+            Some(TrackRange {
+                start: 60.0,
+                end: None
+            })
+        );
+    }
+}
@@ -1,25 +1,84 @@
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+use std::{fmt, io};
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum OpenError {
-    FileCorrupt,
-    UnsupportedFormat,
+    /// The media is damaged or truncated. Contains a description of what couldn't be read.
+    FileCorrupt(String),
+    /// The container isn't supported by the provider. Contains a description of the container, or
+    /// of the feature that isn't supported.
+    UnsupportedFormat(String),
+    NotFound,
+    /// The media couldn't be read because of an IO error other than it not existing.
+    Io(io::ErrorKind),
+    /// The media couldn't be retrieved over the network. Contains the error from the server or
+    /// the HTTP client.
+    NetworkError(String),
     Unknown,
 }
 
+impl From<io::Error> for OpenError {
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            io::ErrorKind::NotFound => OpenError::NotFound,
+            kind => OpenError::Io(kind),
+        }
+    }
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::FileCorrupt(v) => write!(f, "file is corrupt: {}", v),
+            OpenError::UnsupportedFormat(v) => write!(f, "unsupported format: {}", v),
+            OpenError::NotFound => write!(f, "file not found"),
+            OpenError::Io(kind) => write!(f, "could not read file: {}", kind),
+            OpenError::NetworkError(v) => write!(f, "network error: {}", v),
+            OpenError::Unknown => write!(f, "unknown error"),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum CloseError {
     Unknown,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+impl fmt::Display for CloseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not close file")
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum PlaybackStartError {
     NothingOpen,
     NothingToPlay,
-    Undecodable,
-    BrokenContainer,
-    ContainerSupportedButNotCodec,
+    /// A decoder exists for the codec, but it couldn't be created for this track. Contains the
+    /// decoder's error message.
+    Undecodable(String),
+    /// The container couldn't be read past its headers. Contains the demuxer's error message.
+    BrokenContainer(String),
+    /// The container was read, but there is no decoder for the codec inside of it. Contains the
+    /// name (or identifier) of the codec.
+    ContainerSupportedButNotCodec(String),
     Unknown,
 }
 
+impl fmt::Display for PlaybackStartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaybackStartError::NothingOpen => write!(f, "no file open"),
+            PlaybackStartError::NothingToPlay => write!(f, "file has no audio tracks"),
+            PlaybackStartError::Undecodable(v) => write!(f, "could not create decoder: {}", v),
+            PlaybackStartError::BrokenContainer(v) => write!(f, "container is broken: {}", v),
+            PlaybackStartError::ContainerSupportedButNotCodec(v) => {
+                write!(f, "unsupported codec: {}", v)
+            }
+            PlaybackStartError::Unknown => write!(f, "unknown error"),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum PlaybackStopError {
     NothingOpen,
@@ -42,6 +101,16 @@ pub enum MetadataError {
     Unknown,
 }
 
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::NothingOpen => write!(f, "no file open"),
+            MetadataError::OperationUnsupported => write!(f, "provider does not read metadata"),
+            MetadataError::Unknown => write!(f, "could not read metadata"),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum FrameDurationError {
     NothingOpen,
@@ -56,6 +125,16 @@ pub enum TrackDurationError {
     Unknown,
 }
 
+impl fmt::Display for TrackDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackDurationError::NothingOpen => write!(f, "no file open"),
+            TrackDurationError::NeverStarted => write!(f, "duration is unknown"),
+            TrackDurationError::Unknown => write!(f, "could not read duration"),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum SeekError {
     NothingOpen,
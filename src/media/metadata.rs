@@ -24,4 +24,87 @@ pub struct Metadata {
     pub label: Option<String>,
     pub catalog: Option<String>,
     pub isrc: Option<String>,
+    /// The MusicBrainz release ID, which tells apart releases that share a title and artist.
+    pub release_mbid: Option<String>,
+
+    /// The text of a CUE sheet embedded in the file's metadata, if there is one.
+    pub cuesheet: Option<String>,
+}
+
+/// What an embedded image depicts. This is a simplified version of the picture types defined by
+/// ID3v2 and FLAC, collapsed into the categories the library can actually display.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ImageUsage {
+    FrontCover,
+    BackCover,
+    Booklet,
+    Media,
+    Artist,
+    Other,
+}
+
+impl ImageUsage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageUsage::FrontCover => "front_cover",
+            ImageUsage::BackCover => "back_cover",
+            ImageUsage::Booklet => "booklet",
+            ImageUsage::Media => "media",
+            ImageUsage::Artist => "artist",
+            ImageUsage::Other => "other",
+        }
+    }
+}
+
+impl From<&str> for ImageUsage {
+    fn from(value: &str) -> Self {
+        match value {
+            "front_cover" => ImageUsage::FrontCover,
+            "back_cover" => ImageUsage::BackCover,
+            "booklet" => ImageUsage::Booklet,
+            "media" => ImageUsage::Media,
+            "artist" => ImageUsage::Artist,
+            _ => ImageUsage::Other,
+        }
+    }
+}
+
+impl From<String> for ImageUsage {
+    fn from(value: String) -> Self {
+        ImageUsage::from(value.as_str())
+    }
+}
+
+/// An image embedded in a file's metadata, along with its usage and mime-type.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EmbeddedImage {
+    pub usage: ImageUsage,
+    pub mime: String,
+    pub data: Box<[u8]>,
+}
+
+/// A chapter marker within a file. Times are in seconds from the start of the file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Chapter {
+    pub title: Option<String>,
+    pub start: f64,
+    /// The end of the chapter, or None if the chapter runs until the end of the file.
+    pub end: Option<f64>,
+}
+
+/// Technical information about an audio stream, read from the container's headers.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct StreamInfo {
+    /// The short name of the codec, e.g. "flac" or "mp3".
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// The number of bits per sample. Only lossless codecs have a meaningful bit depth.
+    pub bit_depth: Option<u32>,
+    pub channels: Option<u16>,
+    /// The average bitrate of the file, in bits per second.
+    pub bitrate: Option<u32>,
+    /// Whether the codec reproduces the original audio exactly.
+    pub lossless: Option<bool>,
+    /// The duration of the stream in seconds, if it could be determined.
+    pub duration: Option<u64>,
 }
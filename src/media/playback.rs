@@ -23,8 +23,19 @@ impl Samples {
             Samples::Float32(_) => format == SampleFormat::Float32,
             Samples::Signed32(_) => format == SampleFormat::Signed32,
             Samples::Unsigned32(_) => format == SampleFormat::Unsigned32,
-            Samples::Signed24(_) => format == SampleFormat::Signed24,
-            Samples::Unsigned24(_) => format == SampleFormat::Unsigned24,
+            // packing is up to the device, the samples themselves are the same
+            Samples::Signed24(_) => {
+                matches!(
+                    format,
+                    SampleFormat::Signed24 | SampleFormat::Signed24Packed
+                )
+            }
+            Samples::Unsigned24(_) => {
+                matches!(
+                    format,
+                    SampleFormat::Unsigned24 | SampleFormat::Unsigned24Packed
+                )
+            }
             Samples::Signed16(_) => format == SampleFormat::Signed16,
             Samples::Unsigned16(_) => format == SampleFormat::Unsigned16,
             Samples::Signed8(_) => format == SampleFormat::Signed8,
@@ -32,6 +43,45 @@ impl Samples {
             Samples::DSD(_) => format == SampleFormat::DSD,
         }
     }
+
+    /// Returns the number of samples in each channel.
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::Float64(v) => v.first().map(|c| c.len()),
+            Samples::Float32(v) => v.first().map(|c| c.len()),
+            Samples::Signed32(v) => v.first().map(|c| c.len()),
+            Samples::Unsigned32(v) => v.first().map(|c| c.len()),
+            Samples::Signed24(v) => v.first().map(|c| c.len()),
+            Samples::Unsigned24(v) => v.first().map(|c| c.len()),
+            Samples::Signed16(v) => v.first().map(|c| c.len()),
+            Samples::Unsigned16(v) => v.first().map(|c| c.len()),
+            Samples::Signed8(v) => v.first().map(|c| c.len()),
+            Samples::Unsigned8(v) => v.first().map(|c| c.len()),
+            Samples::DSD(v) => v.first().map(|c| c.len()),
+        }
+        .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Shortens every channel to the specified number of samples.
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            Samples::Float64(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Float32(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Signed32(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Unsigned32(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Signed24(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Unsigned24(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Signed16(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Unsigned16(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Signed8(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::Unsigned8(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+            Samples::DSD(v) => v.iter_mut().for_each(|c| c.truncate(len)),
+        }
+    }
 }
 
 pub trait Mute {
@@ -0,0 +1,595 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use super::stream::{fetch_text, is_stream_location};
+
+/// An entry in a playlist or station file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PlaylistEntry {
+    pub location: String,
+    pub title: Option<String>,
+    /// The length of the entry in seconds, or None for streams and unknown lengths.
+    pub duration: Option<u64>,
+}
+
+const PLAYLIST_EXTENSIONS: &[&str] = &["pls", "m3u", "m3u8", "xspf"];
+
+/// Returns true if the location refers to a playlist file (PLS, M3U or XSPF), which should be resolved
+/// to the entries it lists rather than played directly.
+pub fn is_playlist_location(location: &str) -> bool {
+    // ignore any query string on URLs
+    let path = location.split(['?', '#']).next().unwrap_or(location);
+
+    Path::new(path)
+        .extension()
+        .and_then(|v| v.to_str())
+        .is_some_and(|ext| {
+            PLAYLIST_EXTENSIONS
+                .iter()
+                .any(|v| ext.eq_ignore_ascii_case(v))
+        })
+}
+
+/// Resolves a playlist entry relative to the location of the playlist. URLs and absolute paths
+/// are returned as-is.
+fn resolve_entry(entry: &str, base: Option<&Path>) -> String {
+    if is_stream_location(entry) || Path::new(entry).is_absolute() {
+        return entry.to_string();
+    }
+
+    match base {
+        Some(base) => base.join(entry).to_string_lossy().to_string(),
+        None => entry.to_string(),
+    }
+}
+
+fn parse_duration(value: &str) -> Option<u64> {
+    value
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|v| *v >= 0)
+        .map(|v| v as u64)
+}
+
+/// Parses a PLS playlist. Entries are numbered (File1, Title1, Length1, ...), and are returned in
+/// order of their numbers.
+pub fn parse_pls(text: &str, base: Option<&Path>) -> Vec<PlaylistEntry> {
+    let mut entries: Vec<(u64, PlaylistEntry)> = Vec::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let (field, number) = match key.find(|c: char| c.is_ascii_digit()) {
+            Some(split) => (&key[..split], &key[split..]),
+            None => continue,
+        };
+
+        let Ok(number) = number.parse::<u64>() else {
+            continue;
+        };
+
+        let index = match entries.iter().position(|(n, _)| *n == number) {
+            Some(index) => index,
+            None => {
+                entries.push((
+                    number,
+                    PlaylistEntry {
+                        location: String::new(),
+                        title: None,
+                        duration: None,
+                    },
+                ));
+                entries.len() - 1
+            }
+        };
+
+        let entry = &mut entries[index].1;
+
+        match field {
+            "file" => entry.location = resolve_entry(value, base),
+            "title" => entry.title = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "length" => entry.duration = parse_duration(value),
+            _ => (),
+        }
+    }
+
+    entries.sort_by_key(|(number, _)| *number);
+
+    entries
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+/// Parses an M3U or extended M3U (#EXTINF) playlist.
+pub fn parse_m3u(text: &str, base: Option<&Path>) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending_info: Option<(Option<u64>, Option<String>)> = None;
+
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, title) = match info.split_once(',') {
+                Some((duration, title)) => (duration, Some(title.trim().to_string())),
+                None => (info, None),
+            };
+
+            // the duration may be followed by attributes (e.g. `-1 tvg-name="..."`)
+            let duration = duration.split_whitespace().next().and_then(parse_duration);
+
+            pending_info = Some((duration, title.filter(|v| !v.is_empty())));
+            continue;
+        }
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, title) = pending_info.take().unwrap_or_default();
+
+        entries.push(PlaylistEntry {
+            location: resolve_entry(line, base),
+            title,
+            duration,
+        });
+    }
+
+    entries
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+
+        let entity = &rest[1..end];
+        let value = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|v| u32::from_str_radix(v, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|v| v.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match value {
+            Some(value) => {
+                decoded.push(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn encode_entities(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn decode_percent(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|v| u8::from_str_radix(v, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn encode_percent(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+/// Returns the text of the first `<name>` element in the XML fragment, with entities decoded.
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+
+    let mut search = xml;
+    let start = loop {
+        let start = search.find(&open)?;
+        let after = &search[start + open.len()..];
+
+        // make sure that this isn't a longer element name with the same prefix
+        if after.starts_with(['>', ' ', '\t', '\r', '\n']) {
+            break xml.len() - after.len();
+        }
+
+        search = after;
+    };
+
+    let content_start = start + xml[start..].find('>')? + 1;
+    let content_end = content_start + xml[content_start..].find(&close)?;
+    let content = xml[content_start..content_end].trim();
+
+    let content = content
+        .strip_prefix("<![CDATA[")
+        .and_then(|v| v.strip_suffix("]]>"))
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| decode_entities(content));
+
+    Some(content).filter(|v| !v.is_empty())
+}
+
+/// Converts an XSPF location (a URI) into a path or URL. Relative URIs are resolved against the
+/// location of the playlist.
+fn resolve_uri(uri: &str, base: Option<&Path>) -> String {
+    if let Some(path) = uri.strip_prefix("file://") {
+        // file:///path and file://localhost/path are both local
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        let path = decode_percent(path);
+
+        // file:///C:/Music/... on Windows
+        let is_drive_path = path.len() > 2 && path.as_bytes()[2] == b':';
+        return match is_drive_path {
+            true => path[1..].to_string(),
+            false => path,
+        };
+    }
+
+    if is_stream_location(uri) {
+        return uri.to_string();
+    }
+
+    resolve_entry(&decode_percent(uri), base)
+}
+
+/// Parses an XSPF playlist. Durations in XSPF files are in milliseconds, and are rounded down to
+/// seconds.
+pub fn parse_xspf(text: &str, base: Option<&Path>) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("<track") {
+        let after = &rest[start + "<track".len()..];
+
+        // skip <trackList> and <trackNum>
+        if !after.starts_with(['>', ' ', '\t', '\r', '\n']) {
+            rest = after;
+            continue;
+        }
+
+        let end = after.find("</track>").unwrap_or(after.len());
+        let track = &after[..end];
+        rest = &after[end..];
+
+        let Some(location) = element_text(track, "location") else {
+            continue;
+        };
+
+        entries.push(PlaylistEntry {
+            location: resolve_uri(&location, base),
+            title: element_text(track, "title"),
+            duration: element_text(track, "duration")
+                .as_deref()
+                .and_then(parse_duration)
+                .map(|v| v / 1000),
+        });
+    }
+
+    entries
+}
+
+/// Reads a playlist from disk or from a URL. The format is determined by the contents of the
+/// file, since station files served over HTTP frequently have misleading extensions.
+pub fn read_playlist(location: &str) -> Option<Vec<PlaylistEntry>> {
+    let (text, base) = if is_stream_location(location) {
+        (fetch_text(location)?, None)
+    } else {
+        let data = fs::read(location).ok()?;
+        let text = match String::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|v| *v as char).collect(),
+        };
+
+        (text, Path::new(location).parent())
+    };
+
+    let start = text
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_ascii_lowercase();
+
+    let entries = if start.starts_with("[playlist]") {
+        parse_pls(&text, base)
+    } else if start.starts_with("<?xml") || start.starts_with("<playlist") {
+        parse_xspf(&text, base)
+    } else {
+        parse_m3u(&text, base)
+    };
+
+    Some(entries)
+}
+
+/// The formats playlists can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    /// Extended M3U, always written as UTF-8 (M3U8).
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// Picks the format from the extension of the path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the location relative to the folder `base`, if they share more than the root of the
+/// filesystem. Other locations (including URLs) are returned as-is, since a path like
+/// `../../../../home/...` is no more portable than an absolute one.
+fn relative_location(location: &str, base: Option<&Path>) -> String {
+    let path = Path::new(location);
+
+    let Some(base) = base.filter(|_| !is_stream_location(location) && path.is_absolute()) else {
+        return location.to_string();
+    };
+
+    let path_components: Vec<_> = path.components().collect();
+    let base_components: Vec<_> = base.components().collect();
+
+    let common = path_components
+        .iter()
+        .zip(base_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let shares_root_only = path_components
+        .iter()
+        .take(common)
+        .all(|v| matches!(v, Component::RootDir | Component::Prefix(_)));
+
+    if shares_root_only {
+        return location.to_string();
+    }
+
+    let mut relative = PathBuf::new();
+
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+
+    for component in &path_components[common..] {
+        relative.push(component);
+    }
+
+    relative.to_string_lossy().to_string()
+}
+
+/// Converts a location into a URI for an XSPF playlist.
+fn location_uri(location: &str, base: Option<&Path>) -> String {
+    if is_stream_location(location) {
+        return location.to_string();
+    }
+
+    let relative = relative_location(location, base).replace('\\', "/");
+
+    if Path::new(&relative).is_absolute() {
+        match relative.starts_with('/') {
+            true => format!("file://{}", encode_percent(&relative)),
+            false => format!("file:///{}", encode_percent(&relative)),
+        }
+    } else {
+        encode_percent(&relative)
+    }
+}
+
+/// Writes the entries as a playlist in the given format. Paths are written relative to `base`
+/// (the folder the playlist is saved in) where possible, so that the playlist keeps working when
+/// the folder is moved.
+pub fn write_playlist(
+    entries: &[PlaylistEntry],
+    format: PlaylistFormat,
+    base: Option<&Path>,
+) -> String {
+    let mut text = String::new();
+
+    match format {
+        PlaylistFormat::M3u => {
+            text.push_str("#EXTM3U\n");
+
+            for entry in entries {
+                if entry.title.is_some() || entry.duration.is_some() {
+                    let duration = entry.duration.map(|v| v as i64).unwrap_or(-1);
+                    let title = entry.title.as_deref().unwrap_or_default();
+                    text.push_str(&format!("#EXTINF:{},{}\n", duration, title));
+                }
+
+                text.push_str(&relative_location(&entry.location, base));
+                text.push('\n');
+            }
+        }
+        PlaylistFormat::Pls => {
+            text.push_str("[playlist]\n");
+
+            for (i, entry) in entries.iter().enumerate() {
+                let number = i + 1;
+                let location = relative_location(&entry.location, base);
+
+                text.push_str(&format!("File{}={}\n", number, location));
+
+                if let Some(title) = &entry.title {
+                    text.push_str(&format!("Title{}={}\n", number, title));
+                }
+
+                let duration = entry.duration.map(|v| v as i64).unwrap_or(-1);
+                text.push_str(&format!("Length{}={}\n", number, duration));
+            }
+
+            text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+        }
+        PlaylistFormat::Xspf => {
+            text.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+            text.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+            text.push_str("  <trackList>\n");
+
+            for entry in entries {
+                let location = location_uri(&entry.location, base);
+
+                text.push_str("    <track>\n");
+                text.push_str(&format!(
+                    "      <location>{}</location>\n",
+                    encode_entities(&location)
+                ));
+
+                if let Some(title) = &entry.title {
+                    text.push_str(&format!(
+                        "      <title>{}</title>\n",
+                        encode_entities(title)
+                    ));
+                }
+
+                if let Some(duration) = entry.duration {
+                    text.push_str(&format!("      <duration>{}</duration>\n", duration * 1000));
+                }
+
+                text.push_str("    </track>\n");
+            }
+
+            text.push_str("  </trackList>\n</playlist>\n");
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(location: &str, title: Option<&str>, duration: Option<u64>) -> PlaylistEntry {
+        PlaylistEntry {
+            location: location.to_string(),
+            title: title.map(|v| v.to_string()),
+            duration,
+        }
+    }
+
+    #[test]
+    fn parses_pls() {
+        let text = "[playlist]\n\
+            File2=http://example.com/stream\n\
+            Title2=Example Radio\n\
+            Length2=-1\n\
+            file1 = song.mp3\n\
+            TITLE1=\n\
+            Length1=215\n\
+            Title3=No file\n\
+            NumberOfEntries=3\n\
+            Version=2\n";
+
+        let base = Path::new("/music");
+        let song = base.join("song.mp3").to_string_lossy().to_string();
+
+        // entries are ordered by number, and entries without a file are dropped
+        assert_eq!(
+            parse_pls(text, Some(base)),
+            vec![
+                entry(&song, None, Some(215)),
+                entry("http://example.com/stream", Some("Example Radio"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_m3u() {
+        let text = "\u{feff}#EXTM3U\n\
+            #EXTINF:123,Artist - Title\n\
+            song.flac\n\
+            \n\
+            # a comment\n\
+            /absolute/other.mp3\n\
+            #EXTINF:-1 tvg-name=\"Radio\",Radio\n\
+            https://example.com/live\n";
+
+        let base = Path::new("/music");
+        let song = base.join("song.flac").to_string_lossy().to_string();
+
+        assert_eq!(
+            parse_m3u(text, Some(base)),
+            vec![
+                entry(&song, Some("Artist - Title"), Some(123)),
+                entry("/absolute/other.mp3", None, None),
+                entry("https://example.com/live", Some("Radio"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_m3u_without_base() {
+        assert_eq!(
+            parse_m3u("a.mp3\nb.mp3\n", None),
+            vec![entry("a.mp3", None, None), entry("b.mp3", None, None)]
+        );
+    }
+}
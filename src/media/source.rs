@@ -0,0 +1,171 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+pub use symphonia::core::io::MediaSource;
+use tracing::info;
+
+use super::{
+    archive::{open_entry, split_archive_location},
+    errors::OpenError,
+    playlist::{is_playlist_location, read_playlist},
+    stream::{is_stream_location, HttpRangeSource, HttpStream, IcyHandle},
+};
+
+/// Media opened from a location, ready to be handed to a MediaProvider. Every kind of source
+/// reports whether it can be seeked and how long it is (if known) through the MediaSource trait,
+/// so providers don't need to know where the media came from.
+pub struct OpenedSource {
+    pub source: Box<dyn MediaSource>,
+    /// An extension hint for the MediaProvider, if one could be determined.
+    pub ext: Option<String>,
+    /// The ICY metadata of the stream, if the media is a live network stream.
+    pub icy: Option<IcyHandle>,
+}
+
+/// Returns the lowercase extension of a path or URL, ignoring any query string or fragment.
+fn location_extension(location: &str) -> Option<String> {
+    let path = location.split(['?', '#']).next().unwrap_or(location);
+
+    Path::new(path)
+        .extension()
+        .and_then(|v| v.to_str())
+        .map(|v| v.to_ascii_lowercase())
+}
+
+impl OpenedSource {
+    /// Opens a local file.
+    pub fn file(path: &Path) -> Result<OpenedSource, OpenError> {
+        let file = File::open(path)?;
+
+        Ok(OpenedSource {
+            source: Box::new(file),
+            ext: path
+                .extension()
+                .and_then(|v| v.to_str())
+                .map(|v| v.to_ascii_lowercase()),
+            icy: None,
+        })
+    }
+
+    /// Wraps media that has already been read into memory.
+    pub fn memory(data: Vec<u8>, ext: Option<String>) -> OpenedSource {
+        OpenedSource {
+            source: Box::new(Cursor::new(data)),
+            ext,
+            icy: None,
+        }
+    }
+
+    /// Opens media served over HTTP or HTTPS. Files on servers that support range requests are
+    /// opened as seekable sources, anything else is treated as a live stream.
+    pub fn http(url: &str) -> Result<OpenedSource, OpenError> {
+        if let Some(source) = HttpRangeSource::open(url) {
+            return Ok(OpenedSource {
+                ext: source.extension().or_else(|| location_extension(url)),
+                source: Box::new(source),
+                icy: None,
+            });
+        }
+
+        let stream = HttpStream::open(url)?;
+
+        Ok(OpenedSource {
+            ext: stream.extension().or_else(|| location_extension(url)),
+            icy: Some(stream.icy()),
+            source: Box::new(stream),
+        })
+    }
+}
+
+/// Opens the media at the specified location, which may be a local file, an entry inside of an
+/// archive (`album.zip!/01.flac`), a URL, or a station file (PLS or M3U) pointing to a stream.
+/// Virtual track locations must be split (with `cue::split_location`) before they're opened.
+pub fn open_location(location: &str) -> Result<OpenedSource, OpenError> {
+    // station files are resolved to the first stream they list
+    if is_playlist_location(location) {
+        let entry = read_playlist(location)
+            .and_then(|entries| entries.into_iter().next())
+            .ok_or(OpenError::NotFound)?;
+
+        info!("Resolved station file {} to {}", location, entry.location);
+
+        return if is_stream_location(&entry.location) {
+            OpenedSource::http(&entry.location)
+        } else {
+            OpenedSource::file(Path::new(&entry.location))
+        };
+    }
+
+    if let Some((archive, entry)) = split_archive_location(location) {
+        return open_entry(archive, entry);
+    }
+
+    if is_stream_location(location) {
+        OpenedSource::http(location)
+    } else {
+        OpenedSource::file(Path::new(location))
+    }
+}
+
+/// A window onto part of another source, such as an uncompressed entry inside of an archive.
+/// Positions are relative to the start of the window.
+pub struct RangeSource<S: MediaSource> {
+    inner: S,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl<S: MediaSource> RangeSource<S> {
+    pub fn new(mut inner: S, start: u64, len: u64) -> io::Result<RangeSource<S>> {
+        inner.seek(SeekFrom::Start(start))?;
+
+        Ok(RangeSource {
+            inner,
+            start,
+            len,
+            position: 0,
+        })
+    }
+}
+
+impl<S: MediaSource> Read for RangeSource<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let len = (buf.len() as u64).min(remaining) as usize;
+
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl<S: MediaSource> Seek for RangeSource<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(v) => Some(v),
+            SeekFrom::End(v) => self.len.checked_add_signed(v),
+            SeekFrom::Current(v) => self.position.checked_add_signed(v),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+
+        self.inner.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+
+        Ok(position)
+    }
+}
+
+impl<S: MediaSource> MediaSource for RangeSource<S> {
+    fn is_seekable(&self) -> bool {
+        self.inner.is_seekable()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+}
@@ -0,0 +1,684 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use symphonia::core::io::MediaSource;
+use tracing::{info, warn};
+
+use super::{errors::OpenError, metadata::Metadata};
+
+const CHUNK_SIZE: usize = 16 * 1024;
+/// The number of chunks that are buffered ahead of the decoder (roughly 1MB).
+const BUFFERED_CHUNKS: usize = 64;
+/// The number of times a dropped stream is reconnected before playback gives up.
+const MAX_RECONNECTS: u64 = 5;
+
+/// Returns true if the location refers to a network stream rather than a local file.
+pub fn is_stream_location(location: &str) -> bool {
+    let location = location.to_ascii_lowercase();
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(10))
+        .timeout_read(Duration::from_secs(15))
+        .user_agent(concat!("muzak/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Requests a stream, asking the server to interleave ICY metadata if it supports it. Note that
+/// Shoutcast v1 servers, which respond with `ICY 200 OK` rather than an HTTP status line, are not
+/// supported.
+fn connect(url: &str) -> Result<ureq::Response, Box<ureq::Error>> {
+    agent()
+        .get(url)
+        .set("Icy-MetaData", "1")
+        .call()
+        .map_err(Box::new)
+}
+
+/// Downloads a small text file, such as a PLS or M3U station file.
+pub fn fetch_text(url: &str) -> Option<String> {
+    match agent().get(url).call() {
+        Ok(response) => response.into_string().ok(),
+        Err(e) => {
+            warn!("Could not fetch {}: {:?}", url, e);
+            None
+        }
+    }
+}
+
+/// Returns the file extension matching a content type, to be used as a hint for the
+/// MediaProvider.
+fn mime_extension(content_type: &str) -> Option<String> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+
+    let extension = match mime.as_str() {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/aac" | "audio/aacp" | "audio/x-aac" => "aac",
+        "audio/ogg" | "application/ogg" | "audio/vorbis" | "audio/opus" => "ogg",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/webm" => "webm",
+        "audio/x-matroska" => "mka",
+        _ => return None,
+    };
+
+    Some(extension.to_string())
+}
+
+/// Metadata sent by Shoutcast and Icecast servers, either in the response headers or interleaved
+/// with the audio data.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct IcyMetadata {
+    /// The name of the station, from the icy-name header.
+    pub station: Option<String>,
+    pub genre: Option<String>,
+    /// The title of what is currently playing, from the StreamTitle field.
+    pub title: Option<String>,
+}
+
+impl IcyMetadata {
+    /// Fills in the metadata of the current track. StreamTitle is conventionally formatted as
+    /// "Artist - Title", and is split accordingly.
+    pub fn apply_to(&self, metadata: &mut Metadata) {
+        match self.title.as_deref().map(|v| v.split_once(" - ")) {
+            Some(Some((artist, title))) => {
+                metadata.artist = Some(artist.trim().to_string());
+                metadata.name = Some(title.trim().to_string());
+            }
+            Some(None) => metadata.name = self.title.clone(),
+            None => {
+                if metadata.name.is_none() {
+                    metadata.name = self.station.clone();
+                }
+            }
+        }
+
+        if metadata.album.is_none() {
+            metadata.album = self.station.clone();
+        }
+
+        if metadata.genre.is_none() {
+            metadata.genre = self.genre.clone();
+        }
+    }
+}
+
+#[derive(Default)]
+struct IcyState {
+    metadata: IcyMetadata,
+    updated: bool,
+}
+
+/// A handle to the ICY metadata of a stream, which is updated as the stream is read.
+#[derive(Clone, Default)]
+pub struct IcyHandle(Arc<Mutex<IcyState>>);
+
+impl IcyHandle {
+    fn new(metadata: IcyMetadata) -> Self {
+        IcyHandle(Arc::new(Mutex::new(IcyState {
+            metadata,
+            updated: true,
+        })))
+    }
+
+    fn set_title(&self, title: Option<String>) {
+        let mut state = self.0.lock().expect("icy state poisoned");
+
+        if state.metadata.title != title {
+            state.metadata.title = title;
+            state.updated = true;
+        }
+    }
+
+    pub fn metadata(&self) -> IcyMetadata {
+        self.0.lock().expect("icy state poisoned").metadata.clone()
+    }
+
+    /// Returns whether or not the metadata has changed since the last call to this function.
+    pub fn take_updated(&self) -> bool {
+        std::mem::take(&mut self.0.lock().expect("icy state poisoned").updated)
+    }
+}
+
+/// Decodes text sent by a server, which is frequently Latin-1 rather than UTF-8.
+fn decode_text(data: Vec<u8>) -> String {
+    match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|v| *v as char).collect(),
+    }
+}
+
+/// Extracts the StreamTitle field from an ICY metadata block (e.g.
+/// `StreamTitle='Artist - Title';StreamUrl='';`).
+fn parse_stream_title(block: &str) -> Option<String> {
+    let start = block.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &block[start..];
+    let end = rest.find("';").unwrap_or(rest.len());
+    let title = rest[..end].trim_end_matches(['\0', '\'']).trim();
+
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Strips the ICY metadata blocks out of a stream. When the server supports ICY metadata, a block
+/// is sent every `metaint` bytes of audio.
+struct IcyReader {
+    inner: Box<dyn Read + Send + Sync>,
+    metaint: Option<usize>,
+    until_metadata: usize,
+    icy: IcyHandle,
+}
+
+impl IcyReader {
+    fn read_metadata_block(&mut self) -> io::Result<()> {
+        let mut length = [0u8; 1];
+        self.inner.read_exact(&mut length)?;
+
+        if length[0] > 0 {
+            let mut block = vec![0u8; length[0] as usize * 16];
+            self.inner.read_exact(&mut block)?;
+
+            self.icy.set_title(parse_stream_title(&decode_text(block)));
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for IcyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(metaint) = self.metaint else {
+            return self.inner.read(buf);
+        };
+
+        if self.until_metadata == 0 {
+            self.read_metadata_block()?;
+            self.until_metadata = metaint;
+        }
+
+        let len = buf.len().min(self.until_metadata);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.until_metadata -= read;
+
+        Ok(read)
+    }
+}
+
+fn icy_reader(response: ureq::Response, icy: IcyHandle) -> IcyReader {
+    let metaint = response
+        .header("icy-metaint")
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|v| *v > 0);
+
+    IcyReader {
+        inner: response.into_reader(),
+        metaint,
+        until_metadata: metaint.unwrap_or_default(),
+        icy,
+    }
+}
+
+/// Reads the stream into the buffer until the stream is dropped. Live streams are reconnected if
+/// they end or the connection drops, since they aren't supposed to end.
+fn buffer_stream(url: String, response: ureq::Response, tx: SyncSender<Vec<u8>>, icy: IcyHandle) {
+    let mut content_length: Option<u64> = response
+        .header("content-length")
+        .and_then(|v| v.parse().ok());
+    let mut reader = Some(icy_reader(response, icy.clone()));
+    let mut received = 0;
+    let mut reconnects = 0;
+
+    loop {
+        if let Some(stream) = reader.as_mut() {
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+
+            match stream.read(&mut chunk) {
+                Ok(0) => reader = None,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    received += read as u64;
+                    reconnects = 0;
+
+                    if tx.send(chunk).is_err() {
+                        // the stream has been closed
+                        return;
+                    }
+
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Stream {} interrupted: {:?}", url, e);
+                    reader = None;
+                }
+            }
+        }
+
+        // streams with a known length are files, which are finished once they've been read
+        if content_length.is_some_and(|length| received >= length) {
+            return;
+        }
+
+        if reconnects >= MAX_RECONNECTS {
+            warn!("Stream {} could not be reconnected, giving up", url);
+            return;
+        }
+
+        reconnects += 1;
+        thread::sleep(Duration::from_secs(reconnects));
+        info!("Reconnecting to {} (attempt {})", url, reconnects);
+
+        match connect(&url) {
+            Ok(response) => {
+                content_length = None;
+                reader = Some(icy_reader(response, icy.clone()));
+            }
+            Err(e) => warn!("Could not reconnect to {}: {:?}", url, e),
+        }
+    }
+}
+
+/// A MediaSource that plays audio from an HTTP or HTTPS stream. The stream is buffered on a
+/// separate thread, so that brief network stalls don't interrupt playback.
+pub struct HttpStream {
+    chunks: Mutex<Receiver<Vec<u8>>>,
+    current: Vec<u8>,
+    offset: usize,
+    content_type: Option<String>,
+    icy: IcyHandle,
+}
+
+impl HttpStream {
+    pub fn open(url: &str) -> Result<HttpStream, OpenError> {
+        let response = connect(url).map_err(|e| {
+            warn!("Could not open stream {}: {:?}", url, e);
+            OpenError::NetworkError(e.to_string())
+        })?;
+
+        let content_type = response
+            .header("content-type")
+            .map(|v| v.to_ascii_lowercase());
+
+        let icy = IcyHandle::new(IcyMetadata {
+            station: response.header("icy-name").map(|v| v.trim().to_string()),
+            genre: response.header("icy-genre").map(|v| v.trim().to_string()),
+            title: None,
+        });
+
+        let (tx, rx) = mpsc::sync_channel(BUFFERED_CHUNKS);
+        let thread_icy = icy.clone();
+        let url = url.to_string();
+
+        thread::Builder::new()
+            .name("stream".to_string())
+            .spawn(move || buffer_stream(url, response, tx, thread_icy))?;
+
+        Ok(HttpStream {
+            chunks: Mutex::new(rx),
+            current: Vec::new(),
+            offset: 0,
+            content_type,
+            icy,
+        })
+    }
+
+    /// Returns the file extension matching the stream's content type, to be used as a hint for
+    /// the MediaProvider.
+    pub fn extension(&self) -> Option<String> {
+        mime_extension(self.content_type.as_deref()?)
+    }
+
+    pub fn icy(&self) -> IcyHandle {
+        self.icy.clone()
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.current.len() {
+            let chunk = self.chunks.lock().expect("stream buffer poisoned").recv();
+
+            match chunk {
+                Ok(chunk) => {
+                    self.current = chunk;
+                    self.offset = 0;
+                }
+                // the buffering thread has stopped, so the stream has ended
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let read = buf.len().min(self.current.len() - self.offset);
+        buf[..read].copy_from_slice(&self.current[self.offset..self.offset + read]);
+        self.offset += read;
+
+        Ok(read)
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "streams cannot be seeked",
+        ))
+    }
+}
+
+impl MediaSource for HttpStream {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// The amount of data requested at a time from servers that support range requests.
+const RANGE_BLOCK_SIZE: u64 = 256 * 1024;
+
+/// A seekable MediaSource for files served over HTTP or HTTPS by servers that support range
+/// requests. Data is requested in blocks as it's read, rather than downloading the entire file.
+pub struct HttpRangeSource {
+    url: String,
+    agent: ureq::Agent,
+    content_type: Option<String>,
+    len: u64,
+    position: u64,
+    block_start: u64,
+    block: Vec<u8>,
+}
+
+impl HttpRangeSource {
+    /// Opens a file served over HTTP. Returns None if the server doesn't support range requests,
+    /// or if the URL points to a live stream, in which case HttpStream should be used instead.
+    pub fn open(url: &str) -> Option<HttpRangeSource> {
+        let agent = agent();
+        let response = agent.head(url).call().ok()?;
+
+        let supports_ranges = response
+            .header("accept-ranges")
+            .is_some_and(|v| v.eq_ignore_ascii_case("bytes"));
+        let is_live =
+            response.header("icy-name").is_some() || response.header("icy-metaint").is_some();
+
+        if !supports_ranges || is_live {
+            return None;
+        }
+
+        let len = response.header("content-length")?.trim().parse().ok()?;
+
+        Some(HttpRangeSource {
+            url: url.to_string(),
+            content_type: response.header("content-type").map(|v| v.to_string()),
+            agent,
+            len,
+            position: 0,
+            block_start: 0,
+            block: Vec::new(),
+        })
+    }
+
+    /// Returns the file extension matching the file's content type, to be used as a hint for the
+    /// MediaProvider.
+    pub fn extension(&self) -> Option<String> {
+        mime_extension(self.content_type.as_deref()?)
+    }
+
+    fn fetch_block(&mut self, start: u64) -> io::Result<()> {
+        let end = (start + RANGE_BLOCK_SIZE).min(self.len) - 1;
+
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", start, end))
+            .call()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        if response.status() != 206 {
+            return Err(io::Error::other("server ignored range request"));
+        }
+
+        let mut block = Vec::with_capacity((end - start + 1) as usize);
+        response.into_reader().read_to_end(&mut block)?;
+
+        self.block_start = start;
+        self.block = block;
+
+        Ok(())
+    }
+}
+
+impl Read for HttpRangeSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len {
+            return Ok(0);
+        }
+
+        let block_end = self.block_start + self.block.len() as u64;
+
+        if self.position < self.block_start || self.position >= block_end {
+            self.fetch_block(self.position)?;
+        }
+
+        let offset = (self.position - self.block_start) as usize;
+        let read = buf.len().min(self.block.len() - offset);
+        buf[..read].copy_from_slice(&self.block[offset..offset + read]);
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl Seek for HttpRangeSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(v) => Some(v),
+            SeekFrom::End(v) => self.len.checked_add_signed(v),
+            SeekFrom::Current(v) => self.position.checked_add_signed(v),
+        };
+
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+
+        Ok(self.position)
+    }
+}
+
+impl MediaSource for HttpRangeSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Cursor, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    #[test]
+    fn parses_stream_title() {
+        assert_eq!(
+            parse_stream_title("StreamTitle='Artist - Title';StreamUrl='';"),
+            Some("Artist - Title".to_string())
+        );
+        assert_eq!(
+            parse_stream_title("StreamTitle='It's Here';"),
+            Some("It's Here".to_string())
+        );
+        // the block is padded with zeros to a multiple of 16 bytes
+        assert_eq!(
+            parse_stream_title("StreamTitle='Title';\0\0\0\0"),
+            Some("Title".to_string())
+        );
+        assert_eq!(
+            parse_stream_title("StreamTitle='Unterminated\0\0"),
+            Some("Unterminated".to_string())
+        );
+        assert_eq!(parse_stream_title("StreamTitle='';"), None);
+        assert_eq!(parse_stream_title("StreamUrl='http://example.com';"), None);
+    }
+
+    /// Builds an ICY metadata block: a length byte (in units of 16 bytes) followed by the padded
+    /// text.
+    fn metadata_block(text: &str) -> Vec<u8> {
+        let mut block = text.as_bytes().to_vec();
+        block.resize(text.len().div_ceil(16) * 16, 0);
+        block.insert(0, (block.len() / 16) as u8);
+        block
+    }
+
+    #[test]
+    fn icy_reader_strips_metadata() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&[1; 10]);
+        data.extend_from_slice(&metadata_block("StreamTitle='First';"));
+        data.extend_from_slice(&[2; 10]);
+        // an empty block means the title hasn't changed
+        data.push(0);
+        data.extend_from_slice(&[3; 10]);
+        data.extend_from_slice(&metadata_block("StreamTitle='Second';"));
+        data.extend_from_slice(&[4; 4]);
+
+        let icy = IcyHandle::default();
+        let mut reader = IcyReader {
+            inner: Box::new(Cursor::new(data)),
+            metaint: Some(10),
+            until_metadata: 10,
+            icy: icy.clone(),
+        };
+
+        // reads that aren't aligned with the metadata interval are split at the blocks
+        let mut audio = Vec::new();
+        let mut buf = [0u8; 7];
+        let mut titles = Vec::new();
+
+        loop {
+            let read = reader.read(&mut buf).unwrap();
+
+            if read == 0 {
+                break;
+            }
+
+            assert!(read <= 7);
+            audio.extend_from_slice(&buf[..read]);
+
+            if icy.take_updated() {
+                titles.push(icy.metadata().title);
+            }
+        }
+
+        let expected = [vec![1; 10], vec![2; 10], vec![3; 10], vec![4; 4]].concat();
+
+        assert_eq!(audio, expected);
+        assert_eq!(
+            titles,
+            vec![Some("First".to_string()), Some("Second".to_string())]
+        );
+    }
+
+    #[test]
+    fn icy_reader_passes_through_without_metaint() {
+        let mut reader = IcyReader {
+            inner: Box::new(Cursor::new(vec![5u8; 100])),
+            metaint: None,
+            until_metadata: 0,
+            icy: IcyHandle::default(),
+        };
+
+        let mut audio = Vec::new();
+        reader.read_to_end(&mut audio).unwrap();
+
+        assert_eq!(audio, vec![5u8; 100]);
+    }
+
+    /// Reads an HTTP request up to the end of its headers.
+    fn read_request(stream: &TcpStream) {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+
+        while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+            line.clear();
+        }
+    }
+
+    #[test]
+    fn http_stream_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+
+        // the server drops the connection after every response, like a flaky live stream
+        let server = thread::spawn(move || {
+            for body in [[1u8; 1000], [2u8; 1000]] {
+                let (mut stream, _) = listener.accept().unwrap();
+                read_request(&stream);
+
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\n\
+                        Content-Type: audio/mpeg\r\n\
+                        icy-name: Test Radio\r\n\
+                        Connection: close\r\n\r\n",
+                    )
+                    .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        let mut stream = HttpStream::open(&url).unwrap();
+        assert_eq!(stream.extension().as_deref(), Some("mp3"));
+        assert_eq!(
+            stream.icy().metadata().station.as_deref(),
+            Some("Test Radio")
+        );
+
+        let mut audio = vec![0u8; 2000];
+        stream.read_exact(&mut audio).unwrap();
+
+        assert_eq!(audio, [[1u8; 1000], [2u8; 1000]].concat());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn http_stream_ends_with_content_length() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.mp3", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&stream);
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 500\r\n\r\n")
+                .unwrap();
+            stream.write_all(&[7u8; 500]).unwrap();
+        });
+
+        // files with a known length aren't reconnected once they've been read
+        let mut audio = Vec::new();
+        HttpStream::open(&url)
+            .unwrap()
+            .read_to_end(&mut audio)
+            .unwrap();
+
+        assert_eq!(audio, vec![7u8; 500]);
+        server.join().unwrap();
+    }
+}
@@ -1,12 +1,11 @@
-use std::fs::File;
-
 use super::{
     errors::{
         CloseError, FrameDurationError, MetadataError, OpenError, PlaybackReadError,
         PlaybackStartError, PlaybackStopError, SeekError, TrackDurationError,
     },
-    metadata::Metadata,
+    metadata::{Chapter, EmbeddedImage, Metadata, StreamInfo},
     playback::PlaybackFrame,
+    source::MediaSource,
 };
 
 /// The MediaPlugin trait defines a set of constants that are used to eneumerate the capabilities
@@ -52,10 +51,12 @@ pub trait MediaPlugin: MediaProvider {
 /// read metadata many times in rapid succession during library indexing. This is normal and
 /// expected behavior, and your plugin must be able to handle this.
 pub trait MediaProvider {
-    /// Requests the Provider open the specified file. The file is provided as a File object, and
-    /// theextension is provided as an Option<String>. If the extension is not provided, the
-    /// Provider attempts to determine the extension based off of the file's contents.
-    fn open(&mut self, file: File, ext: Option<String>) -> Result<(), OpenError>;
+    /// Requests the Provider open the specified media. The media is provided as a MediaSource
+    /// (e.g. a local file, an in-memory buffer, a network stream, or an archive entry - see
+    /// `media::source`), and the extension is provided as an Option<String>. If
+    /// the extension is not provided, the Provider attempts to determine the extension based off
+    /// of the file's contents. Sources that are not seekable can only be read from start to end.
+    fn open(&mut self, source: Box<dyn MediaSource>, ext: Option<String>) -> Result<(), OpenError>;
 
     /// Informs the Provider that the currently opened file is no longer needed. This function is
     /// not guaranteed to be called before open if a file is already opened.
@@ -68,11 +69,15 @@ pub trait MediaProvider {
     fn stop_playback(&mut self) -> Result<(), PlaybackStopError>;
 
     /// Requests the Provider seek to the specified time in the current file. The time is provided
-    /// in seconds. If no file is opened, this function should return an error.
-    fn seek(&mut self, time: f64) -> Result<(), SeekError>;
+    /// in seconds. Returns the time that was actually reached, which may differ slightly from the
+    /// requested time if the Provider can only seek to certain positions (e.g. packet or block
+    /// boundaries). If no file is opened, this function should return an error.
+    fn seek(&mut self, time: f64) -> Result<f64, SeekError>;
 
     /// Requests the Provider provide samples for playback. If no file is opened, or the Provider
-    /// is a metadata-only provider, this function should return an error.
+    /// is a metadata-only provider, this function should return an error. If the metadata changes
+    /// while reading (e.g. in chained or live streams), the Provider should report it through
+    /// metadata_updated.
     fn read_samples(&mut self) -> Result<PlaybackFrame, PlaybackReadError>;
 
     /// Returns the normal duration of the PlaybackFrames returned by this provider for the current
@@ -88,11 +93,29 @@ pub trait MediaProvider {
     /// read_metadata.
     fn metadata_updated(&self) -> bool;
 
-    /// Retrieves the current image from the track's metadata, if there is any. If no file is
-    /// opened, or the provider does not support image retrieval, this function should return an
-    /// error.
+    /// Retrieves the current image from the track's metadata, if there is any. If the metadata
+    /// contains more than one image, the front cover should be preferred. If no file is opened, or
+    /// the provider does not support image retrieval, this function should return an error.
     fn read_image(&mut self) -> Result<Option<Box<[u8]>>, MetadataError>;
 
+    /// Retrieves every image from the track's metadata, along with what each image depicts. Unlike
+    /// read_image, this function does not consume the images, and may be called multiple times.
+    /// If no file is opened, or the provider does not support image retrieval, this function
+    /// should return an error.
+    fn read_images(&mut self) -> Result<Vec<EmbeddedImage>, MetadataError>;
+
+    /// Returns the chapter markers of the currently opened file, sorted by their start time. If
+    /// the file has no chapters, an empty list should be returned. If no file is opened, or the
+    /// provider does not support metadata retrieval, this function should return an error.
+    fn chapters(&mut self) -> Result<Vec<Chapter>, MetadataError>;
+
+    /// Returns technical information about the currently opened file (codec, sample rate, bit
+    /// depth, channels, bitrate and duration). This must not require playback to be started, and
+    /// should be read from the container's headers where possible, since it's called for every
+    /// file during library indexing. If no file is opened, or the file has no playable track,
+    /// this function should return an error.
+    fn probe_info(&mut self) -> Result<StreamInfo, PlaybackStartError>;
+
     /// Returns the duration of the currently opened file in seconds. If no file is opened, or
     /// playback has not started, this function should return an error. This function should be
     /// available immediately after playback has started, and should not require reading any
@@ -1,3 +1,4 @@
 pub mod events;
 pub mod interface;
+pub mod settings;
 pub mod thread;
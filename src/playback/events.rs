@@ -1,4 +1,6 @@
-use crate::media::metadata::Metadata;
+use chrono::{DateTime, Utc};
+
+use crate::media::metadata::{Chapter, Metadata};
 
 use super::thread::PlaybackState;
 
@@ -15,11 +17,12 @@ pub enum PlaybackCommand {
     /// Requests that the playback thread open the specified file for immediate playback.
     Open(String),
     /// Requests that the playback thread queue the specified file for playback after the current
-    /// file. If there is no current file, the specified file will be played immediately.
-    Queue(String),
+    /// file. If there is no current file, the specified file will be played immediately. The
+    /// source is recorded in the play history when the file is played.
+    Queue(String, Option<PlaySource>),
     /// Requests that the playback thread queue a list of files for playback after the current
     /// file. If there is no current file, the first file in the list will be played immediately.
-    QueueList(Vec<String>),
+    QueueList(Vec<String>, Option<PlaySource>),
     /// Requests that the playback thread skip to the next file in the queue.
     Next,
     /// Requests that the playback thread skip to the previous file in the queue.
@@ -35,12 +38,53 @@ pub enum PlaybackCommand {
     SetVolume(f64),
     /// Requests that the playback thread replace the current queue with the specified queue.
     /// This will set the current playing track to the first item in the queue.
-    ReplaceQueue(Vec<String>),
+    ReplaceQueue(Vec<String>, Option<PlaySource>),
     /// Requests that the playback thread stop playback.
     Stop,
     /// Requests that the playback thread shuffle (or stop shuffling) the next tracks in the
     /// queue. Note that this currently results in duplication of the *entire* queue.
     ToggleShuffle,
+    /// Requests that the playback thread skip to the next chapter in the current file.
+    NextChapter,
+    /// Requests that the playback thread skip to the previous chapter in the current file.
+    /// If the current chapter is more than 5 seconds in, it will be restarted.
+    PreviousChapter,
+    /// Jumps to the specified chapter in the current file.
+    JumpChapter(usize),
+}
+
+/// What the user started playback from, e.g. the album a track was played from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PlaySource {
+    Album(i64),
+    Playlist(i64),
+    SmartPlaylist(i64),
+}
+
+impl PlaySource {
+    /// Returns the kind of source and its ID, as stored in the `play_event` table.
+    pub fn to_db(self) -> (&'static str, i64) {
+        match self {
+            PlaySource::Album(id) => ("album", id),
+            PlaySource::Playlist(id) => ("playlist", id),
+            PlaySource::SmartPlaylist(id) => ("smart_playlist", id),
+        }
+    }
+}
+
+/// A track that was played, sent once the track has ended.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PlayRecord {
+    /// The location of the track, as it appears in the queue.
+    pub location: String,
+    pub started_at: DateTime<Utc>,
+    /// How long the track was listened to, in seconds. Parts of the track that were skipped over
+    /// by seeking aren't counted.
+    pub listened: u64,
+    /// Whether the track was ended early, before half of it (or four minutes) had been listened
+    /// to. Tracks without a known length are never skipped.
+    pub skipped: bool,
+    pub source: Option<PlaySource>,
 }
 
 /// An event from the playback thread. This is used to communicate information from the playback
@@ -72,4 +116,13 @@ pub enum PlaybackEvent {
     ShuffleToggled(bool),
     /// Indicates that the volume has changed. The f64 is the new volume, from 0.0 to 1.0.
     VolumeChanged(f64),
+    /// Indicates that the chapters of the current file have been read. The vector is empty if the
+    /// file has no chapters.
+    ChaptersUpdated(Vec<Chapter>),
+    /// Indicates that the current chapter has changed. The usize is the index of the new chapter,
+    /// or None if the current position isn't inside of a chapter.
+    ChapterChanged(Option<usize>),
+    /// Indicates that a track has stopped playing, either because it ended or because something
+    /// else was played. The record is boxed to avoid enum size bloat.
+    TrackPlayed(Box<PlayRecord>),
 }
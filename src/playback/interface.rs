@@ -4,14 +4,19 @@ use std::{
 };
 
 use gpui::AppContext;
+use tracing::error;
 
 use crate::{
     data::interface::GPUIDataInterface,
-    ui::models::{ImageEvent, Models, PlaybackInfo},
+    library::db::{record_play, LibraryAccess},
+    ui::{
+        app::Pool,
+        models::{ImageEvent, Models, PlaybackInfo},
+    },
 };
 
 use super::{
-    events::{PlaybackCommand, PlaybackEvent},
+    events::{PlaySource, PlaybackCommand, PlaybackEvent},
     thread::PlaybackState,
 };
 
@@ -65,15 +70,15 @@ impl GPUIPlaybackInterface {
             .expect("could not send tx");
     }
 
-    pub fn queue(&self, path: &str) {
+    pub fn queue(&self, path: &str, source: Option<PlaySource>) {
         self.commands_tx
-            .send(PlaybackCommand::Queue(path.to_string()))
+            .send(PlaybackCommand::Queue(path.to_string(), source))
             .expect("could not send tx");
     }
 
-    pub fn queue_list(&self, paths: Vec<String>) {
+    pub fn queue_list(&self, paths: Vec<String>, source: Option<PlaySource>) {
         self.commands_tx
-            .send(PlaybackCommand::QueueList(paths.clone()))
+            .send(PlaybackCommand::QueueList(paths.clone(), source))
             .expect("could not send tx");
     }
 
@@ -113,9 +118,9 @@ impl GPUIPlaybackInterface {
             .expect("could not send tx");
     }
 
-    pub fn replace_queue(&self, paths: Vec<String>) {
+    pub fn replace_queue(&self, paths: Vec<String>, source: Option<PlaySource>) {
         self.commands_tx
-            .send(PlaybackCommand::ReplaceQueue(paths.clone()))
+            .send(PlaybackCommand::ReplaceQueue(paths.clone(), source))
             .expect("could not send tx");
     }
 
@@ -131,6 +136,24 @@ impl GPUIPlaybackInterface {
             .expect("could not send tx");
     }
 
+    pub fn next_chapter(&self) {
+        self.commands_tx
+            .send(PlaybackCommand::NextChapter)
+            .expect("could not send tx");
+    }
+
+    pub fn previous_chapter(&self) {
+        self.commands_tx
+            .send(PlaybackCommand::PreviousChapter)
+            .expect("could not send tx");
+    }
+
+    pub fn jump_chapter(&self, index: usize) {
+        self.commands_tx
+            .send(PlaybackCommand::JumpChapter(index))
+            .expect("could not send tx");
+    }
+
     /// Starts the broadcast loop that will read events from the playback thread and update data
     /// models accordingly. This function should be called once, and will panic if called more than
     /// once.
@@ -143,6 +166,7 @@ impl GPUIPlaybackInterface {
         let queue_model = cx.global::<Models>().queue.clone();
 
         let playback_info = cx.global::<PlaybackInfo>().clone();
+        let pool = cx.global::<Pool>().0.clone();
 
         if let Some(events_rx) = events_rx {
             cx.spawn(|mut cx| async move {
@@ -239,6 +263,46 @@ impl GPUIPlaybackInterface {
                                     cx.notify()
                                 })
                                 .expect("failed to update volume model"),
+                            PlaybackEvent::ChaptersUpdated(v) => {
+                                playback_info
+                                    .chapters
+                                    .update(&mut cx, |m, cx| {
+                                        *m = v;
+                                        cx.notify()
+                                    })
+                                    .expect("failed to update chapters");
+
+                                playback_info
+                                    .current_chapter
+                                    .update(&mut cx, |m, cx| {
+                                        *m = None;
+                                        cx.notify()
+                                    })
+                                    .expect("failed to update current chapter");
+                            }
+                            PlaybackEvent::ChapterChanged(v) => {
+                                playback_info
+                                    .current_chapter
+                                    .update(&mut cx, |m, cx| {
+                                        *m = v;
+                                        cx.notify()
+                                    })
+                                    .expect("failed to update current chapter");
+                            }
+                            PlaybackEvent::TrackPlayed(v) => {
+                                let pool = pool.clone();
+
+                                cx.background_executor()
+                                    .spawn(async move {
+                                        if let Err(e) = record_play(&pool, &v).await {
+                                            error!(
+                                                "Could not record play of {}: {}",
+                                                v.location, e
+                                            );
+                                        }
+                                    })
+                                    .detach();
+                            }
                             _ => (),
                         }
                     }
@@ -257,10 +321,45 @@ impl GPUIPlaybackInterface {
 
 // TODO: this should be in a trait for AppContext
 pub fn replace_queue(paths: Vec<String>, cx: &mut AppContext) {
+    replace_queue_from(paths, None, cx);
+}
+
+/// Replaces the queue, recording where the tracks were started from in the play history.
+pub fn replace_queue_from(paths: Vec<String>, source: Option<PlaySource>, cx: &mut AppContext) {
     let playback_interface = cx.global::<GPUIPlaybackInterface>();
-    playback_interface.replace_queue(paths.clone());
+    playback_interface.replace_queue(paths.clone(), source);
 
     let data_interface = cx.global::<GPUIDataInterface>();
 
     data_interface.evict_cache();
 }
+
+/// Replaces the queue with the items in a playlist.
+pub fn queue_playlist(playlist_id: i64, cx: &mut AppContext) -> Result<(), sqlx::Error> {
+    let items = cx.list_playlist_items(playlist_id)?;
+    let paths = items.iter().map(|item| item.location.clone()).collect();
+
+    replace_queue_from(paths, Some(PlaySource::Playlist(playlist_id)), cx);
+
+    Ok(())
+}
+
+/// Replaces the queue with the tracks currently matching a smart playlist's rules.
+pub fn queue_smart_playlist(playlist_id: i64, cx: &mut AppContext) -> Result<(), sqlx::Error> {
+    let tracks = cx.list_tracks_in_smart_playlist(playlist_id)?;
+    let paths = tracks.iter().map(|track| track.location.clone()).collect();
+
+    replace_queue_from(paths, Some(PlaySource::SmartPlaylist(playlist_id)), cx);
+
+    Ok(())
+}
+
+/// Saves the current queue as a new playlist, returning the playlist's ID.
+pub fn save_queue_as_playlist(name: &str, cx: &mut AppContext) -> Result<i64, sqlx::Error> {
+    let queue = cx.global::<Models>().queue.read(cx).0.clone();
+
+    let playlist_id = cx.create_playlist(name)?;
+    cx.append_to_playlist(playlist_id, &queue)?;
+
+    Ok(playlist_id)
+}
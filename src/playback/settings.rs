@@ -0,0 +1,28 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use serde::Deserialize;
+use tracing::warn;
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PlaybackSettings {
+    /// Whether DSD should be sent to the device as DSD over PCM (DoP) when the device's format
+    /// allows it. DoP only works if the DAC recognizes it (otherwise it plays loud noise), so
+    /// DSD is converted to PCM unless this is enabled.
+    pub dsd_over_pcm: bool,
+}
+
+pub fn load_playback_settings(path: &Path) -> PlaybackSettings {
+    if let Ok(file) = File::open(path) {
+        let reader = BufReader::new(file);
+
+        if let Ok(settings) = serde_json::from_reader(reader) {
+            settings
+        } else {
+            warn!("Playback settings file exists but it could not be loaded, using default");
+            PlaybackSettings::default()
+        }
+    } else {
+        PlaybackSettings::default()
+    }
+}
@@ -1,10 +1,15 @@
 use std::{
-    sync::mpsc::{Receiver, Sender},
+    path::Path,
+    sync::mpsc::{Receiver, Sender, TryRecvError},
     thread::sleep,
 };
 
+use ahash::AHashMap;
+use async_std::task;
+use chrono::{DateTime, Utc};
 use rand::{seq::SliceRandom, thread_rng};
-use tracing::{debug, info};
+use sqlx::SqlitePool;
+use tracing::{debug, error, info, warn};
 
 #[cfg(target_os = "linux")]
 use crate::devices::builtin::pulse::PulseProvider;
@@ -14,18 +19,29 @@ use crate::devices::builtin::cpal::CpalProvider;
 
 use crate::{
     devices::{
-        format::{ChannelSpec, FormatInfo},
+        dsd::dop_supported,
+        format::{ChannelSpec, FormatInfo, SampleFormat},
         resample::Resampler,
         traits::{Device, DeviceProvider, OutputStream},
     },
+    library::db::{delete_resume_position, find_audiobook, update_resume_position},
     media::{
-        builtin::symphonia::SymphoniaProvider, errors::PlaybackReadError, traits::MediaProvider,
+        builtin::{create_provider, provider_name, symphonia::SymphoniaProvider},
+        cue::{find_cue_sheet, split_location, CueSheet, TrackRange},
+        errors::{OpenError, PlaybackReadError},
+        metadata::{Chapter, Metadata},
+        playback::PlaybackFrame,
+        playlist::is_playlist_location,
+        source::{open_location, OpenedSource},
+        stream::{is_stream_location, IcyHandle},
+        traits::{MediaPlugin, MediaProvider},
     },
 };
 
 use super::{
-    events::{PlaybackCommand, PlaybackEvent},
+    events::{PlayRecord, PlaySource, PlaybackCommand, PlaybackEvent},
     interface::PlaybackInterface,
+    settings::{load_playback_settings, PlaybackSettings},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,10 +51,32 @@ pub enum PlaybackState {
     Paused,
 }
 
+/// Tracks listened to for at least this long count as played, even if they're ended early.
+const PLAYED_SECS: u64 = 240;
+
+/// The track that is currently being played, which is added to the play history once it ends.
+struct CurrentPlay {
+    location: String,
+    started_at: DateTime<Utc>,
+    duration: u64,
+    /// The length of the audio that has been played so far, in seconds.
+    listened: f64,
+    source: Option<PlaySource>,
+}
+
+/// A network location being opened on another thread, so that slow or unreachable servers don't
+/// hold up the playback thread. The location is played once it has been opened.
+struct PendingOpen {
+    location: String,
+    result_rx: Receiver<Result<OpenedSource, OpenError>>,
+}
+
 pub struct PlaybackThread {
     commands_rx: Receiver<PlaybackCommand>,
     events_tx: Sender<PlaybackEvent>,
     media_provider: Option<Box<dyn MediaProvider>>,
+    /// The name of the current media provider, which is picked based on the file being played.
+    media_provider_name: &'static str,
     device_provider: Option<Box<dyn DeviceProvider>>,
     device: Option<Box<dyn Device>>,
     stream: Option<Box<dyn OutputStream>>,
@@ -51,11 +89,37 @@ pub struct PlaybackThread {
     queue_next: usize,
     last_timestamp: u64,
     pending_reset: bool,
+    /// The path of the file that is currently open.
+    current_file: Option<String>,
+    /// The portion of the current file being played, if the current track is a virtual track.
+    current_range: Option<TrackRange>,
+    /// The position in the current file, in seconds. This is only tracked for virtual tracks.
+    range_position: f64,
+    current_cue: Option<CueSheet>,
+    /// The chapters of the current file, if it has any.
+    chapters: Vec<Chapter>,
+    current_chapter: Option<usize>,
+    /// The library ID of the current file, if it's an audiobook. The playback position of
+    /// audiobooks is saved, so that they can be resumed where they were left off.
+    current_audiobook: Option<i64>,
+    pool: SqlitePool,
+    /// The ICY metadata of the current stream, if the current track is a network stream.
+    current_icy: Option<IcyHandle>,
+    settings: PlaybackSettings,
+    /// The volume requested by the user. This isn't applied while DoP is being played.
+    volume: f64,
+    /// Whether or not the current track is being sent to the device as DoP.
+    dop_active: bool,
+    /// Where each queued location was started from. A location queued more than once keeps the
+    /// source it was last queued with.
+    sources: AHashMap<String, PlaySource>,
+    current_play: Option<CurrentPlay>,
+    pending_open: Option<PendingOpen>,
 }
 
 impl PlaybackThread {
     /// Starts the playback thread and returns the created interface.
-    pub fn start<T: PlaybackInterface>() -> T {
+    pub fn start<T: PlaybackInterface>(pool: SqlitePool) -> T {
         let (commands_tx, commands_rx) = std::sync::mpsc::channel();
         let (events_tx, events_rx) = std::sync::mpsc::channel();
 
@@ -66,6 +130,7 @@ impl PlaybackThread {
                     commands_rx,
                     events_tx,
                     media_provider: None,
+                    media_provider_name: SymphoniaProvider::NAME,
                     device_provider: None,
                     device: None,
                     stream: None,
@@ -78,6 +143,21 @@ impl PlaybackThread {
                     queue_next: 0,
                     last_timestamp: u64::MAX,
                     pending_reset: false,
+                    current_file: None,
+                    current_range: None,
+                    range_position: 0.0,
+                    current_cue: None,
+                    chapters: Vec::new(),
+                    current_chapter: None,
+                    current_audiobook: None,
+                    pool,
+                    current_icy: None,
+                    settings: PlaybackSettings::default(),
+                    volume: 1.0,
+                    dop_active: false,
+                    sources: AHashMap::new(),
+                    current_play: None,
+                    pending_open: None,
                 };
 
                 thread.run();
@@ -122,13 +202,84 @@ impl PlaybackThread {
             format.sample_rate
         );
 
+        self.read_settings();
+
         loop {
             self.main_loop();
         }
     }
 
+    fn read_settings(&mut self) {
+        let dirs = directories::ProjectDirs::from("me", "william341", "muzak")
+            .expect("couldn't find project dirs");
+
+        self.settings = load_playback_settings(&dirs.data_dir().join("playback_settings.json"));
+    }
+
+    /// Saves the position in the current file, if the current file is an audiobook. Only whole
+    /// files are resumed, virtual tracks always start from the beginning.
+    fn save_resume_position(&mut self) {
+        let Some(track_id) = self.current_audiobook else {
+            return;
+        };
+
+        let position = self
+            .media_provider
+            .as_ref()
+            .and_then(|provider| provider.position_secs().ok());
+
+        if let Some(position) = position {
+            if let Err(e) = task::block_on(update_resume_position(&self.pool, track_id, position)) {
+                error!("Could not save resume position: {:?}", e);
+            }
+        }
+    }
+
+    /// Forgets the saved position of the current file. This is used when an audiobook has been
+    /// played to the end, so that it starts from the beginning next time.
+    fn forget_resume_position(&mut self) {
+        let Some(track_id) = self.current_audiobook.take() else {
+            return;
+        };
+
+        if let Err(e) = task::block_on(delete_resume_position(&self.pool, track_id)) {
+            error!("Could not forget resume position: {:?}", e);
+        }
+    }
+
+    /// Looks up whether the current file is an audiobook in the library, and if it is, seeks to
+    /// where it was left off.
+    fn restore_resume_position(&mut self) {
+        self.current_audiobook = None;
+
+        let Some(file) = self
+            .current_file
+            .as_ref()
+            .filter(|_| self.current_range.is_none())
+        else {
+            return;
+        };
+
+        let (track_id, position) = match task::block_on(find_audiobook(&self.pool, file)) {
+            Ok(Some(audiobook)) => audiobook,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Could not look up resume position: {:?}", e);
+                return;
+            }
+        };
+
+        self.current_audiobook = Some(track_id);
+
+        if let Some(position) = position {
+            info!("Resuming audiobook at {}s", position);
+            self.seek(position as f64);
+        }
+    }
+
     pub fn main_loop(&mut self) {
         self.command_intake();
+        self.poll_pending_open();
 
         if self.state == PlaybackState::Playing {
             self.play_audio();
@@ -140,39 +291,107 @@ impl PlaybackThread {
     }
 
     pub fn broadcast_events(&mut self) {
+        let icy_updated = self
+            .current_icy
+            .as_ref()
+            .is_some_and(|icy| icy.take_updated());
+
         if let Some(provider) = &mut self.media_provider {
-            if provider.metadata_updated() {
+            let provider_updated = provider.metadata_updated();
+
+            if provider_updated || icy_updated {
                 // TODO: proper error handling
-                let metadata = provider.read_metadata().expect("failed to get metadata");
-                self.events_tx
-                    .send(PlaybackEvent::MetadataUpdate(Box::new(metadata.clone())))
-                    .expect("unable to send event");
+                let mut metadata = provider
+                    .read_metadata()
+                    .expect("failed to get metadata")
+                    .clone();
+
+                // the album art is consumed when it's read, so it's only sent when it changes
+                let image = if provider_updated {
+                    Some(provider.read_image().expect("failed to decode image"))
+                } else {
+                    None
+                };
+
+                // streams usually don't contain any metadata of their own
+                if let Some(icy) = &self.current_icy {
+                    icy.metadata().apply_to(&mut metadata);
+                }
+
+                self.apply_cue_metadata(&mut metadata);
 
-                let image = provider.read_image().expect("failed to decode image");
                 self.events_tx
-                    .send(PlaybackEvent::AlbumArtUpdate(image))
+                    .send(PlaybackEvent::MetadataUpdate(Box::new(metadata)))
                     .expect("unable to send event");
+
+                if let Some(image) = image {
+                    self.events_tx
+                        .send(PlaybackEvent::AlbumArtUpdate(image))
+                        .expect("unable to send event");
+                }
+            }
+        }
+    }
+
+    /// Replaces the file's metadata with the track's metadata from the CUE sheet, if the current
+    /// track is a virtual track.
+    fn apply_cue_metadata(&mut self, metadata: &mut Metadata) {
+        let (Some(file), Some(range)) = (&self.current_file, self.current_range) else {
+            return;
+        };
+
+        let path = Path::new(file);
+
+        if self.current_cue.is_none() {
+            self.current_cue = find_cue_sheet(path, metadata);
+        }
+
+        if let Some(sheet) = &self.current_cue {
+            if let Some(track) = sheet.track_at(path, range.start) {
+                let count = sheet.tracks_for_file(path).map(|v| v.len());
+                sheet.apply_to_metadata(track, count.unwrap_or_default(), metadata);
             }
         }
     }
 
+    /// Returns the duration of the current track in seconds. For virtual tracks, this is the
+    /// length of the portion of the file that the track occupies.
+    fn current_duration(&self) -> u64 {
+        let file_duration = self
+            .media_provider
+            .as_ref()
+            .and_then(|provider| provider.duration_secs().ok());
+
+        match self.current_range {
+            Some(range) => range
+                .end
+                .or(file_duration.map(|v| v as f64))
+                .map(|end| (end - range.start).max(0.0) as u64)
+                .unwrap_or_default(),
+            None => file_duration.unwrap_or_default(),
+        }
+    }
+
     pub fn command_intake(&mut self) {
         while let Ok(command) = self.commands_rx.try_recv() {
             match command {
                 PlaybackCommand::Play => self.play(),
                 PlaybackCommand::Pause => self.pause(),
                 PlaybackCommand::Open(v) => self.open(&v),
-                PlaybackCommand::Queue(v) => self.queue(&v),
-                PlaybackCommand::QueueList(v) => self.queue_list(v),
+                PlaybackCommand::Queue(v, source) => self.queue(&v, source),
+                PlaybackCommand::QueueList(v, source) => self.queue_list(v, source),
                 PlaybackCommand::Next => self.next(true),
                 PlaybackCommand::Previous => self.previous(),
                 PlaybackCommand::ClearQueue => self.clear_queue(),
                 PlaybackCommand::Jump(v) => self.jump(v),
                 PlaybackCommand::Seek(v) => self.seek(v),
                 PlaybackCommand::SetVolume(v) => self.set_volume(v),
-                PlaybackCommand::ReplaceQueue(v) => self.replace_queue(v),
+                PlaybackCommand::ReplaceQueue(v, source) => self.replace_queue(v, source),
                 PlaybackCommand::Stop => self.stop(),
                 PlaybackCommand::ToggleShuffle => self.toggle_shuffle(),
+                PlaybackCommand::NextChapter => self.next_chapter(),
+                PlaybackCommand::PreviousChapter => self.previous_chapter(),
+                PlaybackCommand::JumpChapter(v) => self.jump_chapter(v),
             }
         }
     }
@@ -188,6 +407,7 @@ impl PlaybackThread {
             }
 
             self.state = PlaybackState::Paused;
+            self.save_resume_position();
 
             self.events_tx
                 .send(PlaybackEvent::StateChanged(PlaybackState::Paused))
@@ -228,6 +448,66 @@ impl PlaybackThread {
     fn open(&mut self, path: &String) {
         info!("Opening: {}", path);
 
+        // anything opened before this is no longer wanted
+        self.pending_open = None;
+
+        let (file_path, _) = split_location(path);
+
+        // streams and station files can take several seconds to connect to
+        if is_stream_location(file_path) || is_playlist_location(file_path) {
+            let (result_tx, result_rx) = std::sync::mpsc::channel();
+            let location = file_path.to_string();
+
+            let spawned = std::thread::Builder::new()
+                .name("open".to_string())
+                .spawn(move || {
+                    // the receiver is gone if something else was opened in the meantime
+                    result_tx.send(open_location(&location)).ok();
+                });
+
+            match spawned {
+                Ok(_) => {
+                    self.pending_open = Some(PendingOpen {
+                        location: path.clone(),
+                        result_rx,
+                    });
+                }
+                Err(e) => error!("Unable to open {}: {:?}", path, e),
+            }
+
+            return;
+        }
+
+        match open_location(file_path) {
+            Ok(opened) => self.play_opened(path, opened),
+            Err(e) => error!("Unable to open {}: {:?}", path, e),
+        }
+    }
+
+    /// Plays the location opened on another thread, once it's ready.
+    fn poll_pending_open(&mut self) {
+        let Some(pending) = &self.pending_open else {
+            return;
+        };
+
+        let result = match pending.result_rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(OpenError::Unknown),
+        };
+
+        let path = self.pending_open.take().unwrap().location;
+
+        match result {
+            Ok(opened) => self.play_opened(&path, opened),
+            Err(e) => error!("Unable to open {}: {:?}", path, e),
+        }
+    }
+
+    fn play_opened(&mut self, path: &String, opened: OpenedSource) {
+        // virtual tracks (e.g. from a CUE sheet) are played by seeking to the start of the track
+        let (file_path, range) = split_location(path);
+
         if self.state == PlaybackState::Paused {
             self.stream
                 .as_mut()
@@ -242,28 +522,71 @@ impl PlaybackThread {
             .play()
             .expect("unable to play stream");
 
-        // TODO: handle multiple media providers
+        self.save_resume_position();
+        self.finish_play(false);
+
+        let name = provider_name(opened.ext.as_deref());
+
+        if name != self.media_provider_name {
+            if let Some(provider) = &mut self.media_provider {
+                provider.close().ok();
+            }
+
+            self.media_provider = Some(create_provider(name));
+            self.media_provider_name = name;
+        }
+
         if let Some(provider) = &mut self.media_provider {
             // TODO: proper error handling
             self.resampler = None;
-            let src = std::fs::File::open(path).expect("failed to open media");
-            provider.open(src, None).expect("unable to open file");
+
+            if let Err(e) = provider.open(opened.source, opened.ext) {
+                error!("Unable to open {}: {:?}", path, e);
+                return;
+            }
+
             provider.start_playback().expect("unable to start playback");
 
+            let position = match range.map(|v| provider.seek(v.start)).transpose() {
+                Ok(position) => position.unwrap_or_default(),
+                Err(e) => {
+                    // playing the file from the beginning would play the wrong track
+                    error!("Unable to seek to the start of {}: {:?}", path, e);
+                    self.stop();
+                    self.next(false);
+                    return;
+                }
+            };
+
+            self.current_file = Some(file_path.to_string());
+            self.current_range = range;
+            self.range_position = position;
+            self.current_cue = None;
+            self.current_audiobook = None;
+            self.current_icy = opened.icy;
+
+            // chapter times are relative to the whole file, so virtual tracks don't use them
+            self.chapters = match range {
+                Some(_) => Vec::new(),
+                None => provider.chapters().unwrap_or_default(),
+            };
+            self.current_chapter = None;
+
             self.state = PlaybackState::Playing;
             self.events_tx
                 .send(PlaybackEvent::SongChanged(path.clone()))
                 .expect("unable to send event");
 
-            if let Ok(duration) = provider.duration_secs() {
-                self.events_tx
-                    .send(PlaybackEvent::DurationChanged(duration))
-                    .expect("unable to send event");
-            } else {
-                self.events_tx
-                    .send(PlaybackEvent::DurationChanged(0))
-                    .expect("unable to send event");
-            }
+            self.events_tx
+                .send(PlaybackEvent::DurationChanged(self.current_duration()))
+                .expect("unable to send event");
+            self.start_play(path);
+
+            self.events_tx
+                .send(PlaybackEvent::ChaptersUpdated(self.chapters.clone()))
+                .expect("unable to send event");
+
+            self.restore_resume_position();
 
             self.update_ts();
 
@@ -311,8 +634,9 @@ impl PlaybackThread {
         }
     }
 
-    fn queue(&mut self, path: &String) {
+    fn queue(&mut self, path: &String, source: Option<PlaySource>) {
         info!("Adding file to queue: {}", path);
+        self.set_source(path, source);
         let pre_len = self.queue.len();
         self.queue.push(path.clone());
 
@@ -320,7 +644,8 @@ impl PlaybackThread {
             self.shuffled_queue.push(path.clone());
         }
 
-        if self.state == PlaybackState::Stopped {
+        // a stream that is still connecting counts as playing
+        if self.state == PlaybackState::Stopped && self.pending_open.is_none() {
             self.open(path);
             self.queue_next = pre_len + 1;
             self.events_tx
@@ -339,8 +664,11 @@ impl PlaybackThread {
         }
     }
 
-    fn queue_list(&mut self, mut paths: Vec<String>) {
+    fn queue_list(&mut self, mut paths: Vec<String>, source: Option<PlaySource>) {
         info!("Adding files to queue: {:?}", paths);
+        for path in &paths {
+            self.set_source(path, source);
+        }
         let pre_len = self.queue.len();
         let first = paths.first().cloned();
 
@@ -353,7 +681,7 @@ impl PlaybackThread {
 
         self.queue.append(&mut paths);
 
-        if self.state == PlaybackState::Stopped {
+        if self.state == PlaybackState::Stopped && self.pending_open.is_none() {
             if let Some(first) = first {
                 self.open(&first);
                 self.queue_next = pre_len + 1;
@@ -372,29 +700,254 @@ impl PlaybackThread {
     }
 
     fn update_ts(&mut self) {
-        if let Some(provider) = &self.media_provider {
-            if let Ok(timestamp) = provider.position_secs() {
-                if timestamp == self.last_timestamp {
-                    return;
-                }
+        let timestamp = match self.current_range {
+            Some(range) => Some((self.range_position - range.start).max(0.0) as u64),
+            None => self
+                .media_provider
+                .as_ref()
+                .and_then(|provider| provider.position_secs().ok()),
+        };
+
+        self.update_chapter();
+
+        if let Some(timestamp) = timestamp {
+            if timestamp == self.last_timestamp {
+                return;
+            }
 
-                self.events_tx
-                    .send(PlaybackEvent::PositionChanged(timestamp))
-                    .expect("unable to send event");
+            self.events_tx
+                .send(PlaybackEvent::PositionChanged(timestamp))
+                .expect("unable to send event");
+
+            self.last_timestamp = timestamp;
 
-                self.last_timestamp = timestamp;
+            // save periodically, in case the application isn't closed cleanly
+            if self.current_audiobook.is_some() && timestamp % 15 == 0 {
+                self.save_resume_position();
             }
         }
     }
 
+    /// Returns the position in the current file in seconds. Unlike the timestamps sent to the user
+    /// interface, this is not relative to the start of virtual tracks.
+    fn file_position(&self) -> f64 {
+        match self.current_range {
+            Some(_) => self.range_position,
+            None => self
+                .media_provider
+                .as_ref()
+                .and_then(|provider| provider.position_secs().ok())
+                .unwrap_or_default() as f64,
+        }
+    }
+
+    /// Returns the index of the last chapter that starts at or before the specified position.
+    fn chapter_at(&self, position: f64) -> Option<usize> {
+        self.chapters
+            .iter()
+            .rposition(|chapter| chapter.start <= position)
+    }
+
+    fn update_chapter(&mut self) {
+        if self.chapters.is_empty() {
+            return;
+        }
+
+        let position = self.file_position();
+        let chapter = self
+            .chapter_at(position)
+            .filter(|v| !self.chapters[*v].end.is_some_and(|end| position >= end));
+
+        if chapter != self.current_chapter {
+            self.current_chapter = chapter;
+
+            self.events_tx
+                .send(PlaybackEvent::ChapterChanged(chapter))
+                .expect("unable to send event");
+        }
+    }
+
+    fn next_chapter(&mut self) {
+        let position = self.file_position();
+
+        // positions are only accurate to the second, so the current chapter might start slightly
+        // after the reported position
+        if let Some(next) = self
+            .chapters
+            .iter()
+            .position(|chapter| chapter.start > position + 1.0)
+        {
+            self.jump_chapter(next);
+        }
+    }
+
+    fn previous_chapter(&mut self) {
+        let position = self.file_position();
+
+        if let Some(current) = self.chapter_at(position) {
+            if position - self.chapters[current].start > 5.0 || current == 0 {
+                self.jump_chapter(current);
+            } else {
+                self.jump_chapter(current - 1);
+            }
+        }
+    }
+
+    fn jump_chapter(&mut self, index: usize) {
+        if let Some(chapter) = self.chapters.get(index) {
+            info!("Jumping to chapter {}: {:?}", index, chapter.title);
+            self.seek(chapter.start);
+        }
+    }
+
     fn seek(&mut self, timestamp: f64) {
         if let Some(provider) = &mut self.media_provider {
-            provider.seek(timestamp).expect("unable to seek");
+            // positions in virtual tracks are relative to the start of the track
+            let offset = self.current_range.map(|v| v.start).unwrap_or_default();
+
+            // streams can't be seeked
+            let Ok(position) = provider.seek(timestamp + offset) else {
+                warn!("Unable to seek to {}", timestamp);
+                return;
+            };
+
+            self.range_position = position;
             self.pending_reset = true;
             self.update_ts();
         }
     }
 
+    fn peek_next(&self) -> Option<String> {
+        if self.shuffle {
+            self.shuffled_queue.get(self.queue_next).cloned()
+        } else {
+            self.queue.get(self.queue_next).cloned()
+        }
+    }
+
+    /// Returns true if the next track in the queue is a virtual track that starts in the current
+    /// file exactly where the current track ends.
+    fn next_is_contiguous(&self) -> bool {
+        let (Some(file), Some(range), Some(next)) =
+            (&self.current_file, self.current_range, self.peek_next())
+        else {
+            return false;
+        };
+
+        let (next_file, next_range) = split_location(&next);
+
+        next_file == file && next_range.is_some_and(|next| range.is_followed_by(&next))
+    }
+
+    /// Advances the position in the current virtual track, and cuts the frame off at the end of
+    /// the track if required. Returns true if the end of the track has been reached.
+    fn clip_to_range(&mut self, frame: &mut PlaybackFrame) -> bool {
+        let Some(range) = self.current_range else {
+            return false;
+        };
+
+        let position = self.range_position;
+        self.range_position += frame.samples.len() as f64 / frame.rate as f64;
+
+        let Some(end) = range.end else {
+            return false;
+        };
+
+        if self.range_position < end {
+            return false;
+        }
+
+        // if the next track picks up where this one ends, the rest of the frame belongs to it, and
+        // cutting it off would introduce a gap
+        if !self.next_is_contiguous() {
+            let remaining = ((end - position).max(0.0) * frame.rate as f64).round() as usize;
+            frame.samples.truncate(remaining);
+        }
+
+        true
+    }
+
+    /// Moves on from a virtual track that has reached its end. If the next track continues in the
+    /// same file, playback carries on without reopening the file.
+    fn finish_range(&mut self) {
+        self.finish_play(true);
+
+        if !self.next_is_contiguous() {
+            info!("End of track reached, moving to next song");
+            self.next(false);
+            return;
+        }
+
+        let next = self.peek_next().expect("contiguous track disappeared");
+        info!("Continuing into next track in file: {}", next);
+
+        self.current_range = split_location(&next).1;
+        self.queue_next += 1;
+
+        self.events_tx
+            .send(PlaybackEvent::SongChanged(next.clone()))
+            .expect("unable to send event");
+        self.events_tx
+            .send(PlaybackEvent::DurationChanged(self.current_duration()))
+            .expect("unable to send event");
+        self.start_play(&next);
+
+        let metadata = self
+            .media_provider
+            .as_mut()
+            .and_then(|provider| provider.read_metadata().ok().cloned());
+
+        if let Some(mut metadata) = metadata {
+            self.apply_cue_metadata(&mut metadata);
+            self.events_tx
+                .send(PlaybackEvent::MetadataUpdate(Box::new(metadata)))
+                .expect("unable to send event");
+        }
+
+        self.update_ts();
+    }
+
+    fn start_play(&mut self, location: &str) {
+        self.current_play = Some(CurrentPlay {
+            location: location.to_string(),
+            started_at: Utc::now(),
+            duration: self.current_duration(),
+            listened: 0.0,
+            source: self.sources.get(location).copied(),
+        });
+    }
+
+    /// Records where a location being queued was started from, replacing the source it had if it
+    /// was queued before.
+    fn set_source(&mut self, location: &str, source: Option<PlaySource>) {
+        match source {
+            Some(source) => self.sources.insert(location.to_string(), source),
+            None => self.sources.remove(location),
+        };
+    }
+
+    /// Sends the current track to the play history. `ended` is true if the track was played to
+    /// the end, rather than being stopped or replaced by another track.
+    fn finish_play(&mut self, ended: bool) {
+        let Some(play) = self.current_play.take() else {
+            return;
+        };
+
+        let listened = play.listened.round() as u64;
+        let skipped =
+            !ended && play.duration > 0 && listened < (play.duration / 2).min(PLAYED_SECS);
+
+        self.events_tx
+            .send(PlaybackEvent::TrackPlayed(Box::new(PlayRecord {
+                location: play.location,
+                started_at: play.started_at,
+                listened,
+                skipped,
+                source: play.source,
+            })))
+            .expect("unable to send event");
+    }
+
     fn jump(&mut self, index: usize) {
         if index < self.queue.len() {
             if self.shuffle {
@@ -406,8 +959,12 @@ impl PlaybackThread {
         }
     }
 
-    fn replace_queue(&mut self, paths: Vec<String>) {
+    fn replace_queue(&mut self, paths: Vec<String>, source: Option<PlaySource>) {
         info!("Replacing queue with: {:?}", paths);
+        self.sources.clear();
+        for path in &paths {
+            self.set_source(path, source);
+        }
 
         if self.shuffle {
             let mut shuffled_paths = paths.clone();
@@ -434,17 +991,37 @@ impl PlaybackThread {
     fn clear_queue(&mut self) {
         self.queue = Vec::new();
         self.queue_next = 0;
+        self.sources.clear();
         self.events_tx
             .send(PlaybackEvent::QueueUpdated(self.queue.clone()))
             .expect("unable to send event");
     }
 
     fn stop(&mut self) {
+        self.pending_open = None;
+        self.save_resume_position();
+        self.finish_play(false);
+
         if let Some(provider) = &mut self.media_provider {
             provider.stop_playback().expect("unable to stop playback");
             provider.close().expect("unable to close media");
         }
+        self.current_file = None;
+        self.current_range = None;
+        self.current_cue = None;
+        self.current_audiobook = None;
+        self.current_icy = None;
         self.state = PlaybackState::Stopped;
+
+        if !self.chapters.is_empty() {
+            self.chapters = Vec::new();
+            self.current_chapter = None;
+
+            self.events_tx
+                .send(PlaybackEvent::ChaptersUpdated(Vec::new()))
+                .expect("unable to send event");
+        }
+
         self.events_tx
             .send(PlaybackEvent::StateChanged(PlaybackState::Stopped))
             .expect("unable to send event");
@@ -484,8 +1061,12 @@ impl PlaybackThread {
     }
 
     fn set_volume(&mut self, volume: f64) {
+        self.volume = volume;
+
         if let Some(stream) = self.stream.as_mut() {
-            stream.set_volume(volume).expect("failed to set volume");
+            if !self.dop_active {
+                stream.set_volume(volume).expect("failed to set volume");
+            }
 
             self.events_tx
                 .send(PlaybackEvent::VolumeChanged(volume))
@@ -494,86 +1075,105 @@ impl PlaybackThread {
     }
 
     fn play_audio(&mut self) {
+        let Some(provider) = &mut self.media_provider else {
+            return;
+        };
+
+        // TODO: proper error handling
+        let mut samples = match provider.read_samples() {
+            Ok(samples) => samples,
+            Err(e) => match e {
+                PlaybackReadError::NothingOpen => {
+                    panic!("thread state is invalid: no file open")
+                }
+                PlaybackReadError::NeverStarted => {
+                    panic!("thread state is invalid: playback never started")
+                }
+                PlaybackReadError::EOF => {
+                    info!("EOF, moving to next song");
+                    self.forget_resume_position();
+                    self.finish_play(true);
+                    self.next(false);
+                    return;
+                }
+                PlaybackReadError::Unknown => return,
+                PlaybackReadError::DecodeFatal => panic!("fatal decoding error"),
+            },
+        };
+
+        let range_ended = self.clip_to_range(&mut samples);
+
+        if let Some(play) = &mut self.current_play {
+            play.listened += samples.samples.len() as f64 / samples.rate as f64;
+        }
+
         if let Some(stream) = &mut self.stream {
-            if let Some(provider) = &mut self.media_provider {
-                if self.resampler.is_none() {
-                    // TODO: proper error handling
-                    let first_samples = match provider.read_samples() {
-                        Ok(samples) => samples,
-                        Err(e) => match e {
-                            PlaybackReadError::NothingOpen => {
-                                panic!("thread state is invalid: no file open")
-                            }
-                            PlaybackReadError::NeverStarted => {
-                                panic!("thread state is invalid: playback never started")
-                            }
-                            PlaybackReadError::EOF => {
-                                info!("EOF, moving to next song");
-                                self.next(false);
-                                return;
-                            }
-                            PlaybackReadError::Unknown => return,
-                            PlaybackReadError::DecodeFatal => panic!("fatal decoding error"),
-                        },
-                    };
-                    let duration = provider.frame_duration().expect("can't get duration");
-                    let device_format = stream.get_current_format().unwrap();
-
-                    self.resampler = Some(Resampler::new(
-                        first_samples.rate,
-                        device_format.sample_rate,
-                        duration,
-                        // TODO: support getting channels from the bitmask
-                        match device_format.channels {
-                            ChannelSpec::Count(v) => v,
-                            _ => 2,
-                        },
-                    ));
-                    self.format = Some(device_format.clone());
-
-                    let converted = self
-                        .resampler
-                        .as_mut()
-                        .unwrap()
-                        .convert_formats(first_samples, self.format.as_ref().unwrap());
+            if self.resampler.is_none() {
+                let duration = self
+                    .media_provider
+                    .as_ref()
+                    .unwrap()
+                    .frame_duration()
+                    .expect("can't get duration");
+                let device_format = stream.get_current_format().unwrap().clone();
+                // TODO: support getting channels from the bitmask
+                let channels = match device_format.channels {
+                    ChannelSpec::Count(v) => v,
+                    _ => 2,
+                };
 
+                let is_dsd = samples.samples.is_format(SampleFormat::DSD);
+                let dop = is_dsd
+                    && self.settings.dsd_over_pcm
+                    && dop_supported(samples.rate, &device_format);
+
+                // DoP has to reach the DAC untouched, so the volume can't be applied to it
+                if dop != self.dop_active {
                     stream
-                        .submit_frame(converted)
-                        .expect("failed to submit frames to stream");
+                        .set_volume(if dop { 1.0 } else { self.volume })
+                        .expect("failed to set volume");
+                    self.dop_active = dop;
+                }
 
-                    self.update_ts();
+                self.resampler = Some(if is_dsd {
+                    Resampler::new_dsd(
+                        samples.rate,
+                        device_format.sample_rate,
+                        duration,
+                        channels,
+                        dop,
+                    )
                 } else {
-                    let samples = match provider.read_samples() {
-                        Ok(samples) => samples,
-                        Err(e) => match e {
-                            PlaybackReadError::NothingOpen => {
-                                panic!("thread state is invalid: no file open")
-                            }
-                            PlaybackReadError::NeverStarted => {
-                                panic!("thread state is invalid: playback never started")
-                            }
-                            PlaybackReadError::EOF => {
-                                info!("EOF, moving to next song");
-                                self.next(false);
-                                return;
-                            }
-                            PlaybackReadError::Unknown => return,
-                            PlaybackReadError::DecodeFatal => panic!("fatal decoding error"),
-                        },
-                    };
-                    let converted = self
-                        .resampler
-                        .as_mut()
-                        .unwrap()
-                        .convert_formats(samples, self.format.as_ref().unwrap());
+                    Resampler::new(samples.rate, device_format.sample_rate, duration, channels)
+                });
+                self.format = Some(device_format);
+            }
 
-                    stream
-                        .submit_frame(converted)
-                        .expect("failed to submit frames to stream");
+            if !samples.samples.is_empty() {
+                let converted = match self
+                    .resampler
+                    .as_mut()
+                    .unwrap()
+                    .convert_formats(samples, self.format.as_ref().unwrap())
+                {
+                    Ok(converted) => converted,
+                    Err(e) => {
+                        error!("Unable to convert samples for the output device: {:?}", e);
+                        self.stop();
+                        return;
+                    }
+                };
 
-                    self.update_ts();
-                }
+                stream
+                    .submit_frame(converted)
+                    .expect("failed to submit frames to stream");
             }
         }
+
+        self.update_ts();
+
+        if range_ended {
+            self.finish_range();
+        }
     }
 }
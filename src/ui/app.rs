@@ -300,7 +300,8 @@ pub async fn run() {
                 panic!("fatal: unable to create database pool");
             }
 
-            let mut playback_interface: GPUIPlaybackInterface = PlaybackThread::start();
+            let pool = cx.global::<Pool>().0.clone();
+            let mut playback_interface: GPUIPlaybackInterface = PlaybackThread::start(pool);
             let mut data_interface: GPUIDataInterface = DataThread::start();
 
             playback_interface.start_broadcast(cx);
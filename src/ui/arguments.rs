@@ -28,6 +28,7 @@ pub fn parse_args_and_prepare(interface: &GPUIPlaybackInterface) {
                         .expect("Invalid path")
                 })
                 .collect(),
+            None,
         );
     }
 }
@@ -141,7 +141,9 @@ impl Render for ScanStatus {
                 ScanEvent::DiscoverProgress(progress) => {
                     format!("Discovering files ({})", progress)
                 }
-                ScanEvent::Cleaning => "".to_string(),
+                ScanEvent::Cleaning
+                | ScanEvent::FileFailed { .. }
+                | ScanEvent::LibraryChanged { .. } => "".to_string(),
                 ScanEvent::ScanCompleteWatching => "Watching for updates".to_string(),
             })
     }
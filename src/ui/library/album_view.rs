@@ -53,7 +53,7 @@ impl AlbumView {
             cx.observe(&state, move |this: &mut AlbumView, e, cx| {
                 let value = e.read(cx);
                 match value {
-                    ScanEvent::ScanCompleteIdle => {
+                    ScanEvent::ScanCompleteIdle | ScanEvent::ScanCompleteWatching => {
                         this.regenerate_list_state(cx);
                     }
                     ScanEvent::ScanProgress { current, .. } => {
@@ -66,6 +66,14 @@ impl AlbumView {
             })
             .detach();
 
+            // changes picked up while watching the library don't go through a full scan
+            cx.subscribe(&state, |this: &mut AlbumView, _, event, cx| {
+                if let ScanEvent::LibraryChanged { .. } = event {
+                    this.regenerate_list_state(cx);
+                }
+            })
+            .detach();
+
             let queue = cx.global::<DropOnNavigateQueue>().clone();
 
             queue.drop_all(cx);
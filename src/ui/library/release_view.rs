@@ -14,7 +14,10 @@ use crate::{
         db::{AlbumMethod, LibraryAccess},
         types::{Album, Artist, Track},
     },
-    playback::interface::{replace_queue, GPUIPlaybackInterface},
+    playback::{
+        events::PlaySource,
+        interface::{replace_queue_from, GPUIPlaybackInterface},
+    },
     ui::{
         app::DropOnNavigateQueue,
         components::{
@@ -35,6 +38,7 @@ pub struct ReleaseView {
     tracks: Arc<Vec<Track>>,
     track_list_state: ListState,
     release_info: Option<SharedString>,
+    format_info: Option<SharedString>,
 }
 
 impl ReleaseView {
@@ -117,6 +121,23 @@ impl ReleaseView {
                 }
             };
 
+            // most releases are in a single format, but compilations can be mixed
+            let format_info = {
+                let mut labels: Vec<String> = Vec::new();
+
+                for label in tracks.iter().filter_map(|track| track.format_label()) {
+                    if !labels.contains(&label) {
+                        labels.push(label);
+                    }
+                }
+
+                if !labels.is_empty() {
+                    Some(SharedString::from(labels.join(", ")))
+                } else {
+                    None
+                }
+            };
+
             ReleaseView {
                 album,
                 image,
@@ -124,6 +145,7 @@ impl ReleaseView {
                 tracks,
                 track_list_state: state,
                 release_info,
+                format_info,
             }
         })
     }
@@ -213,13 +235,10 @@ impl Render for ReleaseView {
                                             .intent(ButtonIntent::Primary)
                                             .on_click(cx.listener(
                                                 |this: &mut ReleaseView, _, cx| {
-                                                    let paths = this
-                                                        .tracks
-                                                        .iter()
-                                                        .map(|track| track.location.clone())
-                                                        .collect();
+                                                    let paths = playable_paths(&this.tracks);
 
-                                                    replace_queue(paths, cx)
+                                                    let source = PlaySource::Album(this.album.id);
+                                                    replace_queue_from(paths, Some(source), cx)
                                                 },
                                             ))
                                             .child(div().font_family(FONT_AWESOME).child(""))
@@ -233,14 +252,11 @@ impl Render for ReleaseView {
                                             .flex_none()
                                             .on_click(cx.listener(
                                                 |this: &mut ReleaseView, _, cx| {
-                                                    let paths = this
-                                                        .tracks
-                                                        .iter()
-                                                        .map(|track| track.location.clone())
-                                                        .collect();
+                                                    let paths = playable_paths(&this.tracks);
 
+                                                    let source = PlaySource::Album(this.album.id);
                                                     cx.global::<GPUIPlaybackInterface>()
-                                                        .queue_list(paths);
+                                                        .queue_list(paths, Some(source));
                                                 },
                                             ))
                                             .child(div().font_family(FONT_AWESOME).child("")),
@@ -253,11 +269,7 @@ impl Render for ReleaseView {
                                             .flex_none()
                                             .on_click(cx.listener(
                                                 |this: &mut ReleaseView, _, cx| {
-                                                    let paths = this
-                                                        .tracks
-                                                        .iter()
-                                                        .map(|track| track.location.clone())
-                                                        .collect();
+                                                    let paths = playable_paths(&this.tracks);
 
                                                     if !(*cx
                                                         .global::<PlaybackInfo>()
@@ -268,7 +280,8 @@ impl Render for ReleaseView {
                                                             .toggle_shuffle();
                                                     }
 
-                                                    replace_queue(paths, cx)
+                                                    let source = PlaySource::Album(this.album.id);
+                                                    replace_queue_from(paths, Some(source), cx)
                                                 },
                                             ))
                                             .child(div().font_family(FONT_AWESOME).child("")),
@@ -302,6 +315,9 @@ impl Render for ReleaseView {
                     })
                     .when_some(self.album.isrc.as_ref(), |this, isrc| {
                         this.child(div().child(isrc.clone()))
+                    })
+                    .when_some(self.format_info.clone(), |this, format_info| {
+                        this.child(div().child(format_info))
                     }),
             )
     }
@@ -323,6 +339,8 @@ impl RenderOnce for TrackItem {
         let track_location = self.track.location.clone();
         let track_location_2 = self.track.location;
         let track_id = self.track.id;
+        let offline = self.track.offline;
+        let source = self.track.album_id.map(PlaySource::Album);
         context(("context", self.track.id as usize))
             .with(
                 div()
@@ -330,7 +348,9 @@ impl RenderOnce for TrackItem {
                     .flex_col()
                     .w_full()
                     .id(self.track.id as usize)
-                    .on_click(move |_, cx| play_from_track(cx, &tracks, track_id))
+                    .when(!offline, |this| {
+                        this.on_click(move |_, cx| play_from_track(cx, &tracks, track_id))
+                    })
                     .when(self.is_start, |this| {
                         this.child(
                             div()
@@ -357,7 +377,9 @@ impl RenderOnce for TrackItem {
                             .id(("track", self.track.id as u64))
                             .w_full()
                             .border_color(theme.border_color)
-                            .cursor_pointer()
+                            // tracks on drives that aren't mounted are shown, but can't be played
+                            .when(offline, |this| this.text_color(theme.text_secondary))
+                            .when(!offline, |this| this.cursor_pointer())
                             .px(px(24.0))
                             .py(px(6.0))
                             .hover(|this| this.bg(theme.nav_button_hover))
@@ -401,9 +423,13 @@ impl RenderOnce for TrackItem {
                             Some(""),
                             "Play",
                             move |_, cx| {
+                                if offline {
+                                    return;
+                                }
+
                                 let playback_interface = cx.global::<GPUIPlaybackInterface>();
                                 let queue_length = cx.global::<Models>().queue.read(cx).0.len();
-                                playback_interface.queue(&track_location);
+                                playback_interface.queue(&track_location, source);
                                 playback_interface.jump(queue_length);
                             },
                         ))
@@ -418,8 +444,12 @@ impl RenderOnce for TrackItem {
                             Some("+"),
                             "Add to queue",
                             move |_, cx| {
+                                if offline {
+                                    return;
+                                }
+
                                 let playback_interface = cx.global::<GPUIPlaybackInterface>();
-                                playback_interface.queue(&track_location_2);
+                                playback_interface.queue(&track_location_2, source);
                             },
                         )),
                 ),
@@ -427,11 +457,30 @@ impl RenderOnce for TrackItem {
     }
 }
 
+/// Returns the locations of the tracks that can be played, leaving out tracks that are offline.
+fn playable_paths(tracks: &[Track]) -> Vec<String> {
+    tracks
+        .iter()
+        .filter(|track| !track.offline)
+        .map(|track| track.location.clone())
+        .collect()
+}
+
 fn play_from_track(cx: &mut WindowContext, tracks: &Arc<Vec<Track>>, id: i64) {
-    let paths = tracks.iter().map(|track| track.location.clone()).collect();
+    let Some(index) = tracks
+        .iter()
+        .filter(|t| !t.offline)
+        .position(|t| t.id == id)
+    else {
+        return;
+    };
 
-    replace_queue(paths, cx);
+    let source = tracks
+        .iter()
+        .find_map(|t| t.album_id)
+        .map(PlaySource::Album);
+    replace_queue_from(playable_paths(tracks), source, cx);
 
     let playback_interface = cx.global::<GPUIPlaybackInterface>();
-    playback_interface.jump(tracks.iter().position(|t| t.id == id).unwrap())
+    playback_interface.jump(index)
 }
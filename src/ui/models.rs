@@ -10,7 +10,7 @@ use crate::{
         types::UIQueueItem,
     },
     library::scan::ScanEvent,
-    media::metadata::Metadata,
+    media::metadata::{Chapter, Metadata},
     playback::thread::PlaybackState,
 };
 
@@ -40,6 +40,8 @@ pub struct PlaybackInfo {
     pub current_track: Model<Option<String>>,
     pub shuffling: Model<bool>,
     pub volume: Model<f64>,
+    pub chapters: Model<Vec<Chapter>>,
+    pub current_chapter: Model<Option<usize>>,
 }
 
 impl Global for PlaybackInfo {}
@@ -87,6 +89,8 @@ pub fn build_models(cx: &mut AppContext) {
     let current_track: Model<Option<String>> = cx.new_model(|_| None);
     let shuffling: Model<bool> = cx.new_model(|_| false);
     let volume: Model<f64> = cx.new_model(|_| 1.0);
+    let chapters: Model<Vec<Chapter>> = cx.new_model(|_| Vec::new());
+    let current_chapter: Model<Option<usize>> = cx.new_model(|_| None);
 
     cx.set_global(PlaybackInfo {
         position,
@@ -95,5 +99,7 @@ pub fn build_models(cx: &mut AppContext) {
         current_track,
         shuffling,
         volume,
+        chapters,
+        current_chapter,
     });
 }
@@ -0,0 +1,354 @@
+#!/usr/bin/env python3
+"""Generates the audio fixtures used by the media provider tests.
+
+    python3 tests/fixtures/generate.py <path to opus_demo>
+
+opus_demo is built along with libopus (`make -f Makefile.unix` in the libopus source tree). All
+fixtures are one second of a 440Hz sine wave:
+
+- sine.opus: stereo, 48kHz, encoded by libopus and wrapped in an Ogg container by this script.
+- sine.m4a: mono, 8kHz, 16-bit ALAC in an MP4 container. The ALAC frames are stored uncompressed,
+  so the decoded samples match the generated ones exactly.
+- sine-aac.m4a: mono, 8kHz, AAC LC in an MP4 container, encoded by this script. Every frame uses
+  long sine windows, a single scalefactor and the escape codebook, which is enough to reproduce the
+  sine wave within a fraction of a percent.
+"""
+
+import math
+import os
+import struct
+import subprocess
+import sys
+import tempfile
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+TITLE = "Sine"
+ARTIST = "Muzak"
+
+
+def sine(rate, count, amplitude=0.5):
+    return [int(amplitude * 32767 * math.sin(2 * math.pi * 440 * i / rate)) for i in range(count)]
+
+
+# --- Ogg Opus ---
+
+def ogg_crc(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 24
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x04C11DB7) if crc & 0x80000000 else crc << 1
+            crc &= 0xFFFFFFFF
+    return crc
+
+
+def ogg_page(packets, granule, sequence, flags=0):
+    segments = []
+    for packet in packets:
+        segments += [255] * (len(packet) // 255) + [len(packet) % 255]
+    header = struct.pack("<4sBBqIIIB", b"OggS", 0, flags, granule, 1, sequence, 0, len(segments))
+    page = header + bytes(segments) + b"".join(packets)
+    return page[:22] + struct.pack("<I", ogg_crc(page)) + page[26:]
+
+
+def opus_fixture(opus_demo):
+    rate, channels, pre_skip = 48000, 2, 312
+    samples = sine(rate, rate)
+
+    with tempfile.TemporaryDirectory() as tmp:
+        raw = os.path.join(tmp, "in.raw")
+        bit = os.path.join(tmp, "out.bit")
+
+        with open(raw, "wb") as f:
+            f.write(b"".join(struct.pack("<hh", v, v) for v in samples))
+
+        subprocess.run([opus_demo, "-e", "audio", str(rate), str(channels), "32000", raw, bit],
+                       check=True, capture_output=True)
+
+        with open(bit, "rb") as f:
+            data = f.read()
+
+    # opus_demo writes each packet as a big-endian length and final range, followed by the packet
+    packets = []
+    while data:
+        length, _ = struct.unpack(">II", data[:8])
+        packets.append(data[8:8 + length])
+        data = data[8 + length:]
+
+    head = struct.pack("<8sBBHIhB", b"OpusHead", 1, channels, pre_skip, rate, 0, 0)
+    vendor = b"muzak fixtures"
+    comments = [f"TITLE={TITLE}".encode(), f"ARTIST={ARTIST}".encode()]
+    tags = b"OpusTags" + struct.pack("<I", len(vendor)) + vendor + struct.pack("<I", len(comments))
+    tags += b"".join(struct.pack("<I", len(c)) + c for c in comments)
+
+    pages = [ogg_page([head], 0, 0, flags=2), ogg_page([tags], 0, 1)]
+    total = rate + pre_skip
+
+    for i in range(0, len(packets), 10):
+        chunk = packets[i:i + 10]
+        granule = min((i + len(chunk)) * 960, total)
+        last = i + 10 >= len(packets)
+        pages.append(ogg_page(chunk, granule, len(pages), flags=4 if last else 0))
+
+    with open(os.path.join(HERE, "sine.opus"), "wb") as f:
+        f.write(b"".join(pages))
+
+
+# --- MP4 ALAC ---
+
+class Bits:
+    def __init__(self):
+        self.bits = []
+
+    def write(self, value, count):
+        self.bits += [(value >> (count - 1 - i)) & 1 for i in range(count)]
+
+    def bytes(self):
+        bits = self.bits + [0] * (-len(self.bits) % 8)
+        return bytes(int("".join(map(str, bits[i:i + 8])), 2) for i in range(0, len(bits), 8))
+
+
+def alac_frame(samples, frame_length):
+    bits = Bits()
+    bits.write(0, 3)  # single channel element
+    bits.write(0, 4)  # element instance
+    bits.write(0, 12)
+    partial = len(samples) != frame_length
+    bits.write(int(partial), 1)
+    bits.write(0, 2)  # no shifted bytes
+    bits.write(1, 1)  # uncompressed
+    if partial:
+        bits.write(len(samples), 32)
+    for sample in samples:
+        bits.write(sample & 0xFFFF, 16)
+    bits.write(7, 3)  # end
+    return bits.bytes()
+
+
+def box(name, *children):
+    body = b"".join(children)
+    return struct.pack(">I4s", len(body) + 8, name) + body
+
+
+def full_box(name, version, flags, *children):
+    return box(name, struct.pack(">I", (version << 24) | flags), *children)
+
+
+def ilst_item(name, text):
+    return box(name, box(b"data", struct.pack(">II", 1, 0), text.encode()))
+
+
+def m4a(entry, frames, frame_length, duration, rate):
+    """Wraps the frames of a mono track in an M4A file with a title and an artist."""
+    last = duration - (len(frames) - 1) * frame_length
+
+    def moov(chunk_offset):
+        stbl = box(
+            b"stbl",
+            full_box(b"stsd", 0, 0, struct.pack(">I", 1), entry),
+            full_box(b"stts", 0, 0, struct.pack(">III", 2, len(frames) - 1, frame_length),
+                     struct.pack(">II", 1, last)),
+            full_box(b"stsc", 0, 0, struct.pack(">IIII", 1, 1, len(frames), 1)),
+            full_box(b"stsz", 0, 0, struct.pack(">II", 0, len(frames)),
+                     b"".join(struct.pack(">I", len(f)) for f in frames)),
+            full_box(b"stco", 0, 0, struct.pack(">II", 1, chunk_offset)),
+        )
+        minf = box(
+            b"minf",
+            full_box(b"smhd", 0, 0, bytes(4)),
+            box(b"dinf", full_box(b"dref", 0, 0, struct.pack(">I", 1), full_box(b"url ", 0, 1))),
+            stbl,
+        )
+        mdia = box(
+            b"mdia",
+            full_box(b"mdhd", 0, 0, struct.pack(">IIIIHH", 0, 0, rate, duration, 0x55C4, 0)),
+            full_box(b"hdlr", 0, 0, bytes(4), b"soun", bytes(12), b"\0"),
+            minf,
+        )
+        matrix = struct.pack(">9I", 0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000)
+        tkhd = full_box(b"tkhd", 0, 7, struct.pack(">IIIII", 0, 0, 1, 0, duration), bytes(8),
+                        struct.pack(">hhhH", 0, 0, 0x0100, 0), matrix, struct.pack(">II", 0, 0))
+        mvhd = full_box(b"mvhd", 0, 0, struct.pack(">IIII", 0, 0, rate, duration),
+                        struct.pack(">IH", 0x10000, 0x0100), bytes(10), matrix, bytes(24),
+                        struct.pack(">I", 2))
+        meta = full_box(
+            b"meta", 0, 0,
+            full_box(b"hdlr", 0, 0, bytes(4), b"mdir", b"appl", bytes(8), b"\0"),
+            box(b"ilst", ilst_item(b"\xa9nam", TITLE), ilst_item(b"\xa9ART", ARTIST)),
+        )
+        return box(b"moov", mvhd, box(b"trak", tkhd, mdia), box(b"udta", meta))
+
+    ftyp = box(b"ftyp", b"M4A ", struct.pack(">I", 0), b"M4A mp42isom")
+    # the size of moov doesn't depend on the offset it contains
+    offset = len(ftyp) + len(moov(0)) + 8
+    return ftyp + moov(offset) + box(b"mdat", *frames)
+
+
+def m4a_fixture():
+    rate, frame_length = 8000, 4096
+    samples = sine(rate, rate)
+    frames = [alac_frame(samples[i:i + frame_length], frame_length)
+              for i in range(0, len(samples), frame_length)]
+
+    config = struct.pack(">IBBBBBBHIII", frame_length, 0, 16, 40, 10, 14, 1, 255, 0, 0, rate)
+    entry = box(b"alac", bytes(6), struct.pack(">H", 1), bytes(8),
+                struct.pack(">HHHHI", 1, 16, 0, 0, rate << 16), full_box(b"alac", 0, 0, config))
+
+    with open(os.path.join(HERE, "sine.m4a"), "wb") as f:
+        f.write(m4a(entry, frames, frame_length, len(samples), rate))
+
+
+# --- MP4 AAC ---
+
+# Spectrum codebook 11 of ISO/IEC 14496-3, indexed by 17 * |x| + |y|. 16 stands for an escaped
+# value.
+AAC_ESC_LENGTHS = [
+     4,  5,  6,  7,  8,  8,  9, 10, 10, 10, 11, 11, 12, 11, 12, 12, 10,
+     5,  4,  5,  6,  7,  7,  8,  8,  9,  9,  9, 10, 10, 10, 10, 11,  8,
+     6,  5,  5,  6,  7,  7,  8,  8,  8,  9,  9,  9, 10, 10, 10, 10,  8,
+     7,  6,  6,  6,  7,  7,  8,  8,  8,  9,  9,  9, 10, 10, 10, 10,  8,
+     8,  7,  7,  7,  7,  8,  8,  8,  8,  9,  9,  9, 10, 10, 10, 10,  8,
+     8,  7,  7,  7,  7,  8,  8,  8,  9,  9,  9,  9, 10, 10, 10, 10,  8,
+     9,  8,  8,  8,  8,  8,  8,  8,  9,  9,  9, 10, 10, 10, 10, 10,  8,
+     9,  8,  8,  8,  8,  8,  8,  9,  9,  9, 10, 10, 10, 10, 10, 10,  8,
+    10,  9,  8,  8,  9,  9,  9,  9,  9, 10, 10, 10, 10, 10, 10, 11,  8,
+    10,  9,  9,  9,  9,  9,  9,  9, 10, 10, 10, 10, 10, 10, 11, 11,  8,
+    11,  9,  9,  9,  9,  9,  9, 10, 10, 10, 10, 10, 11, 10, 11, 11,  8,
+    11, 10,  9,  9, 10,  9, 10, 10, 10, 10, 10, 11, 11, 11, 11, 11,  8,
+    11, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 11, 11, 11, 11, 11,  9,
+    11, 10,  9,  9, 10, 10, 10, 10, 10, 10, 11, 11, 11, 11, 11, 11,  9,
+    11, 10, 10, 10, 10, 10, 10, 10, 10, 10, 11, 11, 11, 11, 11, 11,  9,
+    12, 10, 10, 10, 10, 10, 10, 10, 11, 11, 11, 11, 11, 11, 12, 12,  9,
+     9,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  8,  9,  5,
+]
+AAC_ESC_CODES = [
+    0x000, 0x006, 0x019, 0x03d, 0x09c, 0x0c6, 0x1a7, 0x390, 0x3c2,
+    0x3df, 0x7e6, 0x7f3, 0xffb, 0x7ec, 0xffa, 0xffe, 0x38e,
+    0x005, 0x001, 0x008, 0x014, 0x037, 0x042, 0x092, 0x0af, 0x191,
+    0x1a5, 0x1b5, 0x39e, 0x3c0, 0x3a2, 0x3cd, 0x7d6, 0x0ae,
+    0x017, 0x007, 0x009, 0x018, 0x039, 0x040, 0x08e, 0x0a3, 0x0b8,
+    0x199, 0x1ac, 0x1c1, 0x3b1, 0x396, 0x3be, 0x3ca, 0x09d,
+    0x03c, 0x015, 0x016, 0x01a, 0x03b, 0x044, 0x091, 0x0a5, 0x0be,
+    0x196, 0x1ae, 0x1b9, 0x3a1, 0x391, 0x3a5, 0x3d5, 0x094,
+    0x09a, 0x036, 0x038, 0x03a, 0x041, 0x08c, 0x09b, 0x0b0, 0x0c3,
+    0x19e, 0x1ab, 0x1bc, 0x39f, 0x38f, 0x3a9, 0x3cf, 0x093,
+    0x0bf, 0x03e, 0x03f, 0x043, 0x045, 0x09e, 0x0a7, 0x0b9, 0x194,
+    0x1a2, 0x1ba, 0x1c3, 0x3a6, 0x3a7, 0x3bb, 0x3d4, 0x09f,
+    0x1a0, 0x08f, 0x08d, 0x090, 0x098, 0x0a6, 0x0b6, 0x0c4, 0x19f,
+    0x1af, 0x1bf, 0x399, 0x3bf, 0x3b4, 0x3c9, 0x3e7, 0x0a8,
+    0x1b6, 0x0ab, 0x0a4, 0x0aa, 0x0b2, 0x0c2, 0x0c5, 0x198, 0x1a4,
+    0x1b8, 0x38c, 0x3a4, 0x3c4, 0x3c6, 0x3dd, 0x3e8, 0x0ad,
+    0x3af, 0x192, 0x0bd, 0x0bc, 0x18e, 0x197, 0x19a, 0x1a3, 0x1b1,
+    0x38d, 0x398, 0x3b7, 0x3d3, 0x3d1, 0x3db, 0x7dd, 0x0b4,
+    0x3de, 0x1a9, 0x19b, 0x19c, 0x1a1, 0x1aa, 0x1ad, 0x1b3, 0x38b,
+    0x3b2, 0x3b8, 0x3ce, 0x3e1, 0x3e0, 0x7d2, 0x7e5, 0x0b7,
+    0x7e3, 0x1bb, 0x1a8, 0x1a6, 0x1b0, 0x1b2, 0x1b7, 0x39b, 0x39a,
+    0x3ba, 0x3b5, 0x3d6, 0x7d7, 0x3e4, 0x7d8, 0x7ea, 0x0ba,
+    0x7e8, 0x3a0, 0x1bd, 0x1b4, 0x38a, 0x1c4, 0x392, 0x3aa, 0x3b0,
+    0x3bc, 0x3d7, 0x7d4, 0x7dc, 0x7db, 0x7d5, 0x7f0, 0x0c1,
+    0x7fb, 0x3c8, 0x3a3, 0x395, 0x39d, 0x3ac, 0x3ae, 0x3c5, 0x3d8,
+    0x3e2, 0x3e6, 0x7e4, 0x7e7, 0x7e0, 0x7e9, 0x7f7, 0x190,
+    0x7f2, 0x393, 0x1be, 0x1c0, 0x394, 0x397, 0x3ad, 0x3c3, 0x3c1,
+    0x3d2, 0x7da, 0x7d9, 0x7df, 0x7eb, 0x7f4, 0x7fa, 0x195,
+    0x7f8, 0x3bd, 0x39c, 0x3ab, 0x3a8, 0x3b3, 0x3b9, 0x3d0, 0x3e3,
+    0x3e5, 0x7e2, 0x7de, 0x7ed, 0x7f1, 0x7f9, 0x7fc, 0x193,
+    0xffd, 0x3dc, 0x3b6, 0x3c7, 0x3cc, 0x3cb, 0x3d9, 0x3da, 0x7d3,
+    0x7e1, 0x7ee, 0x7ef, 0x7f5, 0x7f6, 0xffc, 0xfff, 0x19d,
+    0x1c2, 0x0b5, 0x0a1, 0x096, 0x097, 0x095, 0x099, 0x0a0, 0x0a2,
+    0x0ac, 0x0a9, 0x0b1, 0x0b3, 0x0bb, 0x0c0, 0x18f, 0x004,
+]
+
+# Scalefactor band offsets of a long window at 8kHz.
+AAC_BANDS = [
+    0, 12, 24, 36, 48, 60, 72, 84, 96, 108, 120, 132, 144, 156, 172, 188, 204, 220, 236, 252, 268,
+    288, 308, 328, 348, 372, 396, 420, 448, 476, 508, 544, 580, 620, 664, 712, 764, 820, 880, 944,
+    1024,
+]
+AAC_GAIN = 140
+
+
+def mdct(block):
+    n = len(block) // 2
+    window = [math.sin(math.pi * (i + 0.5) / (2 * n)) for i in range(2 * n)]
+    cos = [math.cos(math.pi * i / (4 * n)) for i in range(8 * n)]
+    windowed = [(2 * i + n + 1, v * w) for i, (v, w) in enumerate(zip(block, window)) if v]
+    return [2 * sum(v * cos[(i * (2 * k + 1)) % (8 * n)] for i, v in windowed) for k in range(n)]
+
+
+def aac_value(bits, value):
+    """Writes the escape sequence of a quantized value of at least 16."""
+    length = value.bit_length() - 1
+    bits.write((1 << (length - 4)) - 1, length - 4)
+    bits.write(0, 1)
+    bits.write(value - (1 << length), length)
+
+
+def aac_frame(block):
+    step = 2 ** ((AAC_GAIN - 100) / 4)
+    values = [int(math.copysign(round((abs(v) / step) ** 0.75), v)) for v in mdct(block)]
+    bands = len(AAC_BANDS) - 1
+
+    bits = Bits()
+    bits.write(0, 3)  # single channel element
+    bits.write(0, 4)  # element instance
+    bits.write(AAC_GAIN, 8)
+    bits.write(0, 1)
+    bits.write(0, 2)  # only long sequence
+    bits.write(0, 1)  # sine window
+    bits.write(bands, 6)
+    bits.write(0, 1)  # no prediction
+
+    # a single section coded with the escape codebook
+    bits.write(11, 4)
+    for _ in range(bands // 31):
+        bits.write(31, 5)
+    bits.write(bands % 31, 5)
+    # every scalefactor is the global gain
+    bits.write(0, bands)
+    bits.write(0, 3)  # no pulse, TNS or gain control data
+
+    for x, y in zip(values[::2], values[1::2]):
+        index = 17 * min(abs(x), 16) + min(abs(y), 16)
+        bits.write(AAC_ESC_CODES[index], AAC_ESC_LENGTHS[index])
+        for v in (x, y):
+            if v:
+                bits.write(int(v < 0), 1)
+        for v in (x, y):
+            if abs(v) >= 16:
+                aac_value(bits, abs(v))
+
+    bits.write(7, 3)  # end
+    return bits.bytes()
+
+
+def aac_fixture():
+    rate, frame_length = 8000, 1024
+    # each frame overlaps the previous one, so the decoder starts a frame behind the samples
+    samples = [0] * frame_length + sine(rate, rate)
+    samples += [0] * (-len(samples) % frame_length + frame_length)
+    frames = [aac_frame(samples[i:i + 2 * frame_length])
+              for i in range(0, len(samples) - frame_length, frame_length)]
+
+    def descriptor(tag, *children):
+        body = b"".join(children)
+        return struct.pack(">BB", tag, len(body)) + body
+
+    # AAC LC, 8kHz, mono
+    config = descriptor(5, struct.pack(">BB", 0x15, 0x88))
+    decoder = descriptor(4, struct.pack(">BB", 0x40, 0x15), bytes(11), config)
+    esds = full_box(b"esds", 0, 0, descriptor(3, struct.pack(">HB", 1, 0), decoder,
+                                              descriptor(6, b"\x02")))
+    entry = box(b"mp4a", bytes(6), struct.pack(">H", 1), bytes(8),
+                struct.pack(">HHHHI", 1, 16, 0, 0, rate << 16), esds)
+
+    with open(os.path.join(HERE, "sine-aac.m4a"), "wb") as f:
+        f.write(m4a(entry, frames, frame_length, len(frames) * frame_length, rate))
+
+
+if __name__ == "__main__":
+    if len(sys.argv) != 2:
+        sys.exit(__doc__)
+
+    opus_fixture(sys.argv[1])
+    m4a_fixture()
+    aac_fixture()